@@ -0,0 +1,61 @@
+// Measures lexer throughput on large generated sources.
+//
+// Run with `cargo bench -p nenc`
+
+extern crate nenc;
+
+use std::hint::black_box;
+use std::time::{ Duration, Instant };
+
+use nenc::lexer::Lexer;
+use nenc::source::SourceFile;
+
+const ITERATIONS: u32 = 20;
+
+// Generates a program of `functions` functions, each containing
+// a mix of calls, plain strings, escapes and interpolation.
+fn generate_source(functions: usize) -> String {
+    let mut src = String::new();
+
+    for i in 0..functions {
+        src.push_str(&format!("impure func function_{}() {{\n", i));
+        src.push_str("    print(\"Hello, world!\");\n");
+        src.push_str("    println(\"An escaped\\tstring\\n\");\n");
+        src.push_str(&format!("    println(\"Interpolated {{{}:>8}} and {{\"nested\"}}\");\n", i));
+        src.push_str(&format!("    function_{}();\n", (i + 1) % functions));
+        src.push_str("}\n\n");
+    }
+
+    src
+}
+
+fn bench(name: &str, functions: usize) {
+    let source = SourceFile::new(name.to_string(), generate_source(functions));
+    let bytes = source.text.len();
+
+    let mut tokens = 0;
+    let mut elapsed = Duration::ZERO;
+
+    for _ in 0..ITERATIONS {
+        let start = Instant::now();
+        tokens = black_box(Lexer::new(&source).count());
+        elapsed += start.elapsed();
+    }
+
+    let seconds = elapsed.as_secs_f64() / ITERATIONS as f64;
+    println!(
+        "{:<8} {:>10} bytes {:>9} tokens {:>10.3} ms {:>9.2} MB/s {:>8.2} Mtokens/s",
+        name,
+        bytes,
+        tokens,
+        seconds * 1000.0,
+        bytes as f64 / seconds / 1_000_000.0,
+        tokens as f64 / seconds / 1_000_000.0
+    );
+}
+
+fn main() {
+    bench("small", 100);
+    bench("medium", 10_000);
+    bench("large", 100_000);
+}
@@ -1,19 +1,229 @@
-use codegen::instruction_opcode;
 use ir::Instruction;
+use types::{ Signature, Type };
 
-pub fn get_bytecode_of_function(name: String) -> Option<Vec<u8>> {
-    match name.as_str() {
-        "print" => Some(
-            instruction_opcode(Instruction::Write).to_vec()
-        ),
-        "println" => Some(
-            [
-                instruction_opcode(Instruction::Write),
-                instruction_opcode(Instruction::PushString(String::new())),
-                &[0x00, 0x01, 0x0A], // New Line length, and New Line
-                instruction_opcode(Instruction::Write)
-            ].concat()
-        ),
-        _ => None
+// The standard library, compiled alongside every program. Its functions
+// can be called from any module without being imported.
+pub const PRELUDE: &str = include_str!("../std/prelude.nen");
+
+pub const PRELUDE_NAME: &str = "std";
+
+pub enum Implementation {
+    // Inlined at the call site
+    Instructions(&'static [Instruction]),
+    // Implemented by the VM, and called by name with a `CallNative` instruction
+    Native
+}
+
+pub struct Builtin {
+    pub name: &'static str,
+    pub parameters: &'static [Type],
+    pub returns: Type,
+    pub impure: bool,
+    // Primitives that can only be called from the prelude,
+    // which wraps them in ordinary functions
+    pub internal: bool,
+    pub implementation: Implementation
+}
+
+// Every function provided by the compiler or the VM, rather than written in nen.
+// Native functions must have a matching implementation in the interpreter.
+pub static BUILTINS: &[Builtin] = &[
+    Builtin {
+        name: "__write",
+        parameters: &[Type::Any],
+        returns: Type::Void,
+        impure: true,
+        internal: true,
+        implementation: Implementation::Instructions(&[Instruction::Write])
+    },
+    Builtin {
+        name: "__write_error",
+        parameters: &[Type::Any],
+        returns: Type::Void,
+        impure: true,
+        internal: true,
+        implementation: Implementation::Instructions(&[Instruction::WriteError])
+    },
+    Builtin {
+        name: "flush",
+        parameters: &[],
+        returns: Type::Void,
+        impure: true,
+        internal: false,
+        implementation: Implementation::Instructions(&[Instruction::Flush])
+    },
+    Builtin {
+        name: "exit",
+        parameters: &[Type::Int],
+        returns: Type::Void,
+        impure: true,
+        internal: false,
+        implementation: Implementation::Instructions(&[Instruction::Exit])
+    },
+    Builtin {
+        name: "read_line",
+        parameters: &[],
+        returns: Type::String,
+        impure: true,
+        internal: false,
+        implementation: Implementation::Instructions(&[Instruction::ReadLine])
+    },
+    Builtin {
+        name: "read_all",
+        parameters: &[],
+        returns: Type::String,
+        impure: true,
+        internal: false,
+        implementation: Implementation::Instructions(&[Instruction::ReadAll])
+    },
+    Builtin {
+        name: "read_full_line",
+        parameters: &[],
+        returns: Type::String,
+        impure: true,
+        internal: false,
+        implementation: Implementation::Instructions(&[Instruction::ReadFullLine])
+    },
+    Builtin {
+        name: "at_eof",
+        parameters: &[],
+        returns: Type::Int,
+        impure: true,
+        internal: false,
+        implementation: Implementation::Instructions(&[Instruction::AtEof])
+    },
+    Builtin {
+        name: "read_int",
+        parameters: &[],
+        returns: Type::Int,
+        impure: true,
+        internal: false,
+        implementation: Implementation::Instructions(&[Instruction::ReadInt])
+    },
+    Builtin {
+        name: "length",
+        parameters: &[Type::String],
+        returns: Type::Int,
+        impure: false,
+        internal: false,
+        implementation: Implementation::Native
+    },
+    Builtin {
+        name: "read_file",
+        parameters: &[Type::String],
+        returns: Type::String,
+        impure: true,
+        internal: false,
+        implementation: Implementation::Native
+    },
+    Builtin {
+        name: "write_file",
+        parameters: &[Type::String, Type::String],
+        returns: Type::Void,
+        impure: true,
+        internal: false,
+        implementation: Implementation::Native
+    },
+    Builtin {
+        name: "append_file",
+        parameters: &[Type::String, Type::String],
+        returns: Type::Void,
+        impure: true,
+        internal: false,
+        implementation: Implementation::Native
+    },
+    Builtin {
+        name: "list_dir",
+        parameters: &[Type::String],
+        returns: Type::String,
+        impure: true,
+        internal: false,
+        implementation: Implementation::Native
+    },
+    Builtin {
+        name: "delete_file",
+        parameters: &[Type::String],
+        returns: Type::Void,
+        impure: true,
+        internal: false,
+        implementation: Implementation::Native
+    },
+    Builtin {
+        name: "create_dir",
+        parameters: &[Type::String],
+        returns: Type::Void,
+        impure: true,
+        internal: false,
+        implementation: Implementation::Native
+    },
+    Builtin {
+        name: "delete_dir",
+        parameters: &[Type::String],
+        returns: Type::Void,
+        impure: true,
+        internal: false,
+        implementation: Implementation::Native
+    },
+    Builtin {
+        name: "args",
+        parameters: &[],
+        returns: Type::String,
+        impure: true,
+        internal: false,
+        implementation: Implementation::Native
+    },
+    Builtin {
+        name: "arg",
+        parameters: &[Type::Int],
+        returns: Type::String,
+        impure: true,
+        internal: false,
+        implementation: Implementation::Native
+    },
+    Builtin {
+        name: "arg_count",
+        parameters: &[],
+        returns: Type::Int,
+        impure: true,
+        internal: false,
+        implementation: Implementation::Native
+    },
+    Builtin {
+        name: "env",
+        parameters: &[Type::String],
+        returns: Type::String,
+        impure: true,
+        internal: false,
+        implementation: Implementation::Native
+    },
+    Builtin {
+        name: "env_vars",
+        parameters: &[],
+        returns: Type::String,
+        impure: true,
+        internal: false,
+        implementation: Implementation::Native
+    }
+];
+
+pub fn builtin(name: &str) -> Option<&'static Builtin> {
+    BUILTINS.iter().find(|b| b.name == name)
+}
+
+// The name and number of parameters of each built-in implemented by the VM
+pub fn native_builtins() -> Vec<(&'static str, usize)> {
+    BUILTINS.iter()
+        .filter(|b| matches!(b.implementation, Implementation::Native))
+        .map(|b| (b.name, b.parameters.len()))
+        .collect()
+}
+
+impl Builtin {
+    pub fn signature(&self) -> Signature {
+        Signature {
+            parameters: self.parameters.to_vec(),
+            returns: self.returns,
+            impure: self.impure
+        }
     }
 }
@@ -1,83 +1,194 @@
-use ir::{ IR, Instruction, ScopeElement };
-use builtin::get_bytecode_of_function;
+use std::convert::TryFrom;
+
+use error::{ CompileError, SemanticError };
+use ir::{ IR, FunctionDebug, Instruction, ScopeElement };
+use lexer::Alignment;
+use source::SourceFile;
+
+// Where each instruction of a function came from, as its byte offset along with a line and column.
+// Columns are counted as displayed, the same as in compile errors, rather than in bytes.
+type LineTable = Vec<(u32, u32, u32)>;
 
 pub fn instruction_opcode(instruction: Instruction) -> &'static [u8] {
     match instruction {
         Instruction::Write => &[0x12],
+        Instruction::ReadLine => &[0x13],
+        Instruction::ReadAll => &[0x14],
+        Instruction::ReadInt => &[0x15],
+        Instruction::WriteError => &[0x16],
+        Instruction::Flush => &[0x17],
+        Instruction::AtEof => &[0x18],
+        Instruction::ReadFullLine => &[0x19],
         Instruction::Call(_) => &[0xA1],
+        Instruction::CallNative { .. } => &[0xA2],
         Instruction::PushString(_) => &[0xE1],
+        Instruction::PushInt(_) => &[0xE2],
+        Instruction::ToString(_) => &[0x30],
+        Instruction::Concat(_) => &[0x31],
+        Instruction::LoadLocal(_) => &[0x20],
+        Instruction::StoreLocal(_) => &[0x21],
+        Instruction::Pop => &[0x01],
+        Instruction::Return => &[0x02],
+        Instruction::Exit => &[0x03],
     }
 }
 
-pub fn instruction_operand(instruction: Instruction) -> Vec<u8> {
-    match instruction {
+// Operands that don't fit in their encoding, such as a string
+// longer than its u16 length allows, can't be compiled
+pub fn instruction_operand(instruction: Instruction) -> Option<Vec<u8>> {
+    let operand = match instruction {
         Instruction::PushString(string) |
-        Instruction::Call(string) => {
+        Instruction::Call(string) => string_bytes(&string)?,
+        // The flags are whether the function is impure, whether it returns a value,
+        // and whether it is a built-in that the VM implements
+        Instruction::CallNative { name, arguments, impure, returns_value, builtin } => {
             [
-                (string.len() as u16).to_be_bytes().to_vec(),
-                string.as_bytes().to_vec()
+                string_bytes(&name)?,
+                vec![arguments, (impure as u8) | ((returns_value as u8) << 1) | ((builtin as u8) << 2)]
             ].concat()
         },
+        Instruction::PushInt(i) => i.to_be_bytes().to_vec(),
+        Instruction::ToString(format) => {
+            let align: u8 = match format.align {
+                Alignment::Default => 0,
+                Alignment::Left => 1,
+                Alignment::Right => 2,
+                Alignment::Center => 3
+            };
+            // A precision of u16::MAX means no precision was specified
+            [
+                vec![align],
+                format.width.unwrap_or(0).to_be_bytes().to_vec(),
+                format.precision.unwrap_or(u16::MAX).to_be_bytes().to_vec()
+            ].concat()
+        },
+        Instruction::Concat(count) |
+        Instruction::LoadLocal(count) |
+        Instruction::StoreLocal(count) => count.to_be_bytes().to_vec(),
         _ => Vec::<u8>::new() 
+    };
+    Some(operand)
+}
+
+fn u16_bytes(value: usize) -> Option<[u8; 2]> {
+    u16::try_from(value).ok().map(u16::to_be_bytes)
+}
+
+fn u32_bytes(value: usize) -> Option<[u8; 4]> {
+    u32::try_from(value).ok().map(u32::to_be_bytes)
+}
+
+fn string_bytes(string: &str) -> Option<Vec<u8>> {
+    Some([
+        u16_bytes(string.len())?.to_vec(),
+        string.as_bytes().to_vec()
+    ].concat())
+}
+
+// The debug section follows the functions, and is skipped by anything that doesn't need it:
+//     "DBUG", u32 length
+//     u16 file count, then each file name
+//     u16 function count, then for each function:
+//         name, u16 file index, u16 local count, each local name,
+//         u32 line count, then each u32 offset, u32 line, u32 column
+fn debug_section(sources: &[SourceFile], functions: &[(String, &FunctionDebug, LineTable)]) -> Option<Vec<u8>> {
+    let mut section = Vec::<u8>::new();
+
+    section.extend(u16_bytes(sources.len())?);
+    for source in sources {
+        section.extend(string_bytes(&source.name)?);
+    }
+
+    section.extend(u16_bytes(functions.len())?);
+    for (name, debug, lines) in functions {
+        section.extend(string_bytes(name)?);
+        section.extend(u16_bytes(debug.file)?);
+
+        section.extend(u16_bytes(debug.locals.len())?);
+        for local in &debug.locals {
+            section.extend(string_bytes(local)?);
+        }
+
+        section.extend(u32_bytes(lines.len())?);
+        for (offset, line, column) in lines {
+            section.extend(offset.to_be_bytes());
+            section.extend(line.to_be_bytes());
+            section.extend(column.to_be_bytes());
+        }
     }
+
+    Some([b"DBUG".to_vec(), u32_bytes(section.len())?.to_vec(), section].concat())
 }
 
-pub fn ir_bytecode(mut ir: IR) -> Vec<u8> {
+// Debug info is only included when `debug_info` is set. `sources` are in the order the modules were linked.
+pub fn ir_bytecode(mut ir: IR, sources: &[SourceFile], debug_info: bool) -> Result<Vec<u8>, CompileError> {
     let header: &[u8; 4] = &[0x4E, 0x45, 0x4E, 0x43];
 
     let mut bytecode = Vec::<u8>::new();
+    let mut debug_functions = Vec::<(String, &FunctionDebug, LineTable)>::new();
+    let default_debug = FunctionDebug::default();
 
     if let Some(scope) = ir.scope.pop() {
-        if scope.get("main").is_none() {
+        if !scope.contains_key("main") {
             panic!("No main function defined!");
         }
 
         for (name, element) in scope {
             match element {
-                ScopeElement::Variable => todo!("Handle variables"),
-                ScopeElement::PlaceholderFunction => panic!("Unreachable"),
+                ScopeElement::Variable(_, _) => todo!("Handle variables"),
+                ScopeElement::PlaceholderFunction(_) => panic!("Unreachable"),
                 ScopeElement::Function(f) => {
-                    let name_len: &[u8] = &(name.len() as u16).to_be_bytes();
+                    let too_large = || CompileError::message(format!("Function '{}' is too large to compile.", name));
+                    let name_len: &[u8] = &u16_bytes(name.len()).ok_or_else(too_large)?;
 
                     let mut body = Vec::<u8>::new();
+                    let debug = ir.debug.get(&name).unwrap_or(&default_debug);
+                    let source = sources.get(debug.file);
+                    let mut lines = LineTable::new();
+
+                    for (instruction, span) in f {
+                        // A new entry is only needed when the position changes
+                        if let Some(source) = source.filter(|_| debug_info) {
+                            let location = source.location(span.start);
+                            let position = (location.line as u32, location.display_column as u32);
+                            if lines.last().map(|(_, line, column)| (*line, *column)) != Some(position) {
+                                lines.push((body.len() as u32, position.0, position.1));
+                            }
+                        }
 
-                    for instruction in f {
                         let opcode = instruction_opcode(instruction.clone());
                         body.extend(opcode);
 
-                        let operand = instruction_operand(instruction.clone());
+                        let operand = match instruction_operand(instruction) {
+                            Some(operand) => operand,
+                            None => return Err(match source {
+                                Some(source) => CompileError::new(source, &SemanticError::OperandTooLarge(span)),
+                                None => too_large()
+                            })
+                        };
                         body.extend(operand);
                     }
 
-                    let body_len: &[u8] = &(body.len() as u32).to_be_bytes();
+                    let parameters: &[u8] = &u16_bytes(ir.parameter_count(&name)).ok_or_else(too_large)?;
+                    let body_len: &[u8] = &u32_bytes(body.len()).ok_or_else(too_large)?;
 
                     let function_bytecode = [
                         name_len, 
                         name.as_bytes(), 
+                        parameters,
                         body_len, 
                         &body
                     ].concat();
 
                     bytecode.extend(function_bytecode);
-                },
-                ScopeElement::BuiltInFunction => {
-                    match get_bytecode_of_function(name.clone()) {
-                        Some(b) => {
-                            let name_len: &[u8] = &(name.len() as u16).to_be_bytes();
-                            let body_len: &[u8] = &(b.len() as u32).to_be_bytes();
-
-                            let function_bytecode = [
-                                name_len, 
-                                name.as_bytes(), 
-                                body_len, 
-                                &b
-                            ].concat();
-
-                            bytecode.extend(function_bytecode);
-                        },
-                        None => todo!("Built-in {name} called without codegen definition")
+
+                    if debug_info {
+                        debug_functions.push((name, debug, lines));
                     }
-                }
+                },
+                // Linking replaces built-ins with the prelude's functions
+                ScopeElement::BuiltInFunction |
+                ScopeElement::ExternFunction => panic!("Unreachable")
             }
         }
     } else {
@@ -85,8 +196,14 @@ pub fn ir_bytecode(mut ir: IR) -> Vec<u8> {
     }
 
     let mut full_bytecode: Vec<u8> = header.to_vec();
-    full_bytecode.extend((bytecode.len() as u32).to_be_bytes());
+    full_bytecode.extend(u32_bytes(bytecode.len()).ok_or_else(|| CompileError::message("The program is too large to compile.".to_string()))?);
     full_bytecode.extend(bytecode);
 
-    full_bytecode
+    if debug_info {
+        let section = debug_section(sources, &debug_functions)
+            .ok_or_else(|| CompileError::message("The debug info of the program is too large to compile.".to_string()))?;
+        full_bytecode.extend(section);
+    }
+
+    Ok(full_bytecode)
 }
@@ -1,23 +1,359 @@
 use std::fmt;
 
+use source::{ display_width, expand_tabs, SourceFile, Span };
+use types::Type;
+
 const RED: &str = "\u{001b}[91m";
+const YELLOW: &str = "\u{001b}[93m";
 const RESET: &str = "\u{001b}[0m";
 
+#[derive(Debug, Clone, Copy)]
 pub struct ErrorLocation {
     pub line: usize,
-    pub column: usize
+    // Column in UTF-8 bytes from the start of the line
+    pub column: usize,
+    // Column as displayed in a terminal
+    pub display_column: usize
+}
+
+pub trait Diagnostic: fmt::Display {
+    fn span(&self) -> Span;
 }
 
+#[derive(Debug)]
 pub enum SyntaxError {
-    UnknownStartOfToken(ErrorLocation, char)
+    UnknownStartOfToken(Span, char),
+    BidiControlCharacter(Span, char),
+    DisallowedIdentifierCharacter(Span, char),
+    MixedScriptIdentifier(Span, String),
+    UnterminatedString(Span),
+    UnknownEscapeSequence(Span, char),
+    InvalidUnicodeEscape(Span),
+    IntegerTooLarge(Span),
+    UnterminatedInterpolation(Span),
+    EmptyInterpolation(Span),
+    UnmatchedCloseCurly(Span),
+    // A `)` or `}` inside of an interpolation that doesn't close the last bracket opened in it
+    UnbalancedInterpolation(Span, char),
+    InvalidFormatSpec(Span, String),
+    UnexpectedToken { span: Span, expected: String, found: String },
+    UnexpectedEndOfFile { span: Span, expected: String }
+}
+
+impl Diagnostic for SyntaxError {
+    fn span(&self) -> Span {
+        match self {
+            SyntaxError::UnknownStartOfToken(span, _) |
+            SyntaxError::BidiControlCharacter(span, _) |
+            SyntaxError::DisallowedIdentifierCharacter(span, _) |
+            SyntaxError::MixedScriptIdentifier(span, _) |
+            SyntaxError::UnterminatedString(span) |
+            SyntaxError::UnknownEscapeSequence(span, _) |
+            SyntaxError::InvalidUnicodeEscape(span) |
+            SyntaxError::IntegerTooLarge(span) |
+            SyntaxError::UnterminatedInterpolation(span) |
+            SyntaxError::EmptyInterpolation(span) |
+            SyntaxError::UnmatchedCloseCurly(span) |
+            SyntaxError::UnbalancedInterpolation(span, _) |
+            SyntaxError::InvalidFormatSpec(span, _) |
+            SyntaxError::UnexpectedToken { span, .. } |
+            SyntaxError::UnexpectedEndOfFile { span, .. } => *span
+        }
+    }
 }
 
 impl fmt::Display for SyntaxError {
    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            SyntaxError::UnknownStartOfToken(loc, c) => {
-                write!(f, "{RED}ERROR{RESET} {}:{}: Unexpected start of token: {RED}{}{RESET}", loc.line, loc.column, c)
+            SyntaxError::UnknownStartOfToken(_, c) => {
+                write!(f, "Unexpected start of token: {RED}{}{RESET}", c)
+            },
+            SyntaxError::BidiControlCharacter(_, c) => {
+                write!(f, "Unicode text direction control character {RED}U+{:04X}{RESET} is not allowed outside of strings", *c as u32)
+            },
+            SyntaxError::DisallowedIdentifierCharacter(_, c) => {
+                write!(f, "Character {RED}{}{RESET} (U+{:04X}) is not allowed in identifiers", c, *c as u32)
+            },
+            SyntaxError::MixedScriptIdentifier(_, name) => {
+                write!(f, "Identifier {RED}{}{RESET} mixes characters from multiple scripts, which can be confused with other identifiers", name)
+            },
+            SyntaxError::UnterminatedString(_) => {
+                write!(f, "String literal starting here is never closed")
+            },
+            SyntaxError::UnknownEscapeSequence(_, c) => {
+                write!(f, "Unknown escape sequence: {RED}\\{}{RESET}", c)
+            },
+            SyntaxError::InvalidUnicodeEscape(_) => {
+                write!(f, "Invalid unicode escape, expected {RED}\\u{{...}}{RESET} with 1 to 6 hex digits of a valid code point")
+            },
+            SyntaxError::IntegerTooLarge(_) => {
+                write!(f, "Integer literal is too large")
+            },
+            SyntaxError::UnterminatedInterpolation(_) => {
+                write!(f, "Interpolation starting here is never closed with {RED}}}{RESET}")
+            },
+            SyntaxError::EmptyInterpolation(_) => {
+                write!(f, "Expected an expression inside of interpolation, use {RED}{{{{{RESET} for a literal brace")
+            },
+            SyntaxError::UnmatchedCloseCurly(_) => {
+                write!(f, "Unmatched {RED}}}{RESET} in string, use {RED}}}}}{RESET} for a literal brace")
+            },
+            SyntaxError::UnbalancedInterpolation(_, c) => {
+                write!(f, "Unmatched {RED}{}{RESET} in interpolation", c)
+            },
+            SyntaxError::InvalidFormatSpec(_, spec) => {
+                write!(f, "Invalid format specifier: {RED}{}{RESET}", spec)
+            },
+            SyntaxError::UnexpectedToken { expected, found, .. } => {
+                write!(f, "Expected {}, found {RED}{}{RESET}", expected, found)
+            },
+            SyntaxError::UnexpectedEndOfFile { expected, .. } => {
+                write!(f, "Expected {}, found end of file", expected)
+            }
+        }
+   }
+}
+
+#[derive(Debug)]
+pub enum SemanticError {
+    UndefinedFunction(Span, String),
+    UndefinedVariable(Span, String),
+    NotAFunction { span: Span, name: String },
+    ParameterAlreadyDefined(Span, String),
+    FunctionAlreadyDefined(Span, String),
+    UnknownModule(Span, String),
+    ModuleNotFound(Span, String, String),
+    ModuleNameConflict(Span, String),
+    InvalidModuleName(Span, String),
+    ImportCycle(Span, String),
+    UnknownType(Span, String),
+    ArgumentCountMismatch { span: Span, name: String, expected: usize, found: usize },
+    TypeMismatch { span: Span, expected: Type, found: Type },
+    VoidValue(Span),
+    ImpureCall(Span, String),
+    MissingReturn(Span, String, Type),
+    InvalidMain(Span),
+    // A string or name that is longer than the bytecode can hold
+    OperandTooLarge(Span)
+}
+
+impl Diagnostic for SemanticError {
+    fn span(&self) -> Span {
+        match self {
+            SemanticError::UndefinedFunction(span, _) |
+            SemanticError::UndefinedVariable(span, _) |
+            SemanticError::NotAFunction { span, .. } |
+            SemanticError::ParameterAlreadyDefined(span, _) |
+            SemanticError::FunctionAlreadyDefined(span, _) |
+            SemanticError::UnknownModule(span, _) |
+            SemanticError::ModuleNotFound(span, _, _) |
+            SemanticError::ModuleNameConflict(span, _) |
+            SemanticError::InvalidModuleName(span, _) |
+            SemanticError::ImportCycle(span, _) |
+            SemanticError::UnknownType(span, _) |
+            SemanticError::ArgumentCountMismatch { span, .. } |
+            SemanticError::TypeMismatch { span, .. } |
+            SemanticError::VoidValue(span) |
+            SemanticError::ImpureCall(span, _) |
+            SemanticError::MissingReturn(span, _, _) |
+            SemanticError::InvalidMain(span) |
+            SemanticError::OperandTooLarge(span) => *span
+        }
+    }
+}
+
+impl fmt::Display for SemanticError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SemanticError::UndefinedFunction(_, name) => {
+                write!(f, "Function {RED}{}{RESET} was called, but not defined", name)
+            },
+            SemanticError::UndefinedVariable(_, name) => {
+                write!(f, "Variable {RED}{}{RESET} was used, but not defined", name)
+            },
+            SemanticError::NotAFunction { name, .. } => {
+                write!(f, "Variable {RED}{}{RESET} is not a function, so it cannot be called", name)
+            },
+            SemanticError::ParameterAlreadyDefined(_, name) => {
+                write!(f, "Parameter {RED}{}{RESET} is already defined", name)
+            },
+            SemanticError::FunctionAlreadyDefined(_, name) => {
+                write!(f, "Function {RED}{}{RESET} is already defined", name)
+            },
+            SemanticError::UnknownModule(_, name) => {
+                write!(f, "Module {RED}{}{RESET} is used, but not imported", name)
+            },
+            SemanticError::ModuleNotFound(_, path, reason) => {
+                write!(f, "Could not import {RED}{}{RESET}: {}", path, reason)
+            },
+            SemanticError::ModuleNameConflict(_, name) => {
+                write!(f, "A different module named {RED}{}{RESET} is already imported", name)
+            },
+            SemanticError::InvalidModuleName(_, name) => {
+                write!(f, "Module {RED}{}{RESET} can't be imported, since its name isn't an identifier", name)
+            },
+            SemanticError::ImportCycle(_, cycle) => {
+                write!(f, "Modules import each other in a cycle: {RED}{}{RESET}", cycle)
+            },
+            SemanticError::UnknownType(_, name) => {
+                write!(f, "Unknown type {RED}{}{RESET}", name)
+            },
+            SemanticError::ArgumentCountMismatch { name, expected, found, .. } => {
+                let plural = if *expected == 1 { "" } else { "s" };
+                let verb = if *found == 1 { "was" } else { "were" };
+                write!(f, "Function {RED}{}{RESET} takes {} argument{}, but {} {} given", name, expected, plural, found, verb)
+            },
+            SemanticError::TypeMismatch { expected, found, .. } => {
+                write!(f, "Expected a value of type {}, found {RED}{}{RESET}", expected, found)
+            },
+            SemanticError::VoidValue(_) => {
+                write!(f, "Expression does not produce a value")
+            },
+            SemanticError::ImpureCall(_, name) => {
+                write!(f, "Impure function {RED}{}{RESET} cannot be called from a function that isn't marked `impure`", name)
+            },
+            SemanticError::MissingReturn(_, name, t) => {
+                write!(f, "Function {RED}{}{RESET} must return a value of type {}", name, t)
+            },
+            SemanticError::InvalidMain(_) => {
+                write!(f, "Function {RED}main{RESET} cannot take any parameters, and can only return an int")
+            },
+            SemanticError::OperandTooLarge(_) => {
+                write!(f, "String is too long to compile, since it can be at most {} bytes", u16::MAX)
             }
         }
-   } 
+    }
+}
+
+#[derive(Debug)]
+pub enum SemanticWarning {
+    BuiltInShadowed(Span, String)
+}
+
+impl Diagnostic for SemanticWarning {
+    fn span(&self) -> Span {
+        match self {
+            SemanticWarning::BuiltInShadowed(span, _) => *span
+        }
+    }
+}
+
+impl fmt::Display for SemanticWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SemanticWarning::BuiltInShadowed(_, name) => {
+                write!(f, "Function {YELLOW}{}{RESET} shadows a built-in function, which can still be called as {YELLOW}std::{}{RESET}", name, name)
+            }
+        }
+    }
+}
+
+// Why a program couldn't be compiled, with each error shown along with the line of source it points to.
+// Warnings found before compiling stopped are kept, and shown before the errors.
+#[derive(Debug)]
+pub struct CompileError {
+    pub warnings: Vec<String>,
+    pub errors: Vec<String>
+}
+
+impl CompileError {
+    pub fn new(source: &SourceFile, error: &impl Diagnostic) -> CompileError {
+        CompileError { warnings: Vec::<String>::new(), errors: vec![render(source, error)] }
+    }
+
+    // An error that isn't in the source of the program, such as a file that couldn't be read
+    pub fn message(message: String) -> CompileError {
+        CompileError { warnings: Vec::<String>::new(), errors: vec![format!("{RED}ERROR{RESET} {}\n", message)] }
+    }
+
+    // The warnings and errors without colors, for showing somewhere other than a terminal
+    pub fn plain(&self) -> String {
+        without_colors(&self.to_string())
+    }
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for message in self.warnings.iter().chain(&self.errors) {
+            write!(f, "{}", message)?;
+        }
+        Ok(())
+    }
+}
+
+// Problems with a program that still compiled, which are left to the caller to show
+#[derive(Debug, Default)]
+pub struct CompileWarnings {
+    pub warnings: Vec<String>
+}
+
+impl CompileWarnings {
+    pub fn is_empty(&self) -> bool {
+        self.warnings.is_empty()
+    }
+
+    // The warnings without colors, for showing somewhere other than a terminal
+    pub fn plain(&self) -> String {
+        without_colors(&self.to_string())
+    }
+}
+
+impl fmt::Display for CompileWarnings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for warning in &self.warnings {
+            write!(f, "{}", warning)?;
+        }
+        Ok(())
+    }
+}
+
+fn without_colors(text: &str) -> String {
+    let mut parts = text.split('\u{001b}');
+    let mut plain = parts.next().unwrap_or_default().to_string();
+    for part in parts {
+        // Each escape sequence ends with `m`, such as `\u{001b}[91m`
+        plain.push_str(part.split_once('m').map_or("", |(_, rest)| rest));
+    }
+    plain
+}
+
+// Formats the error along with the line of source it points to:
+//
+// ERROR main.nen:2:9: Expected `;`, found `}`
+//    2 |   print("")
+//      |            ^
+pub fn render(source: &SourceFile, error: &impl Diagnostic) -> String {
+    render_diagnostic(source, error, "ERROR", RED)
+}
+
+pub fn render_warning(source: &SourceFile, warning: &impl Diagnostic) -> String {
+    render_diagnostic(source, warning, "WARNING", YELLOW)
+}
+
+fn render_diagnostic(source: &SourceFile, error: &impl Diagnostic, label: &str, color: &str) -> String {
+    let span = error.span();
+    let location = source.location(span.start);
+
+    let mut rendered = format!("{color}{label}{RESET} {}:{}:{}: {}\n", source.name, location.line, location.display_column, error);
+
+    let line = location.line - 1;
+    let text = source.line_text(line);
+    let line_start = source.lines.line_start(line);
+    let line_end = line_start + text.len();
+
+    let underline_start = span.start.min(line_end);
+    let underline_end = span.end.clamp(underline_start, line_end);
+    let before = &text[..underline_start - line_start];
+    let padding = display_width(before, 0);
+    let length = display_width(&text[underline_start - line_start..underline_end - line_start], padding).max(1);
+
+    let number = location.line.to_string();
+    let gutter = " ".repeat(number.len());
+
+    rendered.push_str(&format!(" {} | {}\n", number, expand_tabs(text)));
+    rendered.push_str(&format!(" {} | {}{color}{}{RESET}\n", gutter, " ".repeat(padding), "^".repeat(length)));
+    // The header uses the column as displayed, while tools
+    // working with the file's bytes need the byte column
+    rendered.push_str(&format!(" {} = display column {}, byte column {}\n", gutter, location.display_column, location.column));
+    rendered
 }
@@ -1,32 +1,108 @@
-use std::collections::HashMap;
+use std::collections::{ HashMap, HashSet };
 
-use crate::parser::{ Node, Program, Expr, Statement };
+use crate::builtin::{ builtin, Builtin, Implementation, PRELUDE_NAME };
+use crate::error::{ SemanticError, SemanticWarning };
+use crate::lexer::FormatSpec;
+use crate::parser::{ Node, NodeKind, Parameter, Program, Expr, ExprKind, Statement, StatementKind };
+use crate::source::Span;
+use crate::types::{ Signature, Type };
 
 #[allow(dead_code)]
 #[derive(Debug)]
 pub struct IR {
-    pub scope: Vec<HashMap<String, ScopeElement>>
+    pub scope: Vec<HashMap<String, ScopeElement>>,
+    pub errors: Vec<SemanticError>,
+    pub warnings: Vec<SemanticWarning>,
+    // Prepended to the names of functions defined in this module, such as `util::`
+    prefix: String,
+    // The functions of each imported module, by the name it is imported as
+    imports: HashMap<String, ModuleExports>,
+    // The functions of the prelude, which are in scope without being imported.
+    // This is `None` when compiling the prelude itself, which can use intrinsics.
+    prelude: Option<ModuleExports>,
+    // Signatures of the functions defined in this module, collected
+    // before any bodies so that calls can be checked in any order
+    signatures: HashMap<String, Signature>,
+    // Functions declared with `extern`, which are implemented by the host of the VM
+    externs: HashSet<String>,
+    // Whether the function being handled is impure, and so can call impure functions
+    impure: bool,
+    // The return type of the function being handled
+    returns: Type,
+    // What the debug info records about each function, by name
+    pub debug: HashMap<String, FunctionDebug>
 }
 
+#[derive(Debug, Clone, Default)]
+pub struct FunctionDebug {
+    // Index of the module the function was defined in, in the order the modules were linked
+    pub file: usize,
+    // Names of the function's locals, by their index
+    pub locals: Vec<String>
+}
+
+#[derive(Debug, Clone)]
+pub struct ModuleExports {
+    pub prefix: String,
+    pub functions: HashMap<String, Signature>,
+    pub externs: HashSet<String>
+}
+
+// Every instruction is paired with the span of source it was generated from
+pub type Body = Vec<(Instruction, Span)>;
+
 #[allow(dead_code)]
 #[derive(Debug, Clone)]
 pub enum ScopeElement {
-    Function(Vec<Instruction>),
+    Function(Body),
     // PlaceholderFunction is when a function is called,
     // but it is not defined yet.
     // 
     // We need this so that we can define functions later in
     // code than where they are called.
-    PlaceholderFunction,
+    // The span is of the first call, to report if it is never defined.
+    PlaceholderFunction(Span),
+    // A function defined in the prelude
     BuiltInFunction,
-    Variable
+    // A function declared with `extern`, which has no body
+    ExternFunction,
+    // A parameter of the current function, by its index in the function's locals
+    Variable(u16, Type)
 }
 
 #[derive(Debug, Clone)]
 pub enum Instruction {
     PushString(String),
+    PushInt(i64),
     Call(String),
-    Write
+    // Calls a function implemented by the VM, or registered by its host.
+    // The VM checks host functions against how they were declared, and
+    // built-ins against its own implementations when the program is loaded.
+    CallNative { name: String, arguments: u8, impure: bool, returns_value: bool, builtin: bool },
+    Write,
+    ReadLine,
+    ReadAll,
+    ReadInt,
+    // Pushes the next line along with its line ending, or an empty string at the end of input
+    ReadFullLine,
+    // Pushes `1` if there is no input left to read, or `0` otherwise
+    AtEof,
+    // Writes the value on top of the stack to standard error
+    WriteError,
+    // Flushes standard output and standard error
+    Flush,
+    ToString(FormatSpec),
+    Concat(u16),
+    // Pushes a copy of a local of the current call
+    LoadLocal(u16),
+    // Pops a value into a local of the current call
+    StoreLocal(u16),
+    // Discards the value on top of the stack
+    Pop,
+    // Returns from the current call, leaving its return value (if any) on the stack
+    Return,
+    // Stops the program, with the value on top of the stack as its exit status
+    Exit
 }
 
 impl IR {
@@ -46,49 +122,248 @@ impl IR {
         self.scope.push(scope);
     }
 
-    fn handle_expression(&mut self, expression: Expr) -> Vec<Instruction> {
-        let mut instructions = Vec::<Instruction>::new();
+    // The name of a function defined in this module, once merged with all other modules
+    fn qualify(&self, name: &str) -> String {
+        format!("{}{}", self.prefix, name)
+    }
+
+    fn handle_arguments(&mut self, arguments: Vec<Expr>) -> (Body, Vec<(Type, Span)>) {
+        let mut instructions = Body::new();
+        let mut types = Vec::<(Type, Span)>::new();
+
+        for argument in arguments {
+            let span = argument.span;
+            let (body, t) = self.handle_expression(argument);
+            instructions.extend(body);
+            types.push((t, span));
+        }
+
+        (instructions, types)
+    }
+
+    // Checks the arguments of a call against the signature of the function being called
+    fn check_call(&mut self, name: &str, signature: &Signature, arguments: &[(Type, Span)], span: Span) {
+        if arguments.len() != signature.parameters.len() {
+            self.errors.push(SemanticError::ArgumentCountMismatch {
+                span,
+                name: name.to_string(),
+                expected: signature.parameters.len(),
+                found: arguments.len()
+            });
+        } else {
+            for (expected, (found, argument_span)) in signature.parameters.iter().zip(arguments) {
+                if !expected.accepts(*found) {
+                    self.errors.push(SemanticError::TypeMismatch { span: *argument_span, expected: *expected, found: *found });
+                }
+            }
+        }
+
+        if signature.impure && !self.impure {
+            self.errors.push(SemanticError::ImpureCall(span, name.to_string()));
+        }
+    }
+
+    fn handle_builtin_call(&mut self, builtin: &Builtin, mut instructions: Body, arguments: &[(Type, Span)], span: Span) -> (Body, Type) {
+        self.check_call(builtin.name, &builtin.signature(), arguments, span);
+
+        match builtin.implementation {
+            Implementation::Instructions(code) => {
+                instructions.extend(code.iter().map(|instruction| (instruction.clone(), span)));
+            },
+            Implementation::Native => instructions.push((native_call(builtin.name, &builtin.signature(), true), span))
+        }
+
+        (instructions, builtin.returns)
+    }
+
+    // Returns the instructions of the expression, along with the type of its value
+    fn handle_expression(&mut self, expression: Expr) -> (Body, Type) {
+        let mut instructions = Body::new();
+        let span = expression.span;
         
-        match expression {
-            Expr::FunctionCall { name, arguments } => {
-                for argument in arguments {
-                    instructions.extend(self.handle_expression(argument));
-                } 
+        let value_type = match expression.kind {
+            ExprKind::FunctionCall { module: Some(module), name, arguments } => {
+                let (body, types) = self.handle_arguments(arguments);
+                instructions.extend(body);
 
-                match self.get_from_scope(&name) {
-                    Some((_, ScopeElement::Function(_))) => {},
-                    Some((_, ScopeElement::PlaceholderFunction)) => {},
-                    Some((_, ScopeElement::BuiltInFunction)) => {},
-                    Some((_, ScopeElement::Variable)) => todo!("Tried to call variable"),
+                let qualified_name = format!("{}::{}", module, name);
+
+                // `std::name` always refers to the built-in, even if it is shadowed
+                if module == PRELUDE_NAME && !self.imports.contains_key(&module) {
+                    if let Some(builtin) = builtin(&name).filter(|b| !b.internal) {
+                        return self.handle_builtin_call(builtin, instructions, &types, span);
+                    }
+                }
+
+                let imported = match self.imports.get(&module) {
+                    Some(exports) => Some(exports),
+                    None if module == PRELUDE_NAME => self.prelude.as_ref(),
+                    None => None
+                };
+
+                let (function, signature) = match imported {
+                    Some(exports) => {
+                        let function = format!("{}{}", exports.prefix, name);
+                        match exports.functions.get(&name) {
+                            Some(signature) => (function, Some(signature.clone())),
+                            None => {
+                                self.errors.push(SemanticError::UndefinedFunction(span, qualified_name.clone()));
+                                (function, None)
+                            }
+                        }
+                    },
                     None => {
-                        self.add_to_scope(&name, ScopeElement::PlaceholderFunction);
+                        self.errors.push(SemanticError::UnknownModule(span, module));
+                        (name.to_string(), None)
                     }
+                };
+
+                // Host functions aren't qualified, since they're registered with the VM by name
+                let call = match (imported, &signature) {
+                    (Some(exports), Some(signature)) if exports.externs.contains(&name) => native_call(&name, signature, false),
+                    _ => Instruction::Call(function)
+                };
+                instructions.push((call, span));
+
+                match signature {
+                    Some(signature) => {
+                        self.check_call(&qualified_name, &signature, &types, span);
+                        signature.returns
+                    },
+                    None => Type::Any
                 }
-                
-                instructions.push(Instruction::Call(name));
             },
-            Expr::StringLiteral(s) => {
-                instructions.push(Instruction::PushString(s));
+            ExprKind::FunctionCall { module: None, name, arguments } => {
+                let (body, types) = self.handle_arguments(arguments);
+                instructions.extend(body);
+
+                // Functions defined in this module shadow built-ins
+                let defined = self.signatures.get(&name).cloned();
+
+                if defined.is_none() {
+                    if let Some(builtin) = builtin(&name) {
+                        if !builtin.internal || self.prelude.is_none() {
+                            return self.handle_builtin_call(builtin, instructions, &types, span);
+                        }
+                    }
+                }
+
+                let (function, signature) = match self.get_from_scope(&name) {
+                    _ if defined.is_some() => (self.qualify(&name), defined),
+                    Some((_, ScopeElement::Function(_))) |
+                    Some((_, ScopeElement::ExternFunction)) |
+                    Some((_, ScopeElement::PlaceholderFunction(_))) => (self.qualify(&name), None),
+                    Some((_, ScopeElement::BuiltInFunction)) => {
+                        let prelude = self.prelude.as_ref().expect("Built-ins should come from the prelude");
+                        (format!("{}{}", prelude.prefix, name), prelude.functions.get(&name).cloned())
+                    },
+                    Some((_, ScopeElement::Variable(_, _))) => {
+                        self.errors.push(SemanticError::NotAFunction { span, name });
+                        return (instructions, Type::Any);
+                    },
+                    None => {
+                        // Functions can only be defined at the top level
+                        self.scope[0].insert(name.to_string(), ScopeElement::PlaceholderFunction(span));
+                        (self.qualify(&name), self.signatures.get(&name).cloned())
+                    }
+                };
+
+                let call = match &signature {
+                    // Host functions aren't qualified, since they're registered with the VM by name
+                    Some(signature) if self.externs.contains(&name) => native_call(&name, signature, false),
+                    _ => Instruction::Call(function)
+                };
+                instructions.push((call, span));
+
+                match signature {
+                    Some(signature) => {
+                        self.check_call(&name, &signature, &types, span);
+                        signature.returns
+                    },
+                    None => Type::Any
+                }
+            },
+            ExprKind::StringLiteral(s) => {
+                instructions.push((Instruction::PushString(s), span));
+                Type::String
+            },
+            ExprKind::IntegerLiteral(i) => {
+                instructions.push((Instruction::PushInt(i), span));
+                Type::Int
+            },
+            ExprKind::Variable(name) => {
+                match self.get_from_scope(&name) {
+                    Some((_, ScopeElement::Variable(index, t))) => {
+                        instructions.push((Instruction::LoadLocal(index), span));
+                        t
+                    },
+                    _ => {
+                        self.errors.push(SemanticError::UndefinedVariable(span, name));
+                        Type::Any
+                    }
+                }
+            },
+            ExprKind::ToString { value, format } => {
+                let value_span = value.span;
+                let (body, t) = self.handle_expression(*value);
+                if t == Type::Void {
+                    self.errors.push(SemanticError::VoidValue(value_span));
+                }
+                instructions.extend(body);
+                instructions.push((Instruction::ToString(format), span));
+                Type::String
+            },
+            ExprKind::Concat(parts) => {
+                let count = parts.len() as u16;
+                for part in parts {
+                    instructions.extend(self.handle_expression(part).0);
+                }
+                instructions.push((Instruction::Concat(count), span));
+                Type::String
             }
-        }
+        };
 
-        instructions
+        (instructions, value_type)
     }
 
-    fn handle_statement(&mut self, statement: Statement) -> Vec<Instruction> {
-        let mut instructions = Vec::<Instruction>::new();
+    fn handle_statement(&mut self, statement: Statement) -> Body {
+        let mut instructions = Body::new();
+
+        match statement.kind {
+            StatementKind::Expr(e) => {
+                let (body, t) = self.handle_expression(e);
+                instructions.extend(body);
 
-        match statement {
-            Statement::Expr(e) => {
-                instructions.extend(self.handle_expression(e));
+                // Values that aren't used are discarded
+                if t != Type::Void {
+                    instructions.push((Instruction::Pop, statement.span));
+                }
+            },
+            StatementKind::Return(value) => {
+                match value {
+                    Some(value) => {
+                        let value_span = value.span;
+                        let (body, t) = self.handle_expression(value);
+                        if self.returns == Type::Void || !self.returns.accepts(t) {
+                            self.errors.push(SemanticError::TypeMismatch { span: value_span, expected: self.returns, found: t });
+                        }
+                        instructions.extend(body);
+                    },
+                    None => {
+                        if self.returns != Type::Void {
+                            self.errors.push(SemanticError::TypeMismatch { span: statement.span, expected: self.returns, found: Type::Void });
+                        }
+                    }
+                }
+                instructions.push((Instruction::Return, statement.span));
             }
         }
 
         instructions
     }
 
-    fn handle_function_body(&mut self, contents: Vec<Statement>) -> Vec<Instruction> {
-        let mut instructions = Vec::<Instruction>::new();
+    fn handle_function_body(&mut self, contents: Vec<Statement>) -> Body {
+        let mut instructions = Body::new();
 
         for statement in contents {
             instructions.extend(self.handle_statement(statement));
@@ -97,21 +372,73 @@ impl IR {
         instructions
     }
 
+    // Arguments are pushed in order by the caller,
+    // so they are popped into locals in reverse
+    fn handle_parameters(&mut self, parameters: Vec<Parameter>) -> Body {
+        let mut instructions = Body::new();
+        let mut locals = HashMap::<String, ScopeElement>::new();
+
+        for (index, parameter) in parameters.iter().enumerate() {
+            // Unknown types are reported along with the function's signature
+            let t = Type::from_name(&parameter.type_name).unwrap_or(Type::Any);
+            if locals.insert(parameter.name.to_string(), ScopeElement::Variable(index as u16, t)).is_some() {
+                self.errors.push(SemanticError::ParameterAlreadyDefined(parameter.span, parameter.name.to_string()));
+            }
+        }
+
+        for (index, parameter) in parameters.iter().enumerate().rev() {
+            instructions.push((Instruction::StoreLocal(index as u16), parameter.span));
+        }
+
+        self.scope.push(locals);
+        instructions
+    }
+
     fn handle_node(&mut self, node: Node) {
-        match node {
-            Node::FunctionDefinition { name, contents, impure: _ } => {
-                if let Some(element) = self.get_from_scope(&name) {
-                    match element {
-                        (_, ScopeElement::Function(_)) => todo!("Function {name} already defined"),
-                        (_, ScopeElement::PlaceholderFunction) => {},
-                        (_, ScopeElement::BuiltInFunction) => {
-                            todo!("Function {name} defined as built-in - We need to decide if we want to allow overwriting of built-in functions");
-                        },
-                        (_, ScopeElement::Variable) => todo!("Function {name} already defined as a variable") 
+        match node.kind {
+            NodeKind::FunctionDefinition { name, name_span, parameters, returns, contents, impure } => {
+                // A function with the same name as a built-in shadows it within this module
+                match self.get_from_scope(&name) {
+                    Some((_, ScopeElement::Function(_))) |
+                    Some((_, ScopeElement::ExternFunction)) => {
+                        self.errors.push(SemanticError::FunctionAlreadyDefined(name_span, name));
+                        return;
+                    },
+                    Some((_, ScopeElement::BuiltInFunction)) => {
+                        self.warnings.push(SemanticWarning::BuiltInShadowed(name_span, name.to_string()));
+                    },
+                    Some((_, ScopeElement::Variable(_, _))) => todo!("Function {name} already defined as a variable"),
+                    _ => {
+                        if builtin(&name).is_some_and(|b| !b.internal) {
+                            self.warnings.push(SemanticWarning::BuiltInShadowed(name_span, name.to_string()));
+                        }
                     }
                 }
 
-                let body = self.handle_function_body(contents);
+                // Unknown types are reported along with the function's signature
+                self.returns = returns.as_ref().map_or(Type::Void, |t| Type::from_name(&t.name).unwrap_or(Type::Any));
+
+                // The entry module's `main` is where the program starts,
+                // and its return value is the program's exit status
+                if name == "main" && self.prefix.is_empty() && (!parameters.is_empty() || !matches!(self.returns, Type::Void | Type::Int)) {
+                    self.errors.push(SemanticError::InvalidMain(name_span));
+                }
+
+                // Without any branching, a function that returns a
+                // value must return it from the top level of its body
+                let has_return = contents.iter().any(|s| matches!(s.kind, StatementKind::Return(_)));
+                if self.returns != Type::Void && !has_return {
+                    self.errors.push(SemanticError::MissingReturn(name_span, name.to_string(), self.returns));
+                }
+
+                self.impure = impure;
+                self.debug.insert(name.to_string(), FunctionDebug {
+                    file: 0,
+                    locals: parameters.iter().map(|p| p.name.to_string()).collect()
+                });
+                let mut body = self.handle_parameters(parameters);
+                body.extend(self.handle_function_body(contents));
+                self.scope.pop();
 
                 let function = ScopeElement::Function(body); 
 
@@ -124,49 +451,219 @@ impl IR {
                 }
 
                 self.add_to_scope(&name, function); 
+            },
+            NodeKind::ExternFunction { name, name_span, .. } => {
+                match self.get_from_scope(&name) {
+                    Some((_, ScopeElement::Function(_))) |
+                    Some((_, ScopeElement::ExternFunction)) => {
+                        self.errors.push(SemanticError::FunctionAlreadyDefined(name_span, name));
+                        return;
+                    },
+                    Some((_, ScopeElement::BuiltInFunction)) => {
+                        self.warnings.push(SemanticWarning::BuiltInShadowed(name_span, name.to_string()));
+                    },
+                    _ => {
+                        if builtin(&name).is_some_and(|b| !b.internal) {
+                            self.warnings.push(SemanticWarning::BuiltInShadowed(name_span, name.to_string()));
+                        }
+                    }
+                }
+
+                self.add_to_scope(&name, ScopeElement::ExternFunction);
+            },
+            // Imports are resolved when loading modules
+            NodeKind::Import(_) => {}
+        }
+    }
+
+    // Records the signature of every function defined in the program
+    fn collect_signatures(&mut self, program: &Program) {
+        for node in program {
+            let (name, parameters, returns, impure) = match &node.kind {
+                NodeKind::FunctionDefinition { name, parameters, returns, impure, .. } => (name, parameters, returns, impure),
+                NodeKind::ExternFunction { name, parameters, returns, impure, .. } => {
+                    if !self.signatures.contains_key(name) {
+                        self.externs.insert(name.to_string());
+                    }
+                    (name, parameters, returns, impure)
+                },
+                NodeKind::Import(_) => continue
+            };
+
+            let mut types = Vec::<Type>::new();
+            for parameter in parameters {
+                match Type::from_name(&parameter.type_name) {
+                    Some(t) => types.push(t),
+                    None => {
+                        self.errors.push(SemanticError::UnknownType(parameter.span, parameter.type_name.to_string()));
+                        types.push(Type::Any);
+                    }
+                }
             }
+
+            let returns = match returns {
+                Some(type_name) => match Type::from_name(&type_name.name) {
+                    Some(t) => t,
+                    None => {
+                        self.errors.push(SemanticError::UnknownType(type_name.span, type_name.name.to_string()));
+                        Type::Any
+                    }
+                },
+                None => Type::Void
+            };
+
+            // Later definitions with the same name are reported as errors when handled
+            self.signatures.entry(name.to_string()).or_insert(Signature {
+                parameters: types,
+                returns,
+                impure: *impure
+            });
         }
     }
-}
 
-impl From<Program> for IR {
-    fn from(program: Program) -> IR {
+    // Removes functions with the given prefix that can't be reached from `main`,
+    // so that only the parts of the prelude a program uses end up in its bytecode
+    pub fn remove_unused(&mut self, prefix: &str) {
+        let functions = &self.scope[0];
+        let mut reachable = HashSet::<String>::new();
+        // Any function outside of the prelude can be called, such as by a host or by `nen test`
+        let mut pending = functions.keys()
+            .filter(|name| !name.starts_with(prefix))
+            .cloned()
+            .collect::<Vec<String>>();
+
+        while let Some(name) = pending.pop() {
+            if let Some(ScopeElement::Function(body)) = functions.get(&name) {
+                if reachable.insert(name) {
+                    for (instruction, _) in body {
+                        if let Instruction::Call(callee) = instruction {
+                            pending.push(callee.to_string());
+                        }
+                    }
+                }
+            }
+        }
+
+        self.scope[0].retain(|name, _| !name.starts_with(prefix) || reachable.contains(name));
+    }
+
+    // The number of parameters of a function of this module, which
+    // the VM checks calls from its host against
+    pub fn parameter_count(&self, name: &str) -> usize {
+        self.signatures.get(name).map_or(0, |signature| signature.parameters.len())
+    }
+
+    pub fn exports(&self) -> ModuleExports {
+        ModuleExports {
+            prefix: self.prefix.clone(),
+            functions: self.signatures.clone(),
+            externs: self.externs.clone()
+        }
+    }
+
+    // Merges the functions of every module into a single scope,
+    // with each function named by its qualified name
+    pub fn link(modules: Vec<IR>) -> IR {
+        let mut functions = HashMap::<String, ScopeElement>::new();
+        let mut debug = HashMap::<String, FunctionDebug>::new();
+        let mut signatures = HashMap::<String, Signature>::new();
+
+        for (file, mut module) in modules.into_iter().enumerate() {
+            for (name, function) in module.debug.drain() {
+                debug.insert(format!("{}{}", module.prefix, name), FunctionDebug { file, ..function });
+            }
+
+            for (name, signature) in module.signatures.drain() {
+                signatures.insert(format!("{}{}", module.prefix, name), signature);
+            }
+
+            let scope = module.scope.pop().expect("Should have a scope");
+            for (name, element) in scope {
+                match element {
+                    // Calls to built-ins refer to the prelude module's own functions,
+                    // and extern functions are only called with `CallNative`
+                    ScopeElement::BuiltInFunction |
+                    ScopeElement::ExternFunction => {},
+                    element => {
+                        functions.insert(format!("{}{}", module.prefix, name), element);
+                    }
+                };
+            }
+        }
+
+        IR {
+            scope: vec![functions],
+            errors: Vec::<SemanticError>::new(),
+            warnings: Vec::<SemanticWarning>::new(),
+            prefix: String::new(),
+            imports: HashMap::<String, ModuleExports>::new(),
+            prelude: None,
+            signatures,
+            externs: HashSet::<String>::new(),
+            impure: false,
+            returns: Type::Void,
+            debug
+        }
+    }
+
+    pub fn from_module(program: Program, prefix: String, imports: HashMap<String, ModuleExports>, prelude: Option<ModuleExports>) -> IR {
         let mut scope = Vec::<HashMap<String, ScopeElement>>::new();
         let mut top_scope = HashMap::<String, ScopeElement>::new();
 
-        
         // Define built-ins
-
-        top_scope.insert("print".to_string(), ScopeElement::BuiltInFunction);
-        top_scope.insert("println".to_string(), ScopeElement::BuiltInFunction);
+        if let Some(prelude) = &prelude {
+            for name in prelude.functions.keys() {
+                top_scope.insert(name.to_string(), ScopeElement::BuiltInFunction);
+            }
+        }
 
         scope.push(top_scope);
         let mut ir = IR {
-            scope
+            scope,
+            errors: Vec::<SemanticError>::new(),
+            warnings: Vec::<SemanticWarning>::new(),
+            prefix,
+            imports,
+            prelude,
+            signatures: HashMap::<String, Signature>::new(),
+            externs: HashSet::<String>::new(),
+            impure: false,
+            returns: Type::Void,
+            debug: HashMap::<String, FunctionDebug>::new()
         };
 
+        ir.collect_signatures(&program);
+
         for node in program {
             ir.handle_node(node);
         }
 
         // Check for any placeholder functions left
         let map = ir.scope.pop().expect("Should have a scope");
-        let mut placeholder_functions = Vec::<String>::new();
+        let mut placeholder_functions = Vec::<(Span, String)>::new();
         for (name, element) in &map {
-            match element {
-                ScopeElement::PlaceholderFunction => placeholder_functions.push(name.to_string()),
-                _ => {}
-            } 
+            if let ScopeElement::PlaceholderFunction(span) = element {
+                placeholder_functions.push((*span, name.to_string()));
+            }
         }
 
-        if placeholder_functions.len() > 0 {
-            for func in placeholder_functions {
-                eprintln!("ERROR: Function {func} was called, but not defined.");
-            }
-            std::process::exit(1);
+        // Report in the order they appear in the source
+        placeholder_functions.sort_by_key(|(span, _)| span.start);
+        for (span, func) in placeholder_functions {
+            ir.errors.push(SemanticError::UndefinedFunction(span, func));
         }
         ir.scope.push(map);
 
         ir
     }
 }
+
+fn native_call(name: &str, signature: &Signature, builtin: bool) -> Instruction {
+    Instruction::CallNative {
+        name: name.to_string(),
+        arguments: signature.parameters.len() as u8,
+        impure: signature.impure,
+        returns_value: signature.returns != Type::Void,
+        builtin
+    }
+}
@@ -1,177 +1,691 @@
-use crate::error::{ErrorLocation, SyntaxError};
+use std::borrow::Cow;
+use std::fmt;
 
+use unicode_normalization::{ is_nfc, UnicodeNormalization };
+use unicode_security::{ GeneralSecurityProfile, MixedScript };
+use unicode_xid::UnicodeXID;
+
+use crate::error::SyntaxError;
+use crate::source::{ SourceFile, Span };
+
+// Lexes the bytes of a source file, with tokens borrowing
+// their values from the source wherever possible.
 #[derive(Debug)]
-pub struct Lexer {
-    input: Vec<char>,
-    index: usize,
-    line: usize,
-    column: usize
+pub struct Lexer<'a> {
+    source: &'a SourceFile,
+    input: &'a str,
+    bytes: &'a [u8],
+    // Byte offset of the next character within the source file
+    offset: usize,
+    end: usize
 }
 
 #[derive(Debug)]
 pub enum Keyword {
+    Extern,
     Func,
-    Impure
+    Impure,
+    Import,
+    Return
 }
 
 #[derive(Debug)]
-pub enum TokenKind {
-    Identifier(String),
+pub enum TokenKind<'a> {
+    // Identifiers are normalized to NFC, so only
+    // borrowed from the source if they already are
+    Identifier(Cow<'a, str>),
     Keyword(Keyword),
-    StringLiteral(String),
+    StringLiteral(Cow<'a, str>),
+    InterpolatedString(Vec<StringSegment<'a>>),
+    IntegerLiteral(i64),
     OpenParen,
     CloseParen,
     OpenCurly,
     CloseCurly,
     Comma,
-    Semicolon
+    Semicolon,
+    Colon,
+    ColonColon
+}
+
+#[derive(Debug)]
+pub enum StringSegment<'a> {
+    Literal(Cow<'a, str>),
+    // The span of an expression embedded in a string with `{...}`,
+    // so that it can be lexed on its own.
+    Expression { span: Span, format: FormatSpec }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Alignment {
+    #[default]
+    Default,
+    Left,
+    Right,
+    Center
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FormatSpec {
+    pub align: Alignment,
+    pub width: Option<u16>,
+    pub precision: Option<u16>
+}
+
+impl<'a> TokenKind<'a> {
+    // Describes the kind of token, regardless of its value
+    pub fn describe(&self) -> &'static str {
+        match self {
+            TokenKind::Identifier(_) => "identifier",
+            TokenKind::Keyword(Keyword::Extern) => "`extern`",
+            TokenKind::Keyword(Keyword::Func) => "`func`",
+            TokenKind::Keyword(Keyword::Impure) => "`impure`",
+            TokenKind::Keyword(Keyword::Import) => "`import`",
+            TokenKind::Keyword(Keyword::Return) => "`return`",
+            TokenKind::StringLiteral(_) |
+            TokenKind::InterpolatedString(_) => "string literal",
+            TokenKind::IntegerLiteral(_) => "integer literal",
+            TokenKind::OpenParen => "`(`",
+            TokenKind::CloseParen => "`)`",
+            TokenKind::OpenCurly => "`{`",
+            TokenKind::CloseCurly => "`}`",
+            TokenKind::Comma => "`,`",
+            TokenKind::Semicolon => "`;`",
+            TokenKind::Colon => "`:`",
+            TokenKind::ColonColon => "`::`"
+        }
+    }
+}
+
+impl<'a> fmt::Display for TokenKind<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenKind::Identifier(name) => write!(f, "identifier `{}`", name),
+            TokenKind::IntegerLiteral(value) => write!(f, "integer `{}`", value),
+            kind => write!(f, "{}", kind.describe())
+        }
+    }
 }
 
-#[allow(dead_code)]
 #[derive(Debug)]
-pub struct Token {
-    line: usize,
-    column: usize,
-    pub kind: TokenKind
+pub struct Token<'a> {
+    pub span: Span,
+    pub kind: TokenKind<'a>
 }
 
-impl Token {
-    pub fn new(line: usize, column: usize, kind: TokenKind) -> Self {
+impl<'a> Token<'a> {
+    pub fn new(span: Span, kind: TokenKind<'a>) -> Self {
         Token {
-            line,
-            column,
+            span,
             kind
         }
     }
 }
 
-impl Lexer {
-    pub fn new(input: &str) -> Lexer {
+// A fixed size ring buffer of tokens, so that the parser can look
+// up to N tokens ahead without lexing any token more than once.
+pub struct Lookahead<'a, const N: usize> {
+    lexer: Lexer<'a>,
+    buffer: [Option<Token<'a>>; N],
+    head: usize,
+    len: usize
+}
+
+impl<'a, const N: usize> Lookahead<'a, N> {
+    pub fn new(lexer: Lexer<'a>) -> Self {
+        Lookahead {
+            lexer,
+            buffer: std::array::from_fn(|_| None),
+            head: 0,
+            len: 0
+        }
+    }
+
+    pub fn lexer(&self) -> &Lexer<'a> {
+        &self.lexer
+    }
+
+    // Returns the token `n` tokens ahead, where 0 is the next token
+    pub fn peek(&mut self, n: usize) -> Result<Option<&Token<'a>>, SyntaxError> {
+        assert!(n < N, "Can only look {} tokens ahead", N);
+
+        while self.len <= n {
+            let token = match self.lexer.next_token()? {
+                Some(token) => token,
+                None => return Ok(None)
+            };
+            self.buffer[(self.head + self.len) % N] = Some(token);
+            self.len += 1;
+        }
+
+        Ok(self.buffer[(self.head + n) % N].as_ref())
+    }
+
+    pub fn next_token(&mut self) -> Result<Option<Token<'a>>, SyntaxError> {
+        if self.len == 0 {
+            return self.lexer.next_token();
+        }
+
+        let token = self.buffer[self.head].take();
+        self.head = (self.head + 1) % N;
+        self.len -= 1;
+        Ok(token)
+    }
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(source: &'a SourceFile) -> Lexer<'a> {
+        Lexer::with_range(source, Span::new(0, source.text.len()))
+    }
+
+    // Used to lex source that is embedded within another part of the file,
+    // such as an expression inside of an interpolated string.
+    pub fn with_range(source: &'a SourceFile, range: Span) -> Lexer<'a> {
         Lexer {
-            input: input.chars().collect::<Vec<char>>(),
-            index: 0,
-            line: 0,
-            column: 0
+            source,
+            input: &source.text,
+            bytes: source.text.as_bytes(),
+            offset: range.start,
+            end: range.end
         }
     }
-    
+
+    pub fn source(&self) -> &'a SourceFile {
+        self.source
+    }
+
+    // An empty span at the current position, used when hitting the end of the file
+    pub fn end_span(&self) -> Span {
+        Span::new(self.offset, self.offset)
+    }
+
     #[inline(always)]
     fn at_end(&self) -> bool {
-        self.index >= self.input.len()
+        self.offset >= self.end
     }
-    
+
+    #[inline(always)]
+    fn byte_at(&self, offset: usize) -> Option<u8> {
+        if self.offset + offset < self.end {
+            Some(self.bytes[self.offset + offset])
+        } else {
+            None
+        }
+    }
+
+    // Only used where a character may be outside of ASCII
+    fn char_at_offset(&self) -> char {
+        self.input[self.offset..].chars().next().expect("Should not be at the end")
+    }
+
+    // Advances past a single character, which may be several bytes long
     fn advance(&mut self) {
-        self.index += 1;
-        self.column += 1;
+        self.offset += match self.bytes[self.offset] {
+            b if b < 0x80 => 1,
+            _ => self.char_at_offset().len_utf8()
+        };
     }
-    
+
+    #[inline(always)]
+    fn span_from(&self, start: usize) -> Span {
+        Span::new(start, self.offset)
+    }
+
     #[inline(always)]
     fn skip_whitespace(&mut self) {
-        while !self.at_end() {
-            if self.input[self.index].is_whitespace() {
-                match self.input[self.index] {
-                    '\n' => {
-                        self.line += 1;
-                        self.column = 0;
-                    }, 
-                    _ => {
-                        self.column += 1
-                    }
-                };
-                self.index += 1;
-            } else {
-                break;
+        while let Some(b) = self.byte_at(0) {
+            match b {
+                b' ' | b'\t' | b'\n' | b'\r' => self.offset += 1,
+                b if b >= 0x80 && self.char_at_offset().is_whitespace() => self.advance(),
+                _ => break
             }
         }
     }
-    
+
     #[inline(always)]
-    fn tokenize_single_char(&mut self, kind: TokenKind) -> Option<Token> {
-        let token = Token::new(self.line, self.column, kind);
-        self.advance();
-        return Some(token);
-    }
-    
-    pub fn peek_token(&mut self) -> Option<Token> {
-        let index = self.index;
-        let line = self.line;
-        let column = self.column;
-        let token = self.next_token();
-        // Reset position
-        self.index = index;
-        self.line = line;
-        self.column = column;
-        return token;
-    }
-
-    pub fn peek_second_token(&mut self) -> Option<Token> {
-        let index = self.index;
-        let line = self.line;
-        let column = self.column;
-        self.next_token();
-        let token = self.next_token();
-        self.index = index;
-        self.line = line;
-        self.column = column;
-        return token;
+    fn tokenize_single_char(&mut self, kind: TokenKind<'a>) -> Token<'a> {
+        let start = self.offset;
+        self.offset += 1;
+        Token::new(self.span_from(start), kind)
     }
-    
-    pub fn next_token(&mut self) -> Option<Token> {
+
+    pub fn next_token(&mut self) -> Result<Option<Token<'a>>, SyntaxError> {
         self.skip_whitespace();
-        
+
         if self.at_end() {
-            return None;
-        }
-        
-        let c = self.input[self.index];
-        
-        match c {
-            c if c.is_ascii_alphabetic() || c == '_' => {
-                let line = self.line;
-                let column = self.column;
-
-                let mut value = String::new();
-                while !self.at_end() && (self.input[self.index].is_ascii_alphanumeric() || self.input[self.index] == '_') {
-                    value.push(self.input[self.index]);
-                    self.advance();
+            return Ok(None);
+        }
+
+        let b = self.bytes[self.offset];
+
+        let token = match b {
+            b'r' if self.is_raw_string_start() => self.tokenize_raw_string()?,
+            b if b.is_ascii_alphabetic() || b == b'_' => self.tokenize_identifier()?,
+            b if b >= 0x80 && self.char_at_offset().is_xid_start() => self.tokenize_identifier()?,
+            b'"' | b'\'' => self.tokenize_string(b)?,
+            b if b.is_ascii_digit() => self.tokenize_integer()?,
+            b'(' => self.tokenize_single_char(TokenKind::OpenParen),
+            b')' => self.tokenize_single_char(TokenKind::CloseParen),
+            b'{' => self.tokenize_single_char(TokenKind::OpenCurly),
+            b'}' => self.tokenize_single_char(TokenKind::CloseCurly),
+            b',' => self.tokenize_single_char(TokenKind::Comma),
+            b';' => self.tokenize_single_char(TokenKind::Semicolon),
+            b':' if self.byte_at(1) == Some(b':') => {
+                let start = self.offset;
+                self.offset += 2;
+                Token::new(self.span_from(start), TokenKind::ColonColon)
+            },
+            b':' => self.tokenize_single_char(TokenKind::Colon),
+            _ => {
+                let c = self.char_at_offset();
+                let span = Span::new(self.offset, self.offset + c.len_utf8());
+                if is_bidi_control(c) {
+                    return Err(SyntaxError::BidiControlCharacter(span, c));
                 }
-                return match value.as_str() {
-                    "func" => Some(Token::new(line, column, TokenKind::Keyword(Keyword::Func))),
-                    "impure" => Some(Token::new(line, column, TokenKind::Keyword(Keyword::Impure))),
-                    _ => Some(Token::new(line, column, TokenKind::Identifier(value)))
-                };
+                return Err(SyntaxError::UnknownStartOfToken(span, c));
+            }
+        };
+
+        Ok(Some(token))
+    }
+
+    // Identifiers start with an XID_Start character or an underscore, followed by
+    // any number of XID_Continue characters, as described in UAX #31.
+    fn tokenize_identifier(&mut self) -> Result<Token<'a>, SyntaxError> {
+        let start = self.offset;
+        let mut ascii = true;
+
+        while let Some(b) = self.byte_at(0) {
+            if b.is_ascii_alphanumeric() || b == b'_' {
+                self.offset += 1;
+                continue;
+            }
+            if b < 0x80 {
+                break;
+            }
+
+            let c = self.char_at_offset();
+            let span = Span::new(self.offset, self.offset + c.len_utf8());
+            if is_bidi_control(c) {
+                return Err(SyntaxError::BidiControlCharacter(span, c));
+            }
+            if !c.is_xid_continue() {
+                break;
+            }
+            if !c.identifier_allowed() {
+                return Err(SyntaxError::DisallowedIdentifierCharacter(span, c));
+            }
+            ascii = false;
+            self.advance();
+        }
+
+        let value = &self.input[start..self.offset];
+        let span = self.span_from(start);
+
+        if ascii {
+            let kind = match keyword(value) {
+                Some(keyword) => TokenKind::Keyword(keyword),
+                None => TokenKind::Identifier(Cow::Borrowed(value))
+            };
+            return Ok(Token::new(span, kind));
+        }
+
+        // Mixing scripts allows identifiers that look identical to
+        // others, such as a Cyrillic `а` in place of a Latin `a`
+        if !value.is_single_script() {
+            return Err(SyntaxError::MixedScriptIdentifier(span, value.to_string()));
+        }
+
+        let value = if is_nfc(value) {
+            Cow::Borrowed(value)
+        } else {
+            Cow::Owned(value.nfc().collect::<String>())
+        };
+
+        Ok(Token::new(span, TokenKind::Identifier(value)))
+    }
+
+    fn is_raw_string_start(&self) -> bool {
+        let mut offset = 1;
+        while self.byte_at(offset) == Some(b'#') {
+            offset += 1;
+        }
+        matches!(self.byte_at(offset), Some(b'"') | Some(b'\''))
+    }
+
+    // Raw strings are written as r"..." and may be wrapped in any number of
+    // hashes (r#"..."#) so that they can contain their own quote character.
+    // No escape sequences are processed inside of them.
+    fn tokenize_raw_string(&mut self) -> Result<Token<'a>, SyntaxError> {
+        let start = self.offset;
+
+        self.offset += 1;
+        let mut hashes = 0;
+        while self.byte_at(0) == Some(b'#') {
+            hashes += 1;
+            self.offset += 1;
+        }
+        let quote = self.bytes[self.offset];
+        self.offset += 1;
+
+        let value_start = self.offset;
+        loop {
+            match self.byte_at(0) {
+                Some(b) if b == quote && (1..=hashes).all(|offset| self.byte_at(offset) == Some(b'#')) => {
+                    let value = &self.input[value_start..self.offset];
+                    self.offset += 1 + hashes;
+                    return Ok(Token::new(self.span_from(start), TokenKind::StringLiteral(Cow::Borrowed(value))));
+                },
+                Some(_) => self.offset += 1,
+                None => return Err(SyntaxError::UnterminatedString(Span::new(start, start + 1)))
+            }
+        }
+    }
+
+    fn tokenize_integer(&mut self) -> Result<Token<'a>, SyntaxError> {
+        let start = self.offset;
+
+        let mut value: i64 = 0;
+        let mut overflowed = false;
+        while let Some(b) = self.byte_at(0) {
+            if b.is_ascii_digit() {
+                match value.checked_mul(10).and_then(|v| v.checked_add((b - b'0') as i64)) {
+                    Some(v) => value = v,
+                    None => overflowed = true
+                }
+            } else if b != b'_' {
+                break;
+            }
+            self.offset += 1;
+        }
+
+        if overflowed {
+            return Err(SyntaxError::IntegerTooLarge(self.span_from(start)));
+        }
+
+        Ok(Token::new(self.span_from(start), TokenKind::IntegerLiteral(value)))
+    }
+
+    // Finishes a literal part of a string that started at `piece_start`,
+    // borrowing it from the source if it didn't contain any escapes.
+    fn finish_literal(&self, owned: Option<String>, piece_start: usize, piece_end: usize) -> Cow<'a, str> {
+        let rest = &self.input[piece_start..piece_end];
+        match owned {
+            Some(mut value) => {
+                value.push_str(rest);
+                Cow::Owned(value)
             },
-            '"' | '\'' => {
-                let line = self.line;
-                let column = self.column;
-
-                let mut value = String::new();
-                self.advance();
-                while !self.at_end() {
-                    let string_character = self.input[self.index];
-                    if string_character == c {
-                        self.advance();
-                        break;
+            None => Cow::Borrowed(rest)
+        }
+    }
+
+    fn tokenize_string(&mut self, quote: u8) -> Result<Token<'a>, SyntaxError> {
+        let start = self.offset;
+
+        let mut segments = Vec::<StringSegment<'a>>::new();
+        // Only allocated once an escape sequence is found
+        let mut owned: Option<String> = None;
+        self.offset += 1;
+        let mut piece_start = self.offset;
+
+        loop {
+            match self.byte_at(0) {
+                Some(b) if b == quote => break,
+                Some(b'\\') => {
+                    let value = owned.get_or_insert_with(String::new);
+                    value.push_str(&self.input[piece_start..self.offset]);
+                    self.tokenize_escape_sequence(value)?;
+                    piece_start = self.offset;
+                },
+                Some(b @ b'{') | Some(b @ b'}') if self.byte_at(1) == Some(b) => {
+                    let value = owned.get_or_insert_with(String::new);
+                    value.push_str(&self.input[piece_start..self.offset + 1]);
+                    self.offset += 2;
+                    piece_start = self.offset;
+                },
+                Some(b'{') => {
+                    if owned.is_some() || piece_start != self.offset {
+                        segments.push(StringSegment::Literal(self.finish_literal(owned.take(), piece_start, self.offset)));
                     }
-                    value.push(string_character);
-                    self.advance();
+                    let segment = self.tokenize_interpolation()?;
+                    segments.push(segment);
+                    piece_start = self.offset;
+                },
+                Some(b'}') => return Err(SyntaxError::UnmatchedCloseCurly(Span::new(self.offset, self.offset + 1))),
+                Some(_) => self.offset += 1,
+                None => return Err(SyntaxError::UnterminatedString(Span::new(start, start + 1)))
+            }
+        }
+
+        let has_rest = owned.is_some() || piece_start != self.offset;
+        let value = self.finish_literal(owned, piece_start, self.offset);
+        self.offset += 1;
+
+        if segments.is_empty() {
+            return Ok(Token::new(self.span_from(start), TokenKind::StringLiteral(value)));
+        }
+
+        if has_rest {
+            segments.push(StringSegment::Literal(value));
+        }
+
+        Ok(Token::new(self.span_from(start), TokenKind::InterpolatedString(segments)))
+    }
+
+    // Finds the end of an embedded `{expression:spec}` without lexing it,
+    // the parser lexes the expression separately once the string is complete.
+    fn tokenize_interpolation(&mut self) -> Result<StringSegment<'a>, SyntaxError> {
+        let start = self.offset;
+        self.offset += 1;
+
+        let expression_start = self.offset;
+        let mut expression_end: Option<usize> = None;
+        // Brackets opened within the expression, which have to be closed before it ends
+        let mut open = Vec::<u8>::new();
+
+        loop {
+            let b = match self.byte_at(0) {
+                Some(b) => b,
+                None => return Err(SyntaxError::UnterminatedInterpolation(Span::new(start, start + 1)))
+            };
+
+            if expression_end.is_some() {
+                self.offset += 1;
+                if b == b'}' {
+                    break;
                 }
-                
-                return Some(Token::new(line, column, TokenKind::StringLiteral(value)));
-            },
-            '(' => return self.tokenize_single_char(TokenKind::OpenParen),
-            ')' => return self.tokenize_single_char(TokenKind::CloseParen),
-            '{' => return self.tokenize_single_char(TokenKind::OpenCurly),
-            '}' => return self.tokenize_single_char(TokenKind::CloseCurly),
-            ',' => return self.tokenize_single_char(TokenKind::Comma),
-            ';' => return self.tokenize_single_char(TokenKind::Semicolon), 
-            c => {
-                let error = SyntaxError::UnknownStartOfToken(ErrorLocation { line: self.line, column: self.column }, c);
-                
-                println!("{error}");
-                
-                std::process::exit(1);
+                continue;
+            }
+
+            match b {
+                b'}' if open.is_empty() => {
+                    expression_end = Some(self.offset);
+                    self.offset += 1;
+                    break;
+                },
+                b':' if open.is_empty() && self.byte_at(1) != Some(b':') && self.bytes[self.offset - 1] != b':' => {
+                    expression_end = Some(self.offset);
+                    self.offset += 1;
+                },
+                b'"' | b'\'' => {
+                    // Strings nested inside of the expression are skipped over,
+                    // so that their quotes and braces don't end the interpolation.
+                    self.offset += 1;
+                    while let Some(n) = self.byte_at(0) {
+                        self.offset += 1;
+                        if n == b'\\' {
+                            if self.byte_at(0).is_some() {
+                                self.offset += 1;
+                            }
+                        } else if n == b {
+                            break;
+                        }
+                    }
+                },
+                b'(' | b'{' => {
+                    open.push(b);
+                    self.offset += 1;
+                },
+                b')' | b'}' => {
+                    let expected = if b == b')' { b'(' } else { b'{' };
+                    if open.pop() != Some(expected) {
+                        return Err(SyntaxError::UnbalancedInterpolation(Span::new(self.offset, self.offset + 1), b as char));
+                    }
+                    self.offset += 1;
+                },
+                _ => self.offset += 1
             }
+        }
+
+        let expression_end = expression_end.expect("Should have found the end of the expression");
+        let span = Span::new(expression_start, expression_end);
+
+        if self.input[expression_start..expression_end].trim().is_empty() {
+            return Err(SyntaxError::EmptyInterpolation(self.span_from(start)));
+        }
+
+        // The specifier is everything between the colon and the closing brace
+        let format = if expression_end + 1 < self.offset {
+            let spec = &self.input[expression_end + 1..self.offset - 1];
+            match parse_format_spec(spec) {
+                Some(format) => format,
+                None => return Err(SyntaxError::InvalidFormatSpec(Span::new(expression_end + 1, self.offset - 1), spec.to_string()))
+            }
+        } else {
+            FormatSpec::default()
+        };
+
+        Ok(StringSegment::Expression { span, format })
+    }
+
+    fn tokenize_escape_sequence(&mut self, value: &mut String) -> Result<(), SyntaxError> {
+        let start = self.offset;
+
+        // Skip the backslash
+        self.offset += 1;
+
+        let b = match self.byte_at(0) {
+            Some(b) => b,
+            None => return Ok(())
         };
+        self.advance();
+
+        match b {
+            b'n' => value.push('\n'),
+            b't' => value.push('\t'),
+            b'r' => value.push('\r'),
+            b'0' => value.push('\0'),
+            b'\\' => value.push('\\'),
+            b'"' => value.push('"'),
+            b'\'' => value.push('\''),
+            b'u' => value.push(self.tokenize_unicode_escape(start)?),
+            b'\n' | b'\r' => {
+                // A backslash at the end of a line continues the string on
+                // the next line, without the line break or leading whitespace.
+                self.skip_whitespace();
+            },
+            _ => {
+                let c = self.input[start + 1..].chars().next().expect("Should be a character after the backslash");
+                return Err(SyntaxError::UnknownEscapeSequence(self.span_from(start), c));
+            }
+        }
+
+        Ok(())
     }
+
+    fn tokenize_unicode_escape(&mut self, start: usize) -> Result<char, SyntaxError> {
+        if self.byte_at(0) != Some(b'{') {
+            return Err(SyntaxError::InvalidUnicodeEscape(self.span_from(start)));
+        }
+        self.offset += 1;
+
+        let digits_start = self.offset;
+        while let Some(b) = self.byte_at(0) {
+            if b == b'}' {
+                break;
+            }
+            if !b.is_ascii_hexdigit() || self.offset - digits_start == 6 {
+                return Err(SyntaxError::InvalidUnicodeEscape(self.span_from(start)));
+            }
+            self.offset += 1;
+        }
+
+        let digits = &self.input[digits_start..self.offset];
+        if self.byte_at(0) != Some(b'}') || digits.is_empty() {
+            return Err(SyntaxError::InvalidUnicodeEscape(self.span_from(start)));
+        }
+        self.offset += 1;
+
+        u32::from_str_radix(digits, 16).ok()
+            .and_then(char::from_u32)
+            .ok_or(SyntaxError::InvalidUnicodeEscape(self.span_from(start)))
+    }
+}
+
+// Stops after the first error, since the rest of the source can't be lexed reliably
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Result<Token<'a>, SyntaxError>;
+
+    fn next(&mut self) -> Option<Result<Token<'a>, SyntaxError>> {
+        let token = self.next_token();
+        if token.is_err() {
+            self.offset = self.end;
+        }
+        token.transpose()
+    }
+}
+
+pub fn keyword(name: &str) -> Option<Keyword> {
+    match name {
+        "extern" => Some(Keyword::Extern),
+        "func" => Some(Keyword::Func),
+        "impure" => Some(Keyword::Impure),
+        "import" => Some(Keyword::Import),
+        "return" => Some(Keyword::Return),
+        _ => None
+    }
+}
+
+// Whether `name` would be lexed as a single identifier
+pub fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    let start = chars.next().is_some_and(|c| c == '_' || c.is_xid_start());
+    start && chars.all(UnicodeXID::is_xid_continue) && keyword(name).is_none()
+}
+
+// Characters that change the direction of text, which can make
+// source appear differently to how it is interpreted
+fn is_bidi_control(c: char) -> bool {
+    matches!(c, '\u{061C}' | '\u{200E}' | '\u{200F}' | '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}')
+}
+
+// Format specifiers follow the form `[align][width][.precision]`,
+// where align is one of `<`, `>` or `^`.
+fn parse_format_spec(spec: &str) -> Option<FormatSpec> {
+    let mut format = FormatSpec::default();
+    let mut rest = spec;
+    
+    format.align = match rest.chars().next() {
+        Some('<') => Alignment::Left,
+        Some('>') => Alignment::Right,
+        Some('^') => Alignment::Center,
+        _ => Alignment::Default
+    };
+    if format.align != Alignment::Default {
+        rest = &rest[1..];
+    }
+    
+    let (width, precision) = match rest.split_once('.') {
+        Some((width, precision)) => (width, Some(precision)),
+        None => (rest, None)
+    };
+    
+    if !width.is_empty() {
+        format.width = Some(width.parse::<u16>().ok()?);
+    }
+    if let Some(precision) = precision {
+        format.precision = Some(precision.parse::<u16>().ok()?);
+    }
+    
+    Some(format)
 }
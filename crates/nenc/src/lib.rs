@@ -1,33 +1,152 @@
+extern crate unicode_normalization;
+extern crate unicode_security;
+extern crate unicode_width;
+extern crate unicode_xid;
+
 mod builtin;
 mod codegen;
 mod error;
 mod ir;
-mod lexer;
+pub mod lexer;
+mod module;
 mod parser;
+pub mod source;
+mod types;
 
-use std::{io::{ Read, Write }, fs::File};
+use std::{io::{ Read, Write }, fs::{ self, File }, path::{ Path, PathBuf }, collections::HashMap};
 
+use builtin::{ PRELUDE, PRELUDE_NAME };
+use error::{ render, render_warning };
+use module::ModuleLoader;
 use parser::Parser;
-use ir::IR;
+use source::SourceFile;
+use ir::{ IR, ModuleExports };
 use codegen::ir_bytecode;
 
-// TODO
-pub struct CompilerOptions {}
+pub use builtin::native_builtins;
+pub use error::{ CompileError, CompileWarnings };
+
+pub struct CompilerOptions {
+    // Where the compiled bytecode is written to
+    pub output: PathBuf,
+    // Directories searched for `import name;` when there is
+    // no `name.nen` next to the importing file
+    pub search_paths: Vec<PathBuf>,
+    // Packages that can be imported by name
+    pub packages: HashMap<String, Package>,
+    // Whether to include debug info, so that runtime errors can show where they happened
+    pub debug_info: bool
+}
+
+#[derive(Debug)]
+pub struct Package {
+    // The file that `import name;` refers to
+    pub entry: PathBuf,
+    // Directories searched for imports made by the package's own files
+    pub search_paths: Vec<PathBuf>
+}
+
+impl Default for CompilerOptions {
+    fn default() -> Self {
+        CompilerOptions {
+            output: PathBuf::from("out.nenc"),
+            search_paths: Vec::<PathBuf>::new(),
+            packages: HashMap::<String, Package>::new(),
+            debug_info: false
+        }
+    }
+}
 
-pub fn compile(mut readable: impl Read, _options: CompilerOptions) {
+// Compiles source read from `readable`, with any
+// imports resolved relative to the working directory
+pub fn compile(mut readable: impl Read, options: CompilerOptions) -> Result<CompileWarnings, CompileError> {
     // TODO: Chunking 
     let mut src = String::new();
-    readable.read_to_string(&mut src).unwrap();
-    
-    let mut parser = Parser::new(&src);
-    let program = parser.parse_program();
+    if let Err(e) = readable.read_to_string(&mut src) {
+        return Err(CompileError::message(format!("Could not read the program: {}.", e)));
+    }
     
-//    println!("Program {program:#?}");
-    
-    let intermediate = IR::from(program);
+    let mut loader = ModuleLoader::new(&options);
+    loader.load_entry("<input>", src, Path::new(""), PathBuf::from("<input>"))?;
 
-    let bytecode = ir_bytecode(intermediate);
-    
-    let mut file = File::create("out.nenc").expect("couldn't create");
-    file.write(&bytecode).expect("couldn't write");
+    compile_modules(loader, &options)
+}
+
+// Compiles the file at `path`, along with every file it imports
+pub fn compile_file(path: &Path, options: CompilerOptions) -> Result<CompileWarnings, CompileError> {
+    let src = match fs::read_to_string(path) {
+        Ok(s) => s,
+        Err(e) => return Err(CompileError::message(format!("Could not read file '{}': {}.", path.display(), e)))
+    };
+    let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let directory = path.parent().unwrap_or(Path::new(""));
+
+    let mut loader = ModuleLoader::new(&options);
+    loader.load_entry(&path.display().to_string(), src, directory, canonical)?;
+
+    compile_modules(loader, &options)
+}
+
+fn compile_modules(loader: ModuleLoader, options: &CompilerOptions) -> Result<CompileWarnings, CompileError> {
+    let mut modules = Vec::<IR>::new();
+    let mut exports = Vec::<ModuleExports>::new();
+    // In the same order as `modules`, for the debug info
+    let mut sources = Vec::<SourceFile>::new();
+    let mut warnings = Vec::<String>::new();
+    let mut errors = Vec::<String>::new();
+
+    // The prelude is compiled first, so every other module can call its functions
+    let prelude_source = SourceFile::new(format!("<{}>", PRELUDE_NAME), PRELUDE.to_string());
+    let prelude_program = Parser::new(&prelude_source).parse_program().map_err(|e| CompileError::new(&prelude_source, &e))?;
+    let prelude = IR::from_module(prelude_program, format!("{}::", PRELUDE_NAME), HashMap::<String, ModuleExports>::new(), None);
+
+    for error in &prelude.errors {
+        errors.push(render(&prelude_source, error));
+    }
+
+    let prelude_exports = prelude.exports();
+    modules.push(prelude);
+    sources.push(prelude_source);
+
+    // The entry file is always loaded last, and its functions are left unqualified
+    let entry = loader.modules.len() - 1;
+
+    for (index, module) in loader.modules.into_iter().enumerate() {
+        let imports = module.imports.iter()
+            .map(|(name, i)| (name.to_string(), exports[*i].clone()))
+            .collect::<HashMap<String, ModuleExports>>();
+        let prefix = if index == entry { String::new() } else { format!("{}::", module.name) };
+
+        let intermediate = IR::from_module(module.program, prefix, imports, Some(prelude_exports.clone()));
+
+        for warning in &intermediate.warnings {
+            warnings.push(render_warning(&module.source, warning));
+        }
+
+        for error in &intermediate.errors {
+            errors.push(render(&module.source, error));
+        }
+
+        exports.push(intermediate.exports());
+        modules.push(intermediate);
+        sources.push(module.source);
+    }
+
+    if !errors.is_empty() {
+        return Err(CompileError { warnings, errors });
+    }
+
+    let mut intermediate = IR::link(modules);
+    intermediate.remove_unused(&format!("{}::", PRELUDE_NAME));
+
+    let written = ir_bytecode(intermediate, &sources, options.debug_info).and_then(|bytecode| {
+        File::create(&options.output)
+            .and_then(|mut file| file.write_all(&bytecode))
+            .map_err(|e| CompileError::message(format!("Could not write file '{}': {}.", options.output.display(), e)))
+    });
+
+    match written {
+        Ok(()) => Ok(CompileWarnings { warnings }),
+        Err(error) => Err(CompileError { warnings, ..error })
+    }
 }
@@ -0,0 +1,176 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{ Path, PathBuf };
+
+use CompilerOptions;
+use builtin::PRELUDE_NAME;
+use error::{ CompileError, SemanticError };
+use lexer::is_identifier;
+use parser::{ ImportPath, NodeKind, Parser, Program };
+use source::{ SourceFile, Span };
+
+pub struct Module {
+    // Functions of the module are qualified with its name, such as `util::greet`
+    pub name: String,
+    pub source: SourceFile,
+    pub program: Program,
+    // Modules imported by this one, by the name they are imported as
+    pub imports: HashMap<String, usize>
+}
+
+// Loads a file and every file it imports, with the modules ordered
+// so that each module comes after all of the modules it imports.
+pub struct ModuleLoader<'a> {
+    pub modules: Vec<Module>,
+    options: &'a CompilerOptions,
+    by_path: HashMap<PathBuf, usize>,
+    by_name: HashMap<String, PathBuf>,
+    // Files that are currently being loaded, used to detect cycles
+    loading: Vec<(PathBuf, String)>
+}
+
+impl<'a> ModuleLoader<'a> {
+    pub fn new(options: &'a CompilerOptions) -> ModuleLoader<'a> {
+        ModuleLoader {
+            modules: Vec::<Module>::new(),
+            options,
+            by_path: HashMap::<PathBuf, usize>::new(),
+            by_name: HashMap::<String, PathBuf>::new(),
+            loading: Vec::<(PathBuf, String)>::new()
+        }
+    }
+
+    // Imports in the entry file are resolved relative to `directory`
+    pub fn load_entry(&mut self, name: &str, text: String, directory: &Path, canonical: PathBuf) -> Result<(), CompileError> {
+        let source = SourceFile::new(name.to_string(), text);
+        self.load(String::new(), source, directory, &self.options.search_paths, canonical)?;
+        Ok(())
+    }
+
+    // Imports made by the file are searched for in `search_paths`,
+    // which are those of the package the file belongs to
+    fn load(&mut self, name: String, source: SourceFile, directory: &Path, search_paths: &'a [PathBuf], canonical: PathBuf) -> Result<usize, CompileError> {
+        let program = Parser::new(&source).parse_program().map_err(|e| CompileError::new(&source, &e))?;
+
+        self.loading.push((canonical.clone(), source.name.clone()));
+
+        let mut imports = HashMap::<String, usize>::new();
+        for node in &program {
+            if let NodeKind::Import(import) = &node.kind {
+                let (alias, index) = self.load_import(&source, import, directory, search_paths, node.span)?;
+
+                // Importing the same module more than once has no effect
+                match imports.insert(alias.clone(), index) {
+                    Some(previous) if previous != index => {
+                        return Err(CompileError::new(&source, &SemanticError::ModuleNameConflict(node.span, alias)));
+                    },
+                    _ => {}
+                }
+            }
+        }
+
+        self.loading.pop();
+
+        self.modules.push(Module {
+            name,
+            source,
+            program,
+            imports
+        });
+
+        let index = self.modules.len() - 1;
+        self.by_path.insert(canonical, index);
+        Ok(index)
+    }
+
+    // `import util;` refers to `util.nen` next to the importing file,
+    // then `util.nen` in any of the search paths, then the `util` package.
+    // Returns the search paths of the package the module belongs to.
+    fn find_module(&self, name: &str, directory: &Path, search_paths: &'a [PathBuf]) -> (PathBuf, &'a [PathBuf]) {
+        let file = format!("{}.nen", name);
+        let local = directory.join(&file);
+
+        let candidates = std::iter::once(local.clone())
+            .chain(search_paths.iter().map(|p| p.join(&file)));
+
+        for candidate in candidates {
+            if candidate.is_file() {
+                return (candidate, search_paths);
+            }
+        }
+
+        let options: &'a CompilerOptions = self.options;
+        match options.packages.get(name) {
+            Some(package) if package.entry.is_file() => (package.entry.clone(), &package.search_paths),
+            _ => (local, search_paths)
+        }
+    }
+
+    fn load_import(&mut self, source: &SourceFile, import: &ImportPath, directory: &Path, search_paths: &'a [PathBuf], span: Span) -> Result<(String, usize), CompileError> {
+        let error = |error: SemanticError| Err(CompileError::new(source, &error));
+
+        let (name, path, search_paths) = match import {
+            ImportPath::Module(name) => {
+                let (path, search_paths) = self.find_module(name, directory, search_paths);
+                (name.to_string(), path, search_paths)
+            },
+            ImportPath::File(path) => {
+                let path = directory.join(path);
+                match path.file_stem() {
+                    Some(stem) => (stem.to_string_lossy().to_string(), path, search_paths),
+                    None => return error(SemanticError::ModuleNotFound(span, path.display().to_string(), "Not a file".to_string()))
+                }
+            }
+        };
+
+        // `std` always refers to the built-ins and the prelude
+        if name == PRELUDE_NAME {
+            return error(SemanticError::ModuleNameConflict(span, name));
+        }
+
+        // Functions of the module are called as `name::function`
+        if !is_identifier(&name) {
+            return error(SemanticError::InvalidModuleName(span, name));
+        }
+
+        let canonical = match fs::canonicalize(&path) {
+            Ok(p) => p,
+            Err(e) => return error(SemanticError::ModuleNotFound(span, path.display().to_string(), e.to_string()))
+        };
+
+        if let Some(position) = self.loading.iter().position(|(p, _)| *p == canonical) {
+            let cycle = self.loading[position..].iter()
+                .map(|(_, name)| name.as_str())
+                .chain(std::iter::once(self.loading[position].1.as_str()))
+                .collect::<Vec<&str>>()
+                .join(" -> ");
+            return error(SemanticError::ImportCycle(span, cycle));
+        }
+
+        if let Some(index) = self.by_path.get(&canonical) {
+            return Ok((name, *index));
+        }
+
+        // Functions are qualified with the module name once everything
+        // is merged together, so each name can only refer to one file
+        match self.by_name.get(&name) {
+            Some(existing) if *existing != canonical => {
+                return error(SemanticError::ModuleNameConflict(span, name));
+            },
+            _ => {
+                self.by_name.insert(name.clone(), canonical.clone());
+            }
+        }
+
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) => return error(SemanticError::ModuleNotFound(span, path.display().to_string(), e.to_string()))
+        };
+
+        let module_directory = path.parent().map_or(PathBuf::new(), Path::to_path_buf);
+        let module_source = SourceFile::new(path.display().to_string(), text);
+        let index = self.load(name.clone(), module_source, &module_directory, search_paths, canonical)?;
+
+        Ok((name, index))
+    }
+}
@@ -1,215 +1,452 @@
+use std::borrow::Cow;
 use std::mem::discriminant;
 
-use lexer::{Lexer, Token, TokenKind, Keyword};
+use error::SyntaxError;
+use lexer::{Lexer, Lookahead, Token, TokenKind, Keyword, StringSegment, FormatSpec};
+use source::{ SourceFile, Span };
 
-#[derive(Debug)]
-pub struct Parser {
-    lexer: Lexer
+// The most tokens the parser needs to look ahead by
+const LOOKAHEAD: usize = 2;
+
+pub struct Parser<'a> {
+    tokens: Lookahead<'a, LOOKAHEAD>
 }
 
 
 pub type Program = Vec<Node>;
 
 #[derive(Debug)]
-pub enum Node {
-    FunctionDefinition { name: String, contents: Block, impure: bool }
+pub struct Node {
+    pub kind: NodeKind,
+    pub span: Span
+}
+
+#[allow(dead_code)]
+#[derive(Debug)]
+pub enum NodeKind {
+    FunctionDefinition { name: String, name_span: Span, parameters: Vec<Parameter>, returns: Option<TypeName>, contents: Block, impure: bool },
+    // A function implemented by the program embedding the VM
+    // extern impure func log(message: string);
+    ExternFunction { name: String, name_span: Span, parameters: Vec<Parameter>, returns: Option<TypeName>, impure: bool },
+    Import(ImportPath)
+}
+
+#[derive(Debug, Clone)]
+pub enum ImportPath {
+    // import util;
+    Module(String),
+    // import "path/to/util.nen";
+    File(String)
+}
+
+// A function parameter, written as `name: type`
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct Parameter {
+    pub name: String,
+    pub type_name: String,
+    pub span: Span
+}
+
+#[derive(Debug, Clone)]
+pub struct TypeName {
+    pub name: String,
+    pub span: Span
 }
 
 pub type Block = Vec<Statement>;
 
 #[derive(Debug, Clone)]
-pub enum Expr {
-    FunctionCall { name: String, arguments: Vec<Expr> },
-    StringLiteral(String)
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span
+}
+
+#[derive(Debug, Clone)]
+pub enum ExprKind {
+    // `module` is set for qualified calls, such as `util::greet()`
+    FunctionCall { module: Option<String>, name: String, arguments: Vec<Expr> },
+    StringLiteral(String),
+    IntegerLiteral(i64),
+    // A parameter of the enclosing function
+    Variable(String),
+    // Converts the value of an expression into a string
+    ToString { value: Box<Expr>, format: FormatSpec },
+    // Joins the string values of all the expressions together
+    Concat(Vec<Expr>)
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct Statement {
+    pub kind: StatementKind,
+    pub span: Span
 }
 
 #[derive(Debug, Clone)]
-pub enum Statement {
-    Expr(Expr)
+pub enum StatementKind {
+    Expr(Expr),
+    // `return;` or `return value;`
+    Return(Option<Expr>)
+}
+
+impl Expr {
+    pub fn new(kind: ExprKind, span: Span) -> Expr {
+        Expr { kind, span }
+    }
 }
 
-impl Parser {
-    pub fn new(input: &str) -> Parser {
+impl<'a> Parser<'a> {
+    pub fn new(source: &'a SourceFile) -> Parser<'a> {
         Parser {
-            lexer: Lexer::new(input)
+            tokens: Lookahead::new(Lexer::new(source))
         }
     }
-    
-    #[inline(always)]
-    fn expect_token(&mut self, token: TokenKind, consume: bool) -> Token {
-        let next = if consume { self.lexer.next_token() } else { self.lexer.peek_token() };
-        if let Some(n) = next {
-            if discriminant(&n.kind) == discriminant(&token) {
-                return n;
+
+    fn embedded(source: &'a SourceFile, range: Span) -> Parser<'a> {
+        Parser {
+            tokens: Lookahead::new(Lexer::with_range(source, range))
+        }
+    }
+
+    fn unexpected(&self, token: Option<Token<'a>>, expected: &str) -> SyntaxError {
+        match token {
+            Some(t) => SyntaxError::UnexpectedToken {
+                span: t.span,
+                expected: expected.to_string(),
+                found: t.kind.to_string()
+            },
+            None => SyntaxError::UnexpectedEndOfFile {
+                span: self.tokens.lexer().end_span(),
+                expected: expected.to_string()
             }
         }
-        
-        todo!("Handle unexpected token");
     }
-    
-    fn parse_statement(&mut self) -> Option<Statement> {
-        match self.lexer.peek_token() {
+
+    #[inline(always)]
+    fn expect_token(&mut self, token: TokenKind) -> Result<Token<'a>, SyntaxError> {
+        match self.tokens.next_token()? {
+            Some(n) if discriminant(&n.kind) == discriminant(&token) => Ok(n),
+            n => Err(self.unexpected(n, token.describe()))
+        }
+    }
+
+    fn parse_statement(&mut self) -> Result<Option<Statement>, SyntaxError> {
+        match self.tokens.peek(0)? {
             Some(token) => {
                 match token.kind {
+                    TokenKind::Keyword(Keyword::Return) => {
+                        let keyword = self.tokens.next_token()?.expect("Token was peeked");
+                        match self.tokens.peek(0)? {
+                            Some(Token { kind: TokenKind::Semicolon, .. }) | None => {
+                                Ok(Some(Statement { kind: StatementKind::Return(None), span: keyword.span }))
+                            },
+                            _ => {
+                                let value = self.parse_expr()?.expect("Token was peeked");
+                                let span = keyword.span.to(value.span);
+                                Ok(Some(Statement { kind: StatementKind::Return(Some(value)), span }))
+                            }
+                        }
+                    },
                     TokenKind::Identifier(_) => {
-                        if let Some(t) = self.lexer.peek_second_token() {
-                            match t.kind {
-                                TokenKind::OpenParen => {
-                                    let function_call = self.parse_expr().expect("Unreachable, I think");
+                        match self.tokens.peek(1)? {
+                            Some(Token { kind: TokenKind::OpenParen, .. }) |
+                            Some(Token { kind: TokenKind::ColonColon, .. }) => {
+                                let function_call = self.parse_expr()?.expect("Unreachable, I think");
+                                let span = function_call.span;
 
-                                    return Some(Statement::Expr(function_call));
-                                },
-                                _ => todo!()
+                                Ok(Some(Statement { kind: StatementKind::Expr(function_call), span }))
+                            },
+                            _ => {
+                                self.tokens.next_token()?;
+                                let t = self.tokens.next_token()?;
+                                Err(self.unexpected(t, "`(`"))
                             }
-                        } else {
-                            todo!()
                         }
                     },
-                    t => todo!("Unexpected token {t:?} in parse_statement"),
+                    _ => {
+                        let t = self.tokens.next_token()?;
+                        Err(self.unexpected(t, "statement"))
+                    }
                 }
             }
-            None => None
+            None => Ok(None)
         }
     }
-    
-    fn parse_expr(&mut self) -> Option<Expr> {
-        // Only function calls for now
-        match self.lexer.next_token() {
+
+    fn parse_expr(&mut self) -> Result<Option<Expr>, SyntaxError> {
+        match self.tokens.next_token()? {
             Some(token) => {
+                let span = token.span;
                 match token.kind {
                     TokenKind::StringLiteral(s) => {
-                        Some(Expr::StringLiteral(s))
+                        Ok(Some(Expr::new(ExprKind::StringLiteral(s.into_owned()), span)))
+                    },
+                    TokenKind::IntegerLiteral(i) => {
+                        Ok(Some(Expr::new(ExprKind::IntegerLiteral(i), span)))
+                    },
+                    TokenKind::InterpolatedString(segments) => {
+                        Ok(Some(self.parse_interpolated_string(segments, span)?))
                     },
                     TokenKind::Identifier(s) => {
-                        match self.lexer.peek_token() {
-                            Some(peeked_token) => {
-                                match peeked_token.kind {
-                                    TokenKind::OpenParen => {
-                                        self.lexer.next_token();
-                                        let mut args = Vec::<Expr>::new();
-                                        while let Some(tk) = self.lexer.peek_token() {
-                                            match tk.kind {
-                                                TokenKind::CloseParen => break,
-                                                _ => {
-                                                    if let Some(s) = self.parse_expr() {
-                                                        args.push(s);
-                                                    } else {
-                                                        // Hit EOF or something else before close bracket!
-                                                        todo!("Handle unclosed function call");
+                        // Qualified names are written as `module::name`
+                        let (module, s) = match self.tokens.peek(0)? {
+                            Some(Token { kind: TokenKind::ColonColon, .. }) => {
+                                self.tokens.next_token()?;
+                                let name = self.expect_token(TokenKind::Identifier(Cow::Borrowed("")))?;
+                                match name.kind {
+                                    TokenKind::Identifier(n) => (Some(s.into_owned()), n),
+                                    _ => unreachable!()
+                                }
+                            },
+                            _ => (None, s)
+                        };
+                        match self.tokens.peek(0)? {
+                            Some(Token { kind: TokenKind::OpenParen, .. }) => {
+                                self.tokens.next_token()?;
+                                let mut args = Vec::<Expr>::new();
+                                while let Some(tk) = self.tokens.peek(0)? {
+                                    match tk.kind {
+                                        TokenKind::CloseParen => break,
+                                        _ => {
+                                            match self.parse_expr()? {
+                                                Some(s) => args.push(s),
+                                                None => return Err(self.unexpected(None, "`)`"))
+                                            }
+
+                                            match self.tokens.peek(0)? {
+                                                Some(token) => {
+                                                    match token.kind {
+                                                        TokenKind::CloseParen => break,
+                                                        TokenKind::Comma => self.tokens.next_token()?,
+                                                        _ => {
+                                                            let t = self.tokens.next_token()?;
+                                                            return Err(self.unexpected(t, "`,` or `)`"));
+                                                        }
                                                     }
-                                                    
-                                                    match self.lexer.peek_token() {
-                                                        Some(token) => {
-                                                            match token.kind {
-                                                                TokenKind::CloseParen => break,
-                                                                TokenKind::Comma => self.lexer.next_token(),
-                                                                _ => todo!("Unexpected token after func call arg")
-                                                            }
-                                                        },
-                                                        None => todo!("EOF before close of call")
-                                                    };
-                                                }
+                                                },
+                                                None => return Err(self.unexpected(None, "`)`"))
                                             };
                                         }
-                                        
-                                        self.expect_token(TokenKind::CloseParen, true);
-                                        
-                                        Some(Expr::FunctionCall {
-                                            name: s,
-                                            arguments: args
-                                        })
-                                    },
-                                    k => {
-                                        todo!("Unexpected token {k:?} in parse_expr after ident");
-                                    },
+                                    };
                                 }
+
+                                let close = self.expect_token(TokenKind::CloseParen)?;
+
+                                Ok(Some(Expr::new(ExprKind::FunctionCall {
+                                    module,
+                                    name: s.into_owned(),
+                                    arguments: args
+                                }, span.to(close.span))))
+                            },
+                            _ if module.is_none() => {
+                                Ok(Some(Expr::new(ExprKind::Variable(s.into_owned()), span)))
                             },
-                            None => {
-                                todo!("Handle identifier by itself with nothing after it");
+                            _ => {
+                                let t = self.tokens.next_token()?;
+                                Err(self.unexpected(t, "`(`"))
                             }
                         }
                     },
-                    k => {
-                        todo!("Unexpected token {k:?}"); 
-                    },
+                    k => Err(self.unexpected(Some(Token::new(span, k)), "expression"))
+                }
+            },
+            None => Ok(None)
+        }
+    }
+
+    // "Hello, {name}!" is lowered into the concatenation of
+    // "Hello, ", the string conversion of `name` and "!"
+    fn parse_interpolated_string(&mut self, segments: Vec<StringSegment<'a>>, span: Span) -> Result<Expr, SyntaxError> {
+        let mut parts = Vec::<Expr>::new();
+
+        for segment in segments {
+            match segment {
+                StringSegment::Literal(s) => parts.push(Expr::new(ExprKind::StringLiteral(s.into_owned()), span)),
+                StringSegment::Expression { span: range, format } => {
+                    let mut parser = Parser::embedded(self.tokens.lexer().source(), range);
+                    let value = match parser.parse_expr()? {
+                        Some(e) => e,
+                        None => return Err(parser.unexpected(None, "expression"))
+                    };
+
+                    if let Some(t) = parser.tokens.next_token()? {
+                        return Err(parser.unexpected(Some(t), "`}`"));
+                    }
+
+                    let value_span = value.span;
+                    parts.push(Expr::new(ExprKind::ToString { value: Box::new(value), format }, value_span));
+                }
+            }
+        }
+
+        Ok(Expr::new(ExprKind::Concat(parts), span))
+    }
+
+    // Parses `name: type, ...` up to and including the closing `)`
+    fn parse_parameters(&mut self) -> Result<Vec<Parameter>, SyntaxError> {
+        let mut parameters = Vec::<Parameter>::new();
+
+        loop {
+            let name = match self.tokens.next_token()? {
+                Some(Token { kind: TokenKind::CloseParen, .. }) if parameters.is_empty() => break,
+                Some(Token { kind: TokenKind::Identifier(name), span }) => (name.into_owned(), span),
+                t => return Err(self.unexpected(t, "parameter name"))
+            };
+            self.expect_token(TokenKind::Colon)?;
+            let type_name = self.expect_token(TokenKind::Identifier(Cow::Borrowed("")))?;
+
+            if let TokenKind::Identifier(t) = type_name.kind {
+                parameters.push(Parameter {
+                    name: name.0,
+                    type_name: t.into_owned(),
+                    span: name.1.to(type_name.span)
+                });
+            }
+
+            match self.tokens.next_token()? {
+                Some(Token { kind: TokenKind::Comma, .. }) => {},
+                Some(Token { kind: TokenKind::CloseParen, .. }) => break,
+                t => return Err(self.unexpected(t, "`,` or `)`"))
+            }
+        }
+
+        Ok(parameters)
+    }
+
+    // The return type is written after the parameters as `: type`
+    fn parse_return_type(&mut self) -> Result<Option<TypeName>, SyntaxError> {
+        match self.tokens.peek(0)? {
+            Some(Token { kind: TokenKind::Colon, .. }) => {
+                self.tokens.next_token()?;
+                let type_name = self.expect_token(TokenKind::Identifier(Cow::Borrowed("")))?;
+                match type_name.kind {
+                    TokenKind::Identifier(t) => Ok(Some(TypeName { name: t.into_owned(), span: type_name.span })),
+                    _ => unreachable!()
                 }
             },
-            None => None
+            _ => Ok(None)
         }
     }
-    
-    fn parse_node(&mut self) -> Option<Node> {
+
+    fn parse_node(&mut self) -> Result<Option<Node>, SyntaxError> {
         // Only option is a function definition (for now)
-        match self.lexer.peek_token() {
+        match self.tokens.next_token()? {
             Some(token) => {
                 match token.kind {
                     TokenKind::Keyword(Keyword::Impure) => {
-                        self.lexer.next_token();
-                        match self.parse_node() {
-                            Some(Node::FunctionDefinition { name, contents, impure: _ }) => {
-                                Some(Node::FunctionDefinition { name, contents, impure: true })
+                        match self.tokens.peek(0)? {
+                            Some(Token { kind: TokenKind::Keyword(Keyword::Func), .. }) => {},
+                            _ => {
+                                let t = self.tokens.next_token()?;
+                                return Err(self.unexpected(t, "`func` after `impure`"));
+                            }
+                        }
+                        match self.parse_node()? {
+                            Some(Node { kind: NodeKind::FunctionDefinition { name, name_span, parameters, returns, contents, impure: _ }, span }) => {
+                                Ok(Some(Node {
+                                    kind: NodeKind::FunctionDefinition { name, name_span, parameters, returns, contents, impure: true },
+                                    span: token.span.to(span)
+                                }))
+                            },
+                            _ => unreachable!()
+                        }
+                    },
+                    TokenKind::Keyword(Keyword::Extern) => {
+                        let impure = match self.tokens.peek(0)? {
+                            Some(Token { kind: TokenKind::Keyword(Keyword::Impure), .. }) => {
+                                self.tokens.next_token()?;
+                                true
                             },
-                            #[allow(unreachable_patterns)]
-                            Some(_) => todo!("Unexpected token after impure"),
-                            None => todo!("Impure by itself!")
+                            _ => false
+                        };
+                        match self.tokens.next_token()? {
+                            Some(Token { kind: TokenKind::Keyword(Keyword::Func), .. }) => {},
+                            t => return Err(self.unexpected(t, "`func` after `extern`"))
+                        }
+
+                        let name = self.expect_token(TokenKind::Identifier(Cow::Borrowed("")))?;
+                        self.expect_token(TokenKind::OpenParen)?;
+                        let parameters = self.parse_parameters()?;
+                        let returns = self.parse_return_type()?;
+                        let semicolon = self.expect_token(TokenKind::Semicolon)?;
+
+                        match name.kind {
+                            TokenKind::Identifier(n) => Ok(Some(Node {
+                                kind: NodeKind::ExternFunction { name: n.into_owned(), name_span: name.span, parameters, returns, impure },
+                                span: token.span.to(semicolon.span)
+                            })),
+                            _ => unreachable!()
                         }
                     },
+                    TokenKind::Keyword(Keyword::Import) => {
+                        let path = match self.tokens.next_token()? {
+                            Some(Token { kind: TokenKind::Identifier(name), .. }) => ImportPath::Module(name.into_owned()),
+                            Some(Token { kind: TokenKind::StringLiteral(path), .. }) => ImportPath::File(path.into_owned()),
+                            t => return Err(self.unexpected(t, "module name or path"))
+                        };
+                        let semicolon = self.expect_token(TokenKind::Semicolon)?;
+
+                        Ok(Some(Node {
+                            kind: NodeKind::Import(path),
+                            span: token.span.to(semicolon.span)
+                        }))
+                    },
                     TokenKind::Keyword(Keyword::Func) => {
-                        self.lexer.next_token();
-                        let name = self.expect_token(TokenKind::Identifier(String::new()), true);
-                        self.expect_token(TokenKind::OpenParen, true);
-                        self.expect_token(TokenKind::CloseParen, true);
-                        self.expect_token(TokenKind::OpenCurly, true);
-                        
+                        let name = self.expect_token(TokenKind::Identifier(Cow::Borrowed("")))?;
+                        self.expect_token(TokenKind::OpenParen)?;
+                        let parameters = self.parse_parameters()?;
+                        let returns = self.parse_return_type()?;
+
+                        self.expect_token(TokenKind::OpenCurly)?;
+
                         let mut block = Block::new();
-                        
-                        while let Some(t) = self.lexer.peek_token() {
+
+                        while let Some(t) = self.tokens.peek(0)? {
                             match t.kind {
                                 TokenKind::CloseCurly => break,
                                 _ => {
-                                    if let Some(s) = self.parse_statement() {
+                                    if let Some(s) = self.parse_statement()? {
                                         block.push(s);
-                                        self.expect_token(TokenKind::Semicolon, true);
-                                    } else {
-                                        // Hit EOF or something else before close curly!
-                                        todo!("Handle unclosed function definition");
+                                        self.expect_token(TokenKind::Semicolon)?;
                                     }
                                 }
                             }
                         }
-                        
-                        self.expect_token(TokenKind::CloseCurly, true);
-                        
+
+                        let close = self.expect_token(TokenKind::CloseCurly)?;
+
                         if let TokenKind::Identifier(n) = name.kind {
-                            Some(Node::FunctionDefinition { name: n, contents: block, impure: false })
+                            Ok(Some(Node {
+                                kind: NodeKind::FunctionDefinition { name: n.into_owned(), name_span: name.span, parameters, returns, contents: block, impure: false },
+                                span: token.span.to(close.span)
+                            }))
                         } else {
-                            todo!("Unreachable!");
+                            unreachable!();
                         }
                     },
-                    k => {
-                        // TODO: Proper errors
-                        todo!("Unexpected token {k:?} in parse_node");
-                    },
+                    k => Err(self.unexpected(Some(Token::new(token.span, k)), "function definition, extern function or import"))
                 }
             },
-            None => None
+            None => Ok(None)
         }
     }
-    
-    pub fn parse_program(&mut self) -> Program {
+
+    // Stops at the first syntax error, since the rest of the program can't be parsed reliably
+    pub fn parse_program(&mut self) -> Result<Program, SyntaxError> {
         let mut program = Program::new();
-        
-        while let Some(node) = self.parse_node() {
+
+        while let Some(node) = self.parse_node()? {
             program.push(node);
         }
-        
-        program
-    } 
-    
+
+        Ok(program)
+    }
+
     // Use up all tokens and print them
     #[allow(dead_code)]
     pub fn token_drought(&mut self) {
-        while let Some(token) = self.lexer.next_token() {
+        while let Ok(Some(token)) = self.tokens.next_token() {
             println!("{:?}", token);
         }
     }
@@ -0,0 +1,125 @@
+use unicode_width::UnicodeWidthChar;
+
+use error::ErrorLocation;
+
+// Number of columns between tab stops when displaying source
+pub const TAB_WIDTH: usize = 4;
+
+// A range of bytes within a source file
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+
+    // Creates a span covering both this span and `other`
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            start: self.start.min(other.start),
+            end: self.end.max(other.end)
+        }
+    }
+}
+
+// Byte offsets of the start of every line, used to
+// convert a span into a line and column
+#[derive(Debug)]
+pub struct LineIndex {
+    line_starts: Vec<usize>
+}
+
+impl LineIndex {
+    pub fn new(text: &str) -> LineIndex {
+        let mut line_starts = vec![0];
+        for (offset, byte) in text.bytes().enumerate() {
+            if byte == b'\n' {
+                line_starts.push(offset + 1);
+            }
+        }
+
+        LineIndex { line_starts }
+    }
+
+    // Zero-based index of the line containing `offset`
+    pub fn line(&self, offset: usize) -> usize {
+        match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(line) => line - 1
+        }
+    }
+
+    pub fn line_start(&self, line: usize) -> usize {
+        self.line_starts[line]
+    }
+}
+
+#[derive(Debug)]
+pub struct SourceFile {
+    // Path of the file, as displayed in diagnostics
+    pub name: String,
+    pub text: String,
+    pub lines: LineIndex
+}
+
+impl SourceFile {
+    pub fn new(name: String, text: String) -> SourceFile {
+        let lines = LineIndex::new(&text);
+        SourceFile { name, text, lines }
+    }
+
+    // One-based line and columns of `offset`, for display in diagnostics
+    pub fn location(&self, offset: usize) -> ErrorLocation {
+        let offset = offset.min(self.text.len());
+        let line = self.lines.line(offset);
+        let line_start = self.lines.line_start(line);
+
+        ErrorLocation {
+            line: line + 1,
+            column: offset - line_start + 1,
+            display_column: display_width(&self.text[line_start..offset], 0) + 1
+        }
+    }
+
+    // The text of the zero-based line, without the line ending
+    pub fn line_text(&self, line: usize) -> &str {
+        let start = self.lines.line_start(line);
+        let end = self.text[start..].find('\n').map_or(self.text.len(), |e| start + e);
+        self.text[start..end].trim_end_matches('\r')
+    }
+}
+
+// The number of terminal columns `text` takes up when displayed starting at
+// `column`, accounting for tab stops and wide characters such as CJK or emoji
+pub fn display_width(text: &str, column: usize) -> usize {
+    let mut end = column;
+    for c in text.chars() {
+        end += match c {
+            '\t' => TAB_WIDTH - end % TAB_WIDTH,
+            c => c.width().unwrap_or(0)
+        };
+    }
+    end - column
+}
+
+// Replaces tabs with spaces up to the next tab stop, so that
+// the text lines up with carets drawn using `display_width`
+pub fn expand_tabs(text: &str) -> String {
+    let mut expanded = String::with_capacity(text.len());
+    let mut column = 0;
+    for c in text.chars() {
+        if c == '\t' {
+            let width = TAB_WIDTH - column % TAB_WIDTH;
+            expanded.extend(std::iter::repeat_n(' ', width));
+            column += width;
+        } else {
+            expanded.push(c);
+            column += c.width().unwrap_or(0);
+        }
+    }
+    expanded
+}
@@ -0,0 +1,49 @@
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Type {
+    String,
+    Int,
+    // The result of a function that doesn't return a value
+    Void,
+    // Accepts a value of any type, only used by built-ins. This is also the
+    // type of calls to undefined functions, to avoid reporting them twice.
+    Any
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signature {
+    pub parameters: Vec<Type>,
+    pub returns: Type,
+    pub impure: bool
+}
+
+impl Type {
+    pub fn from_name(name: &str) -> Option<Type> {
+        match name {
+            "string" => Some(Type::String),
+            "int" => Some(Type::Int),
+            _ => None
+        }
+    }
+
+    // Whether a value of type `other` can be used where this type is expected
+    pub fn accepts(self, other: Type) -> bool {
+        match (self, other) {
+            (_, Type::Void) => false,
+            (Type::Any, _) | (_, Type::Any) => true,
+            (a, b) => a == b
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::String => write!(f, "string"),
+            Type::Int => write!(f, "int"),
+            Type::Void => write!(f, "void"),
+            Type::Any => write!(f, "any")
+        }
+    }
+}
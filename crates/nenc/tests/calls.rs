@@ -0,0 +1,58 @@
+extern crate nenc;
+
+mod common;
+
+#[test]
+fn variables_cannot_be_called() {
+    let errors = common::errors("call_variable", "impure func greet(name: string) {\n    println(name(\"x\"));\n    greet(1);\n}\n\nimpure func main() {\n}\n");
+    assert!(errors.contains(concat!(
+        "ERROR <input>:2:13: Variable name is not a function, so it cannot be called\n",
+        " 2 |     println(name(\"x\"));\n",
+        "   |             ^^^^^^^^^\n"
+    )), "{}", errors);
+
+    // The call could produce any value, so `println` doesn't report a mismatch
+    // for it, but the rest of the function is still checked
+    assert_eq!(errors.matches("ERROR").count(), 2, "{}", errors);
+    assert!(errors.contains("ERROR <input>:3:11: Expected a value of type string, found int"), "{}", errors);
+}
+
+#[test]
+fn std_only_refers_to_public_built_ins() {
+    let errors = common::errors("std_missing", "impure func main() {\n    std::missing();\n}\n");
+    assert!(errors.starts_with("ERROR <input>:2:5: Function std::missing was called, but not defined"), "{}", errors);
+
+    let errors = common::errors("std_internal", "impure func main() {\n    std::__write(\"x\");\n}\n");
+    assert!(errors.starts_with("ERROR <input>:2:5: Function std::__write was called, but not defined"), "{}", errors);
+}
+
+// The name and line of each built-in that `source` shadows
+fn shadowed(name: &str, source: &str) -> Vec<(String, usize)> {
+    let warnings = common::warnings(name, &format!("{}\nimpure func main() {{\n}}\n", source));
+    let mut shadowed = warnings.lines()
+        .filter_map(|line| line.strip_prefix("WARNING <input>:"))
+        .map(|warning| {
+            let (line, rest) = warning.split_once(':').unwrap();
+            let name = rest.split_once("Function ").unwrap().1.split(' ').next().unwrap();
+            (name.to_string(), line.parse::<usize>().unwrap())
+        })
+        .collect::<Vec<(String, usize)>>();
+    shadowed.sort();
+    shadowed
+}
+
+#[test]
+fn defining_a_built_in_warns() {
+    let source = "impure func print(value: string) {\n    std::print(\"> {value}\");\n}\n\nfunc length(value: string): int {\n    return 0;\n}\n\nextern func read_file(path: string): string;\n";
+    assert_eq!(shadowed("shadow_built_ins", source), vec![
+        ("length".to_string(), 5),
+        ("print".to_string(), 1),
+        ("read_file".to_string(), 9)
+    ]);
+}
+
+#[test]
+fn other_functions_do_not_warn() {
+    // Internal primitives can't be called outside of the prelude, so they can't be shadowed
+    assert!(shadowed("shadow_others", "impure func greet() {\n}\n\nimpure func __write(value: string) {\n}\n").is_empty());
+}
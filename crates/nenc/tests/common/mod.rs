@@ -0,0 +1,61 @@
+// Each test file only uses some of these helpers
+#![allow(dead_code)]
+
+use std::{ env, fs };
+
+// Compiles `source`, returning its bytecode, or its errors without colors
+pub fn compile(name: &str, source: &str) -> Result<Vec<u8>, String> {
+    let output_path = env::temp_dir().join(format!("nenc-test-{}-{}.nenc", std::process::id(), name));
+
+    nenc::compile(source.as_bytes(), nenc::CompilerOptions {
+        output: output_path.clone(),
+        ..nenc::CompilerOptions::default()
+    }).map_err(|e| e.plain())?;
+
+    let bytecode = fs::read(&output_path).expect("Bytecode should have been written");
+    fs::remove_file(&output_path).ok();
+
+    Ok(bytecode)
+}
+
+// Writes each of `files` to a new directory, then compiles its `main.nen`, returning its warnings
+pub fn compile_files(name: &str, files: &[(&str, &str)]) -> Result<String, String> {
+    let directory = env::temp_dir().join(format!("nenc-test-{}-{}", std::process::id(), name));
+    for (path, source) in files {
+        let path = directory.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, source).unwrap();
+    }
+
+    let result = nenc::compile_file(&directory.join("main.nen"), nenc::CompilerOptions {
+        output: directory.join("main.nenc"),
+        ..nenc::CompilerOptions::default()
+    }).map(|warnings| warnings.plain()).map_err(|e| e.plain());
+
+    fs::remove_dir_all(&directory).ok();
+    result
+}
+
+// The warnings of a program that compiles, without colors
+pub fn warnings(name: &str, source: &str) -> String {
+    let output_path = env::temp_dir().join(format!("nenc-test-{}-{}.nenc", std::process::id(), name));
+
+    let result = nenc::compile(source.as_bytes(), nenc::CompilerOptions {
+        output: output_path.clone(),
+        ..nenc::CompilerOptions::default()
+    });
+    fs::remove_file(&output_path).ok();
+
+    match result {
+        Ok(warnings) => warnings.plain(),
+        Err(e) => panic!("Expected {} to compile, found:\n{}", name, e.plain())
+    }
+}
+
+// The errors of a program that shouldn't compile
+pub fn errors(name: &str, source: &str) -> String {
+    match compile(name, source) {
+        Ok(_) => panic!("Expected {} not to compile", name),
+        Err(errors) => errors
+    }
+}
@@ -0,0 +1,106 @@
+extern crate nenc;
+
+mod common;
+
+use nenc::source::SourceFile;
+
+#[test]
+fn offsets_are_converted_to_lines_and_columns() {
+    let source = SourceFile::new("<test>".to_string(), "first\nsecond\r\n\nlast".to_string());
+
+    let location = |offset: usize| {
+        let l = source.location(offset);
+        (l.line, l.column)
+    };
+    assert_eq!(location(0), (1, 1));
+    assert_eq!(location(5), (1, 6));
+    assert_eq!(location(6), (2, 1));
+    assert_eq!(location(14), (3, 1));
+    assert_eq!(location(15), (4, 1));
+    // Offsets past the end are clamped to it
+    assert_eq!(location(100), (4, 5));
+
+    assert_eq!(source.line_text(1), "second");
+    assert_eq!(source.line_text(2), "");
+}
+
+#[test]
+fn errors_underline_the_code_they_are_about() {
+    let errors = common::errors("underline", "impure func main() {\n    println(missing(\"x\"));\n    greet(1, 2);\n}\n\nfunc greet(name: string) {\n}\n");
+
+    assert!(errors.contains(concat!(
+        "ERROR <input>:2:13: Function missing was called, but not defined\n",
+        " 2 |     println(missing(\"x\"));\n",
+        "   |             ^^^^^^^^^^^^\n"
+    )), "{}", errors);
+    assert!(errors.contains(concat!(
+        "ERROR <input>:3:5: Function greet takes 1 argument, but 2 were given\n",
+        " 3 |     greet(1, 2);\n",
+        "   |     ^^^^^^^^^^^\n"
+    )), "{}", errors);
+}
+
+#[test]
+fn spans_across_lines_are_underlined_on_their_first_line() {
+    let errors = common::errors("multiline", "func greet(name: string) {\n}\n\nimpure func main() {\n    greet(\n        1);\n}\n");
+
+    assert!(errors.contains(concat!(
+        " 6 |         1);\n",
+        "   |         ^\n"
+    )), "{}", errors);
+
+    let errors = common::errors("multiline_call", "impure func main() {\n    missing(\n        1);\n}\n");
+    assert!(errors.contains(concat!(
+        "ERROR <input>:2:5: Function missing was called, but not defined\n",
+        " 2 |     missing(\n",
+        "   |     ^^^^^^^^\n"
+    )), "{}", errors);
+}
+
+#[test]
+fn the_end_of_the_file_is_pointed_at() {
+    let errors = common::errors("end_of_file", "impure func main() {\n    println(\"unfinished\"");
+    assert!(errors.starts_with(concat!(
+        "ERROR <input>:2:25: Expected `)`, found end of file\n",
+        " 2 |     println(\"unfinished\"\n",
+        "   |                         ^\n"
+    )), "{}", errors);
+}
+
+#[test]
+fn both_display_and_byte_columns_are_shown() {
+    // `é` is two bytes but one column wide, and `日本` is six bytes and four columns
+    let errors = common::errors("columns", "impure func main() {\n    println(\"é日本\", missing());\n}\n");
+    assert!(errors.contains(concat!(
+        "ERROR <input>:2:22: Function missing was called, but not defined\n",
+        " 2 |     println(\"é日本\", missing());\n",
+        "   |                      ^^^^^^^^^\n",
+        "   = display column 22, byte column 25\n"
+    )), "{}", errors);
+
+    // Tabs expand to the next multiple of four columns
+    let source = SourceFile::new("<test>".to_string(), "\t\"日本\"".to_string());
+    let location = source.location(8);
+    assert_eq!((location.line, location.column, location.display_column), (1, 9, 10));
+}
+
+#[test]
+fn strings_too_long_for_the_bytecode_are_errors() {
+    let program = |length: usize| format!("impure func main() {{\n    println(\"{}\");\n}}\n", "a".repeat(length));
+
+    assert!(common::compile("longest_string", &program(65535)).is_ok());
+
+    let errors = common::errors("too_long_string", &program(65536));
+    assert!(errors.starts_with("ERROR <input>:2:13: String is too long to compile, since it can be at most 65535 bytes"), "{}", &errors[..200]);
+}
+
+// Warnings are returned to the caller rather than written to standard error
+#[test]
+fn warnings_are_kept_with_errors() {
+    let source = "func length(value: string): int {\n    return 0;\n}\n\nimpure func main() {\n    missing();\n}\n";
+    let errors = common::errors("warnings_with_errors", source);
+
+    let warning = errors.find("WARNING <input>:1:6: Function length shadows a built-in function").expect(&errors);
+    let error = errors.find("ERROR <input>:6:5: ").expect(&errors);
+    assert!(warning < error, "{}", errors);
+}
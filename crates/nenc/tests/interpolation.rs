@@ -0,0 +1,79 @@
+extern crate nenc;
+
+mod common;
+
+use nenc::lexer::{ Alignment, FormatSpec, Lexer, StringSegment, TokenKind };
+use nenc::source::SourceFile;
+
+// The format specifiers of each expression in an interpolated string
+fn formats(text: &str) -> Vec<FormatSpec> {
+    let source = SourceFile::new("<test>".to_string(), text.to_string());
+    let token = Lexer::new(&source).next().expect("Should have a token").unwrap();
+
+    match token.kind {
+        TokenKind::InterpolatedString(segments) => segments.into_iter().filter_map(|segment| match segment {
+            StringSegment::Expression { format, .. } => Some(format),
+            StringSegment::Literal(_) => None
+        }).collect(),
+        kind => panic!("Expected an interpolated string, found {:?}", kind)
+    }
+}
+
+fn spec(align: Alignment, width: Option<u16>, precision: Option<u16>) -> (Alignment, Option<u16>, Option<u16>) {
+    (align, width, precision)
+}
+
+#[test]
+fn format_specs_are_parsed() {
+    let specs = formats(r#""{a} {a:<} {a:>8} {a:^10.2} {a:.3} {a:5}""#).into_iter()
+        .map(|f| (f.align, f.width, f.precision))
+        .collect::<Vec<_>>();
+
+    assert_eq!(specs, vec![
+        spec(Alignment::Default, None, None),
+        spec(Alignment::Left, None, None),
+        spec(Alignment::Right, Some(8), None),
+        spec(Alignment::Center, Some(10), Some(2)),
+        spec(Alignment::Default, None, Some(3)),
+        spec(Alignment::Default, Some(5), None)
+    ]);
+}
+
+#[test]
+fn colons_in_expressions_do_not_start_a_format_spec() {
+    let specs = formats(r#""{std::length(":")} {util::name():>4}""#);
+    assert_eq!(specs.len(), 2);
+    assert_eq!(specs[0].align, Alignment::Default);
+    assert_eq!((specs[1].align, specs[1].width), (Alignment::Right, Some(4)));
+}
+
+#[test]
+fn invalid_interpolations_are_errors() {
+    let program = |body: &str| format!("impure func main() {{\n    println({});\n}}\n", body);
+
+    for spec in ["x", ">>", "<-1", "5.", ".x", "99999999"] {
+        let errors = common::errors("format_spec", &program(&format!("\"{{1:{}}}\"", spec)));
+        assert!(errors.starts_with(&format!("ERROR <input>:2:17: Invalid format specifier: {}", spec)), "{}: {}", spec, errors);
+    }
+
+    let errors = common::errors("empty_interpolation", &program(r#""{ }""#));
+    assert!(errors.starts_with("ERROR <input>:2:14: Expected an expression inside of interpolation"), "{}", errors);
+
+    let errors = common::errors("unclosed_interpolation", &program(r#""{1""#));
+    assert!(errors.starts_with("ERROR <input>:2:14: Interpolation starting here is never closed"), "{}", errors);
+
+    let errors = common::errors("unmatched_curly", &program(r#""}""#));
+    assert!(errors.starts_with("ERROR <input>:2:14: Unmatched } in string"), "{}", errors);
+
+    // Brackets inside of an interpolation have to be balanced
+    let errors = common::errors("unbalanced_paren", &program(r#""{a)}""#));
+    assert!(errors.starts_with("ERROR <input>:2:16: Unmatched ) in interpolation"), "{}", errors);
+    let errors = common::errors("unbalanced_call", &program(r#""{f(1))}""#));
+    assert!(errors.starts_with("ERROR <input>:2:19: Unmatched ) in interpolation"), "{}", errors);
+    let errors = common::errors("unclosed_paren", &program(r#""{f(1}""#));
+    assert!(errors.starts_with("ERROR <input>:2:18: Unmatched } in interpolation"), "{}", errors);
+
+    // Only a single expression can be interpolated
+    let errors = common::errors("two_expressions", &program(r#""{1 2}""#));
+    assert!(errors.starts_with("ERROR <input>:2:17: Expected `}`, found integer `2`"), "{}", errors);
+}
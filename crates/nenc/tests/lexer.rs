@@ -0,0 +1,179 @@
+extern crate nenc;
+
+mod common;
+
+use std::borrow::Cow;
+
+use nenc::lexer::{ Lexer, TokenKind };
+use nenc::source::SourceFile;
+
+// The kind of each token, such as `StringLiteral("Hello")`
+fn tokens(text: &str) -> Vec<String> {
+    let source = SourceFile::new("<test>".to_string(), text.to_string());
+    Lexer::new(&source).map(|token| format!("{:?}", token.unwrap().kind)).collect()
+}
+
+// The byte range and kind of each token
+fn spans(text: &str) -> Vec<(usize, usize, String)> {
+    let source = SourceFile::new("<test>".to_string(), text.to_string());
+    Lexer::new(&source).map(|token| {
+        let token = token.unwrap();
+        (token.span.start, token.span.end, format!("{:?}", token.kind))
+    }).collect()
+}
+
+#[test]
+fn every_kind_of_token_is_lexed() {
+    let expected = vec![
+        (0, 6, "Keyword(Impure)".to_string()),
+        (7, 11, "Keyword(Func)".to_string()),
+        (12, 16, r#"Identifier("main")"#.to_string()),
+        (16, 17, "OpenParen".to_string()),
+        (17, 18, "CloseParen".to_string()),
+        (19, 20, "OpenCurly".to_string()),
+        (21, 27, "Keyword(Import)".to_string()),
+        (28, 34, "Keyword(Extern)".to_string()),
+        (35, 41, "Keyword(Return)".to_string()),
+        (42, 45, r#"Identifier("std")"#.to_string()),
+        (45, 47, "ColonColon".to_string()),
+        (47, 48, r#"Identifier("x")"#.to_string()),
+        (48, 49, "Colon".to_string()),
+        (50, 55, "IntegerLiteral(1000)".to_string()),
+        (55, 56, "Comma".to_string()),
+        (57, 61, r#"StringLiteral("hi")"#.to_string()),
+        (61, 62, "Semicolon".to_string()),
+        (63, 64, "CloseCurly".to_string())
+    ];
+    assert_eq!(spans("impure func main() { import extern return std::x: 1_000, \"hi\"; }"), expected);
+}
+
+#[test]
+fn spans_are_byte_offsets() {
+    // `é` and `ü` are two bytes each, and `→` is three
+    assert_eq!(spans("café \"→\" grüße"), vec![
+        (0, 5, r#"Identifier("café")"#.to_string()),
+        (6, 11, r#"StringLiteral("→")"#.to_string()),
+        (12, 19, r#"Identifier("grüße")"#.to_string())
+    ]);
+    assert_eq!(spans("\r\n\t a\r\nb"), vec![
+        (4, 5, r#"Identifier("a")"#.to_string()),
+        (7, 8, r#"Identifier("b")"#.to_string())
+    ]);
+}
+
+#[test]
+fn colons_are_lexed_greedily() {
+    assert_eq!(tokens("a::b"), vec![r#"Identifier("a")"#, "ColonColon", r#"Identifier("b")"#]);
+    assert_eq!(tokens("a: b"), vec![r#"Identifier("a")"#, "Colon", r#"Identifier("b")"#]);
+    assert_eq!(tokens(": :"), vec!["Colon", "Colon"]);
+    assert_eq!(tokens(":::"), vec!["ColonColon", "Colon"]);
+    // Inside of a string, only a single colon outside of an expression starts a format spec
+    assert_eq!(spans(r#""{std::x:>4}""#).len(), 1);
+}
+
+#[test]
+fn integers_must_fit_in_64_bits() {
+    assert_eq!(tokens("9223372036854775807"), vec!["IntegerLiteral(9223372036854775807)"]);
+    assert_eq!(tokens("0 007 1_2_3"), vec!["IntegerLiteral(0)", "IntegerLiteral(7)", "IntegerLiteral(123)"]);
+    let errors = common::errors("too_large", "impure func main() {\n    println(9223372036854775808);\n}\n");
+    assert!(errors.contains(concat!(
+        "ERROR <input>:2:13: Integer literal is too large\n",
+        " 2 |     println(9223372036854775808);\n",
+        "   |             ^^^^^^^^^^^^^^^^^^^\n"
+    )), "{}", errors);
+}
+
+#[test]
+fn values_are_borrowed_from_the_source_when_possible() {
+    let source = SourceFile::new("<test>".to_string(), "name \"plain\" \"esc\\n\" cafe\u{301}".to_string());
+    let kinds: Vec<_> = Lexer::new(&source).map(|token| token.unwrap().kind).collect();
+    assert!(matches!(kinds[0], TokenKind::Identifier(Cow::Borrowed("name"))));
+    assert!(matches!(kinds[1], TokenKind::StringLiteral(Cow::Borrowed("plain"))));
+    // Escapes and identifiers that aren't in NFC have to be rewritten
+    assert!(matches!(&kinds[2], TokenKind::StringLiteral(Cow::Owned(value)) if value == "esc\n"));
+    assert!(matches!(&kinds[3], TokenKind::Identifier(Cow::Owned(value)) if value == "café"));
+}
+
+#[test]
+fn unknown_characters_are_errors() {
+    let errors = common::errors("unknown", "impure func main() {\n    x = €;\n}\n");
+    assert!(errors.starts_with("ERROR <input>:2:7: Unexpected start of token: ="), "{}", errors);
+
+    let errors = common::errors("unknown_unicode", "impure func main() {\n    println(€);\n}\n");
+    assert!(errors.starts_with("ERROR <input>:2:13: Unexpected start of token: €"), "{}", errors);
+}
+
+#[test]
+fn escape_sequences_are_replaced() {
+    assert_eq!(tokens(r#""tab\tnew\nline\r\0""#), vec![r#"StringLiteral("tab\tnew\nline\r\0")"#]);
+    assert_eq!(tokens(r#""\\ \" \'""#), vec![r#"StringLiteral("\\ \" '")"#]);
+    assert_eq!(tokens(r#"'single "quotes"'"#), vec![r#"StringLiteral("single \"quotes\"")"#]);
+    assert_eq!(tokens(r#""\u{48}\u{e9}\u{1F600}""#), vec![r#"StringLiteral("Hé😀")"#]);
+    // A backslash at the end of a line skips the line break and the next line's indentation
+    assert_eq!(tokens("\"one \\\n        two\""), vec![r#"StringLiteral("one two")"#]);
+}
+
+#[test]
+fn raw_strings_keep_backslashes() {
+    assert_eq!(tokens(r#"r"C:\path\n""#), vec![r#"StringLiteral("C:\\path\\n")"#]);
+    assert_eq!(tokens(r##"r#"say "hi""#"##), vec![r#"StringLiteral("say \"hi\"")"#]);
+    assert_eq!(tokens(r#"r'{not interpolated}'"#), vec![r#"StringLiteral("{not interpolated}")"#]);
+    // Only `r` followed by a quote starts a raw string
+    assert_eq!(tokens("r (r)"), vec![r#"Identifier("r")"#, "OpenParen", r#"Identifier("r")"#, "CloseParen"]);
+}
+
+#[test]
+fn invalid_strings_are_errors() {
+    let errors = common::errors("unterminated", "impure func main() {\n    println(\"Hello);\n");
+    assert!(errors.starts_with("ERROR <input>:2:13: String literal starting here is never closed"), "{}", errors);
+
+    let errors = common::errors("unterminated_raw", "impure func main() {\n    println(r#\"Hello\");\n}\n");
+    assert!(errors.starts_with("ERROR <input>:2:13: String literal starting here is never closed"), "{}", errors);
+
+    let errors = common::errors("unknown_escape", "impure func main() {\n    println(\"\\q\");\n}\n");
+    assert!(errors.starts_with("ERROR <input>:2:14: Unknown escape sequence: \\q"), "{}", errors);
+
+    for escape in [r"\u48", r"\u{}", r"\u{110000}", r"\u{D800}", r"\u{1234567}"] {
+        let errors = common::errors("unicode_escape", &format!("impure func main() {{\n    println(\"{}\");\n}}\n", escape));
+        assert!(errors.starts_with("ERROR <input>:2:14: Invalid unicode escape"), "{}: {}", escape, errors);
+    }
+}
+
+#[test]
+fn identifiers_are_normalized_to_nfc() {
+    // `u` followed by a combining diaeresis is the same identifier as `ü`
+    assert_eq!(tokens("gru\u{308}ße grüße"), vec![r#"Identifier("grüße")"#, r#"Identifier("grüße")"#]);
+
+    let program = "impure func main() {\n    println(gru\u{308}ße());\n}\n\nfunc grüße(): string {\n    return \"Hallo\";\n}\n";
+    if let Err(errors) = common::compile("nfc", program) {
+        panic!("{}", errors);
+    }
+}
+
+#[test]
+fn identifiers_cannot_mix_scripts() {
+    let errors = common::errors("mixed_script", "impure func main() {\n    p\u{430}ypal();\n}\n");
+    assert!(errors.starts_with("ERROR <input>:2:5: Identifier p\u{430}ypal mixes characters from multiple scripts"), "{}", errors);
+
+    // Identifiers written entirely in one script are allowed, even where they look like one in another
+    assert_eq!(tokens("привет \u{430}"), vec![r#"Identifier("привет")"#, "Identifier(\"\u{430}\")"]);
+    // Digits and underscores are shared between scripts
+    assert_eq!(tokens("привет_2"), vec![r#"Identifier("привет_2")"#]);
+}
+
+#[test]
+fn text_direction_controls_are_only_allowed_in_strings() {
+    let errors = common::errors("bidi", "impure func main() {\n    println(\"x\"); \u{202E}\n}\n");
+    assert!(errors.starts_with("ERROR <input>:2:19: Unicode text direction control character U+202E is not allowed outside of strings"), "{}", errors);
+
+    let errors = common::errors("bidi_identifier", "impure func main() {\n    abc\u{2066}();\n}\n");
+    assert!(errors.starts_with("ERROR <input>:2:8: Unicode text direction control character U+2066"), "{}", errors);
+
+    assert_eq!(tokens("\"\u{202E}\""), vec![r#"StringLiteral("\u{202e}")"#]);
+}
+
+#[test]
+fn characters_not_recommended_for_identifiers_are_errors() {
+    let errors = common::errors("disallowed", "impure func main() {\n    a\u{1C5}();\n}\n");
+    assert!(errors.starts_with("ERROR <input>:2:6: Character \u{1C5} (U+01C5) is not allowed in identifiers"), "{}", errors);
+}
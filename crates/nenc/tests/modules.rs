@@ -0,0 +1,139 @@
+extern crate nenc;
+
+mod common;
+
+const MAIN: &str = "import util;\n\nimpure func main() {\n    util::greet();\n}\n";
+
+// The errors of a program split across files that shouldn't compile
+fn import_errors(name: &str, files: &[(&str, &str)]) -> String {
+    match common::compile_files(name, files) {
+        Ok(_) => panic!("Expected {} not to compile", name),
+        Err(errors) => errors
+    }
+}
+
+// `lib/text.nen` is imported by both modules, but only loaded once
+#[test]
+fn modules_are_imported_by_name_and_path() {
+    let result = common::compile_files("imports", &[
+        ("main.nen", "import util;\nimport \"lib/text.nen\";\n\nimpure func main() {\n    util::greet();\n    println(text::shout(\"hi\"));\n}\n"),
+        ("util.nen", "impure func greet() {\n    println(text::shout(\"hello\"));\n}\n\nimport \"lib/text.nen\";\n"),
+        ("lib/text.nen", "func shout(value: string): string {\n    return \"{value}!\";\n}\n")
+    ]);
+    if let Err(errors) = result {
+        panic!("{}", errors);
+    }
+}
+
+#[test]
+fn import_cycles_are_errors() {
+    let errors = import_errors("cycle", &[
+        ("main.nen", MAIN),
+        ("util.nen", "import other;\n\nimpure func greet() {\n}\n"),
+        ("other.nen", "import util;\n")
+    ]);
+    assert!(errors.contains("other.nen:1:1: Modules import each other in a cycle: "), "{}", errors);
+    assert!(errors.contains("util.nen -> "), "{}", errors);
+
+    let errors = import_errors("self_import", &[
+        ("main.nen", MAIN),
+        ("util.nen", "import util;\n\nimpure func greet() {\n}\n")
+    ]);
+    assert!(errors.contains("util.nen:1:1: Modules import each other in a cycle: "), "{}", errors);
+}
+
+#[test]
+fn modules_with_the_same_name_are_errors() {
+    let errors = import_errors("conflict", &[
+        ("main.nen", "import util;\nimport \"lib/util.nen\";\n\nimpure func main() {\n}\n"),
+        ("util.nen", ""),
+        ("lib/util.nen", "")
+    ]);
+    assert!(errors.contains("main.nen:2:1: A different module named util is already imported"), "{}", errors);
+}
+
+#[test]
+fn importing_a_module_twice_loads_it_once() {
+    let result = common::compile_files("twice", &[
+        ("main.nen", "import util;\nimport util;\nimport \"util.nen\";\n\nimpure func main() {\n    util::greet();\n}\n"),
+        ("util.nen", "impure func greet() {\n    println(\"hello\");\n}\n")
+    ]);
+    if let Err(errors) = result {
+        panic!("{}", errors);
+    }
+}
+
+#[test]
+fn module_names_must_be_identifiers() {
+    let errors = import_errors("dashed", &[
+        ("main.nen", "import \"my-util.nen\";\n\nimpure func main() {\n}\n"),
+        ("my-util.nen", "")
+    ]);
+    assert!(errors.contains("main.nen:1:1: Module my-util can't be imported, since its name isn't an identifier"), "{}", errors);
+
+    let errors = import_errors("keyword", &[
+        ("main.nen", "import \"func.nen\";\n\nimpure func main() {\n}\n"),
+        ("func.nen", "")
+    ]);
+    assert!(errors.contains("main.nen:1:1: Module func can't be imported"), "{}", errors);
+}
+
+#[test]
+fn std_cannot_be_imported() {
+    let errors = import_errors("std", &[
+        ("main.nen", "import std;\n\nimpure func main() {\n}\n"),
+        ("std.nen", "")
+    ]);
+    assert!(errors.contains("main.nen:1:1: A different module named std is already imported"), "{}", errors);
+
+    // Without a file named `std.nen` to find
+    let errors = import_errors("std_missing", &[("main.nen", "import std;\n\nimpure func main() {\n}\n")]);
+    assert!(errors.contains("main.nen:1:1: A different module named std is already imported"), "{}", errors);
+
+    let errors = import_errors("std_path", &[
+        ("main.nen", "import \"lib/std.nen\";\n\nimpure func main() {\n}\n"),
+        ("lib/std.nen", "")
+    ]);
+    assert!(errors.contains("main.nen:1:1: A different module named std is already imported"), "{}", errors);
+}
+
+#[test]
+fn missing_modules_are_errors() {
+    let errors = import_errors("missing", &[("main.nen", MAIN)]);
+    assert!(errors.contains("main.nen:1:1: "), "{}", errors);
+    assert!(errors.contains("util.nen"), "{}", errors);
+}
+
+// `util` imports `helpers` from its own source directory, which `main.nen` doesn't search
+#[test]
+fn packages_import_from_their_own_search_paths() {
+    let directory = std::env::temp_dir().join(format!("nenc-test-{}-packages", std::process::id()));
+    let files = [
+        ("app/main.nen", "import util;\n\nimpure func main() {\n    util::greet();\n}\n"),
+        ("util/src/main.nen", "import helpers;\n\nimpure func greet() {\n    helpers::greet();\n}\n"),
+        ("util/lib/helpers.nen", "impure func greet() {\n    println(\"hello\");\n}\n")
+    ];
+    for (path, source) in &files {
+        let path = directory.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, source).unwrap();
+    }
+
+    let mut packages = std::collections::HashMap::<String, nenc::Package>::new();
+    packages.insert("util".to_string(), nenc::Package {
+        entry: directory.join("util/src/main.nen"),
+        search_paths: vec![directory.join("util/lib")]
+    });
+
+    let result = nenc::compile_file(&directory.join("app/main.nen"), nenc::CompilerOptions {
+        output: directory.join("main.nenc"),
+        search_paths: vec![directory.join("app")],
+        packages,
+        ..nenc::CompilerOptions::default()
+    }).map_err(|e| e.plain());
+
+    std::fs::remove_dir_all(&directory).ok();
+    if let Err(errors) = result {
+        panic!("{}", errors);
+    }
+}
@@ -0,0 +1,114 @@
+use std::{ collections::{ BTreeMap, HashMap }, fs, io::{ self, Write } };
+
+use { DebugInfo, Frame, Hook, Instruction, RuntimeError };
+
+// Records which instructions of a program run, to report the lines and functions they cover
+pub struct Coverage {
+    // How many times each instruction ran, by function and offset.
+    // Ordered by offset, so that the instructions of a line can be found as a range.
+    executed: HashMap<String, BTreeMap<usize, u64>>,
+    calls: HashMap<String, u64>
+}
+
+// The functions and lines of one source file
+#[derive(Default)]
+struct FileCoverage {
+    // The first line each function has code on, and how many times it was called
+    functions: Vec<(String, usize, u64)>,
+    lines: BTreeMap<usize, u64>
+}
+
+impl Default for Coverage {
+    fn default() -> Self {
+        Coverage::new()
+    }
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Coverage {
+            executed: HashMap::<String, BTreeMap<usize, u64>>::new(),
+            calls: HashMap::<String, u64>::new()
+        }
+    }
+
+    fn files(&self, debug: &DebugInfo) -> BTreeMap<String, FileCoverage> {
+        let mut files = BTreeMap::<String, FileCoverage>::new();
+
+        for (name, function) in &debug.functions {
+            let file = match debug.files.get(function.file) {
+                // The prelude and programs compiled from memory have no file to report
+                Some(file) if !file.starts_with('<') => files.entry(file.to_string()).or_default(),
+                _ => continue
+            };
+            // Functions without any code, such as an empty `main`, have no lines to report them on
+            let start = match function.lines.first() {
+                Some((_, line, _)) => *line,
+                None => continue
+            };
+            file.functions.push((name.to_string(), start, self.calls.get(name).copied().unwrap_or_default()));
+
+            // A line counts as many times as its instruction that ran the most
+            let executed = self.executed.get(name);
+            for (index, (offset, line, _)) in function.lines.iter().enumerate() {
+                let end = function.lines.get(index + 1).map(|(o, _, _)| *o).unwrap_or(usize::MAX);
+                let count = executed.and_then(|e| e.range(*offset..end).map(|(_, c)| *c).max()).unwrap_or_default();
+
+                let hits = file.lines.entry(*line).or_default();
+                *hits = (*hits).max(count);
+            }
+        }
+
+        for file in files.values_mut() {
+            file.functions.sort_by(|a, b| a.1.cmp(&b.1).then(a.0.cmp(&b.0)));
+        }
+        files
+    }
+
+    // Writes the coverage in the lcov format, which needs the debug info of the program to know its lines
+    pub fn write_lcov(&self, debug: &DebugInfo, output: &mut dyn Write) -> io::Result<()> {
+        for (file, coverage) in self.files(debug) {
+            let path = fs::canonicalize(&file).map(|p| p.display().to_string()).unwrap_or(file);
+            writeln!(output, "TN:")?;
+            writeln!(output, "SF:{}", path)?;
+
+            for (name, line, _) in &coverage.functions {
+                writeln!(output, "FN:{},{}", line, name)?;
+            }
+            for (name, _, calls) in &coverage.functions {
+                writeln!(output, "FNDA:{},{}", calls, name)?;
+            }
+            writeln!(output, "FNF:{}", coverage.functions.len())?;
+            writeln!(output, "FNH:{}", coverage.functions.iter().filter(|(_, _, calls)| *calls > 0).count())?;
+
+            for (line, hits) in &coverage.lines {
+                writeln!(output, "DA:{},{}", line, hits)?;
+            }
+            writeln!(output, "LF:{}", coverage.lines.len())?;
+            writeln!(output, "LH:{}", coverage.lines.values().filter(|hits| **hits > 0).count())?;
+            writeln!(output, "end_of_record")?;
+        }
+        Ok(())
+    }
+}
+
+impl Hook for Coverage {
+    fn instruction(&mut self, frame: &Frame, _instruction: &Instruction) -> Result<(), RuntimeError> {
+        match self.executed.get_mut(frame.function) {
+            Some(executed) => *executed.entry(frame.offset).or_default() += 1,
+            None => {
+                self.executed.insert(frame.function.to_string(), BTreeMap::from([(frame.offset, 1)]));
+            }
+        }
+        Ok(())
+    }
+
+    fn enter(&mut self, function: &str) {
+        match self.calls.get_mut(function) {
+            Some(calls) => *calls += 1,
+            None => {
+                self.calls.insert(function.to_string(), 1);
+            }
+        }
+    }
+}
@@ -0,0 +1,431 @@
+use std::{ cell::RefCell, fs, io::{ self, BufRead, Read, Write }, mem, path::Path, rc::Rc };
+
+use debugger::{ describe, Mode, Stepper };
+use json::Json;
+use { Breakpoint, DebugInfo, Error, Frame, Hook, Instruction, InterpreterOptions, RuntimeError, Vm };
+
+// nen programs only have one thread
+const THREAD_ID: usize = 1;
+// The `variablesReference`s of the scopes of the innermost call, which are the only ones that can be seen
+const LOCALS: usize = 1;
+const STACK: usize = 2;
+// The longest request that is read, so that a bad header can't make the adapter allocate without bound
+const MAX_REQUEST_LENGTH: usize = 16 * 1024 * 1024;
+
+// The sending half of the connection, which is shared with the program's output
+struct Connection<'d> {
+    output: Box<dyn Write + 'd>,
+    seq: usize
+}
+
+impl<'d> Connection<'d> {
+    fn send(&mut self, kind: &str, mut members: Vec<(&str, Json)>) -> io::Result<()> {
+        self.seq += 1;
+        members.insert(0, ("seq", Json::from(self.seq)));
+        members.insert(1, ("type", Json::from(kind)));
+
+        let message = Json::object(members).to_string();
+        write!(self.output, "Content-Length: {}\r\n\r\n{}", message.len(), message)?;
+        self.output.flush()
+    }
+
+    fn event(&mut self, event: &str, body: Json) -> io::Result<()> {
+        match body {
+            Json::Null => self.send("event", vec![("event", Json::from(event))]),
+            body => self.send("event", vec![("event", Json::from(event)), ("body", body)])
+        }
+    }
+
+    fn respond(&mut self, request: &Json, result: Result<Json, String>) -> io::Result<()> {
+        let mut members = vec![
+            ("request_seq", request.get("seq").clone()),
+            ("success", Json::from(result.is_ok())),
+            ("command", request.get("command").clone())
+        ];
+        match result {
+            Ok(Json::Null) => {},
+            Ok(body) => members.push(("body", body)),
+            Err(message) => members.push(("message", Json::from(message)))
+        }
+
+        self.send("response", members)
+    }
+}
+
+// Sends what the program writes to the client as `output` events
+struct OutputEvents<'d> {
+    connection: Rc<RefCell<Connection<'d>>>,
+    category: &'static str
+}
+
+impl<'d> Write for OutputEvents<'d> {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        let output = String::from_utf8_lossy(bytes).to_string();
+        self.connection.borrow_mut().event("output", Json::object(vec![
+            ("category", Json::from(self.category)),
+            ("output", Json::from(output))
+        ]))?;
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+enum Action {
+    // Wait for the next request
+    Wait,
+    // Run the program until it stops again
+    Resume,
+    Disconnect
+}
+
+struct Adapter<'d> {
+    input: Box<dyn BufRead + 'd>,
+    connection: Rc<RefCell<Connection<'d>>>,
+    stepper: Stepper,
+    // The debug info of the launched program, to check breakpoints against before it runs
+    debug: Option<DebugInfo>,
+    // Whether the next stop is the one that `stopOnEntry` asked for
+    entry: bool,
+    disconnected: bool
+}
+
+// Paths are sent to the client in full, and compared in full when it sends them back
+fn full_path(path: &str) -> String {
+    fs::canonicalize(path).map(|p| p.display().to_string()).unwrap_or_else(|_| path.to_string())
+}
+
+impl<'d> Adapter<'d> {
+    // Reads the next request, or `None` once the client has closed the connection
+    fn read_request(&mut self) -> io::Result<Option<Json>> {
+        let mut length = None;
+        loop {
+            let mut header = String::new();
+            if self.input.read_line(&mut header)? == 0 {
+                return Ok(None);
+            }
+
+            match header.trim_end() {
+                "" => break,
+                header => if let Some(value) = header.strip_prefix("Content-Length:") {
+                    length = value.trim().parse::<usize>().ok();
+                    if length.is_none() {
+                        return Err(io::Error::new(io::ErrorKind::InvalidData, "A request has an invalid Content-Length"));
+                    }
+                }
+            }
+        }
+
+        let length = match length {
+            Some(length) if length <= MAX_REQUEST_LENGTH => length,
+            Some(_) => return Err(io::Error::new(io::ErrorKind::InvalidData, "A request is too long")),
+            None => return Err(io::Error::new(io::ErrorKind::InvalidData, "A request has no Content-Length"))
+        };
+        let mut content = vec![0; length];
+        self.input.read_exact(&mut content)?;
+
+        match String::from_utf8(content).ok().and_then(|c| Json::parse(&c)) {
+            Some(request) => Ok(Some(request)),
+            None => Err(io::Error::new(io::ErrorKind::InvalidData, "A request is not valid JSON"))
+        }
+    }
+
+    // The name of a source file in the debug info, from the path the client knows it by
+    fn source_name(&self, path: &str) -> String {
+        let full = full_path(path);
+        self.debug.iter().flat_map(|d| d.files.iter())
+            .find(|file| full_path(file) == full)
+            .cloned()
+            .unwrap_or(full)
+    }
+
+    fn set_breakpoints(&mut self, arguments: &Json) -> Json {
+        let path = arguments.get("source").get("path").as_str().unwrap_or_default();
+        let file = self.source_name(path);
+
+        for breakpoint in self.stepper.breakpoints.iter_mut() {
+            if let Some(Breakpoint::Line { file: Some(f), .. }) = breakpoint {
+                if *f == file {
+                    *breakpoint = None;
+                }
+            }
+        }
+
+        let mut breakpoints = Vec::<Json>::new();
+        for line in arguments.get("breakpoints").as_array().iter().filter_map(|b| b.get("line").as_usize()) {
+            // Lines without any instructions can't be stopped at
+            let verified = self.debug.as_ref().is_some_and(|d| d.functions.values().any(|f| {
+                d.files.get(f.file) == Some(&file) && f.lines.iter().any(|(_, l, _)| *l == line)
+            }));
+
+            let id = self.stepper.add_breakpoint(Breakpoint::Line { file: Some(file.to_string()), line });
+            breakpoints.push(Json::object(vec![("id", id.into()), ("verified", verified.into()), ("line", line.into())]));
+        }
+
+        Json::object(vec![("breakpoints", breakpoints.into())])
+    }
+
+    fn set_function_breakpoints(&mut self, arguments: &Json) -> Json {
+        for breakpoint in self.stepper.breakpoints.iter_mut() {
+            if let Some(Breakpoint::Function(_)) = breakpoint {
+                *breakpoint = None;
+            }
+        }
+
+        let mut breakpoints = Vec::<Json>::new();
+        for name in arguments.get("breakpoints").as_array().iter().filter_map(|b| b.get("name").as_str()) {
+            let verified = self.debug.as_ref().is_none_or(|d| d.functions.contains_key(name));
+
+            let id = self.stepper.add_breakpoint(Breakpoint::Function(name.to_string()));
+            breakpoints.push(Json::object(vec![("id", id.into()), ("verified", verified.into())]));
+        }
+
+        Json::object(vec![("breakpoints", breakpoints.into())])
+    }
+
+    // Frames are numbered by their depth, so the innermost call has the highest id
+    fn stack_trace(&self) -> Json {
+        let mut frames = Vec::<Json>::new();
+        for (index, call) in self.stepper.calls.iter().enumerate().rev() {
+            let mut frame = vec![("id", Json::from(index + 1)), ("name", Json::from(call.function.as_str()))];
+
+            match self.debug.as_ref().and_then(|d| d.position(&call.function, call.offset)) {
+                Some(p) => {
+                    let name = Path::new(&p.file).file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+                    frame.push(("source", Json::object(vec![("name", name.into()), ("path", full_path(&p.file).into())])));
+                    frame.push(("line", p.line.into()));
+                    frame.push(("column", p.column.into()));
+                },
+                None => {
+                    frame.push(("line", Json::from(0usize)));
+                    frame.push(("column", Json::from(0usize)));
+                }
+            }
+            frames.push(Json::object(frame));
+        }
+
+        Json::object(vec![("totalFrames", frames.len().into()), ("stackFrames", frames.into())])
+    }
+
+    fn scopes(&self, arguments: &Json) -> Json {
+        let scopes = match arguments.get("frameId").as_usize() {
+            Some(id) if id == self.stepper.calls.len() => vec![
+                Json::object(vec![("name", "Locals".into()), ("variablesReference", LOCALS.into()), ("expensive", false.into())]),
+                Json::object(vec![("name", "Stack".into()), ("variablesReference", STACK.into()), ("expensive", false.into())])
+            ],
+            _ => Vec::<Json>::new()
+        };
+
+        Json::object(vec![("scopes", scopes.into())])
+    }
+
+    fn variables(&self, arguments: &Json, frame: &Frame) -> Json {
+        let names = frame.debug.and_then(|d| d.functions.get(frame.function)).map(|f| &f.locals);
+        let values = match arguments.get("variablesReference").as_usize() {
+            Some(LOCALS) => frame.locals.iter().enumerate()
+                .map(|(index, value)| (names.and_then(|n| n.get(index)).cloned().unwrap_or_else(|| format!("local {}", index)), value))
+                .collect(),
+            // From the top of the stack down
+            Some(STACK) => frame.stack.iter().enumerate().rev().map(|(index, value)| (index.to_string(), value)).collect(),
+            _ => Vec::new()
+        };
+
+        let variables = values.into_iter().map(|(name, value)| Json::object(vec![
+            ("name", name.into()),
+            ("value", describe(value).into()),
+            ("variablesReference", Json::from(0usize))
+        ])).collect::<Vec<Json>>();
+
+        Json::object(vec![("variables", variables.into())])
+    }
+
+    // Only the locals of the innermost call can be evaluated, by name
+    fn evaluate(&self, arguments: &Json, frame: &Frame) -> Result<Json, String> {
+        let expression = arguments.get("expression").as_str().unwrap_or_default().trim();
+        let index = frame.debug.and_then(|d| d.functions.get(frame.function))
+            .and_then(|f| f.locals.iter().position(|name| name == expression));
+
+        match index.and_then(|i| frame.locals.get(i)) {
+            Some(value) => Ok(Json::object(vec![("result", describe(value).into()), ("variablesReference", Json::from(0usize))])),
+            None => Err(format!("'{}' is not a local of {}", expression, frame.function))
+        }
+    }
+
+    // Handles requests that can be made at any time, with `frame` set while the program is stopped
+    fn handle(&mut self, request: &Json, frame: Option<&Frame>) -> io::Result<Action> {
+        let arguments = request.get("arguments");
+        let mut action = Action::Wait;
+
+        let result = match (request.get("command").as_str().unwrap_or_default(), frame) {
+            ("initialize", _) => Ok(Json::object(vec![
+                ("supportsConfigurationDoneRequest", true.into()),
+                ("supportsFunctionBreakpoints", true.into())
+            ])),
+            ("setBreakpoints", _) => Ok(self.set_breakpoints(arguments)),
+            ("setFunctionBreakpoints", _) => Ok(self.set_function_breakpoints(arguments)),
+            ("threads", _) => Ok(Json::object(vec![("threads", vec![
+                Json::object(vec![("id", THREAD_ID.into()), ("name", "main".into())])
+            ].into())])),
+            ("stackTrace", Some(_)) => Ok(self.stack_trace()),
+            ("scopes", Some(_)) => Ok(self.scopes(arguments)),
+            ("variables", Some(frame)) => Ok(self.variables(arguments, frame)),
+            ("evaluate", Some(frame)) => self.evaluate(arguments, frame),
+            ("continue" | "next" | "stepIn" | "stepOut", Some(frame)) => {
+                self.stepper.mode = match request.get("command").as_str() {
+                    Some("next") => Mode::Next(frame.depth),
+                    Some("stepIn") => Mode::Step,
+                    Some("stepOut") => Mode::Finish(frame.depth),
+                    _ => Mode::Continue
+                };
+                action = Action::Resume;
+                Ok(Json::object(vec![("allThreadsContinued", true.into())]))
+            },
+            ("disconnect" | "terminate", _) => {
+                self.disconnected = true;
+                action = Action::Disconnect;
+                Ok(Json::Null)
+            },
+            ("stackTrace" | "scopes" | "variables" | "evaluate" | "continue" | "next" | "stepIn" | "stepOut" | "pause", None) => {
+                Err("The program isn't stopped".to_string())
+            },
+            (command, _) => Err(format!("'{}' isn't supported", command))
+        };
+
+        self.connection.borrow_mut().respond(request, result)?;
+        Ok(action)
+    }
+}
+
+impl<'d> Hook for Adapter<'d> {
+    fn instruction(&mut self, frame: &Frame, instruction: &Instruction) -> Result<(), RuntimeError> {
+        let stop = match self.stepper.stop(frame, instruction) {
+            Some(stop) => stop,
+            None => return Ok(())
+        };
+
+        let reason = match (stop.breakpoint, mem::replace(&mut self.entry, false)) {
+            (true, _) => "breakpoint",
+            (false, true) => "entry",
+            (false, false) => "step"
+        };
+        self.connection.borrow_mut().event("stopped", Json::object(vec![
+            ("reason", reason.into()),
+            ("threadId", THREAD_ID.into()),
+            ("allThreadsStopped", true.into())
+        ]))?;
+
+        // Requests are handled until one of them runs the program again
+        loop {
+            let request = match self.read_request()? {
+                Some(request) => request,
+                None => return Err(RuntimeError::Interrupted)
+            };
+
+            match self.handle(&request, Some(frame))? {
+                Action::Wait => {},
+                Action::Resume => return Ok(()),
+                Action::Disconnect => return Err(RuntimeError::Interrupted)
+            }
+        }
+    }
+}
+
+// Serves the debug adapter protocol, reading requests from `input` and writing responses and events to `output`.
+// `load` gives the bytecode of the program that the client launches, or why it couldn't be loaded.
+// The program runs with no input, and requests are only read while it is stopped.
+pub fn serve_dap<'d>(input: impl BufRead + 'd, output: impl Write + 'd, mut options: InterpreterOptions, mut load: impl FnMut(&str) -> Result<Vec<u8>, String>) -> io::Result<()> {
+    let connection = Rc::new(RefCell::new(Connection { output: Box::new(output), seq: 0 }));
+    let mut adapter = Adapter {
+        input: Box::new(input),
+        connection: Rc::clone(&connection),
+        stepper: Stepper::new(Mode::Continue),
+        debug: None,
+        entry: false,
+        disconnected: false
+    };
+
+    // The program is run once it has been launched, and the client has set its breakpoints
+    let mut bytecode = None;
+    let mut configured = false;
+    while bytecode.is_none() || !configured {
+        let request = match adapter.read_request()? {
+            Some(request) => request,
+            None => return Ok(())
+        };
+        let arguments = request.get("arguments");
+
+        match request.get("command").as_str() {
+            Some("launch") => {
+                let loaded = arguments.get("program").as_str()
+                    .ok_or("No program was given to launch".to_string())
+                    .and_then(&mut load)
+                    .and_then(|b| Vm::load(&b[..], InterpreterOptions::default()).map(|vm| (vm.debug_info().cloned(), b)).map_err(|e| e.to_string()));
+
+                match loaded {
+                    Ok((debug, b)) => {
+                        adapter.debug = debug;
+                        bytecode = Some(b);
+                        options.args = arguments.get("args").as_array().iter().filter_map(|a| a.as_str()).map(str::to_string).collect();
+                        if arguments.get("stopOnEntry").as_bool() == Some(true) {
+                            adapter.stepper.mode = Mode::Step;
+                            adapter.entry = true;
+                        }
+
+                        connection.borrow_mut().respond(&request, Ok(Json::Null))?;
+                        connection.borrow_mut().event("initialized", Json::Null)?;
+                    },
+                    Err(message) => connection.borrow_mut().respond(&request, Err(message))?
+                }
+            },
+            Some("configurationDone") => {
+                configured = true;
+                connection.borrow_mut().respond(&request, Ok(Json::Null))?;
+            },
+            _ => if let Action::Disconnect = adapter.handle(&request, None)? {
+                return Ok(());
+            }
+        }
+    }
+
+    let result = match Vm::load_with_hook(&bytecode.unwrap_or_default()[..], options, &mut adapter) {
+        Ok(mut vm) => {
+            vm.set_input(io::empty());
+            vm.set_output(OutputEvents { connection: Rc::clone(&connection), category: "stdout" });
+            vm.set_error_output(OutputEvents { connection: Rc::clone(&connection), category: "stderr" });
+            vm.run_main()
+        },
+        Err(e) => Err(Error::Load(e))
+    };
+
+    if adapter.disconnected {
+        return Ok(());
+    }
+
+    if let Err(Error::Runtime(error, trace)) = &result {
+        let mut message = format!("ERROR {}\n", error);
+        for location in trace {
+            match &location.position {
+                Some(p) => message.push_str(&format!("    at {} ({}:{}:{})\n", location.function, p.file, p.line, p.column)),
+                None => message.push_str(&format!("    at {} (offset {})\n", location.function, location.offset))
+            }
+        }
+        connection.borrow_mut().event("output", Json::object(vec![("category", "stderr".into()), ("output", message.into())]))?;
+    } else if let Err(Error::Load(error)) = &result {
+        connection.borrow_mut().event("output", Json::object(vec![("category", "stderr".into()), ("output", format!("ERROR {}\n", error).into())]))?;
+    }
+
+    let status = result.unwrap_or_else(|e| e.exit_code());
+    connection.borrow_mut().event("exited", Json::object(vec![("exitCode", Json::from(status as i64))]))?;
+    connection.borrow_mut().event("terminated", Json::object(Vec::new()))?;
+
+    // The client disconnects once it has seen the program finish
+    while let Some(request) = adapter.read_request()? {
+        if let Action::Disconnect = adapter.handle(&request, None)? {
+            break;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,78 @@
+use std::collections::HashMap;
+
+use { get_byte_string_from_idx, get_operand_bytes, get_u16_from_idx, get_u32_from_idx };
+
+// The optional section after the functions, which maps instructions back to the source they came from
+#[derive(Debug, Clone, Default)]
+pub struct DebugInfo {
+    pub files: Vec<String>,
+    pub functions: HashMap<String, FunctionDebug>
+}
+
+#[derive(Debug, Clone)]
+pub struct FunctionDebug {
+    // Index into `DebugInfo::files`
+    pub file: usize,
+    // Names of the function's locals, by their index
+    pub locals: Vec<String>,
+    // Byte offsets of instructions, along with their line and column, where the position changes
+    pub lines: Vec<(usize, usize, usize)>
+}
+
+// A position in a source file, with a one-based line and column
+#[derive(Debug, Clone, PartialEq)]
+pub struct Position {
+    pub file: String,
+    pub line: usize,
+    pub column: usize
+}
+
+impl DebugInfo {
+    // Parses the contents of the debug section, which is `None` if it is malformed
+    pub fn parse(section: &[u8]) -> Option<DebugInfo> {
+        let mut idx = 0;
+        let mut debug = DebugInfo::default();
+
+        for _ in 0..get_u16_from_idx(section, &mut idx)? {
+            debug.files.push(get_byte_string_from_idx(section, &mut idx)?);
+        }
+
+        for _ in 0..get_u16_from_idx(section, &mut idx)? {
+            let name = get_byte_string_from_idx(section, &mut idx)?;
+            let file = get_u16_from_idx(section, &mut idx)? as usize;
+
+            let mut locals = Vec::<String>::new();
+            for _ in 0..get_u16_from_idx(section, &mut idx)? {
+                locals.push(get_byte_string_from_idx(section, &mut idx)?);
+            }
+
+            let mut lines = Vec::<(usize, usize, usize)>::new();
+            for _ in 0..get_u32_from_idx(section, &mut idx)? {
+                let offset = get_u32_from_idx(section, &mut idx)? as usize;
+                let line = get_u32_from_idx(section, &mut idx)? as usize;
+                let column = get_u32_from_idx(section, &mut idx)? as usize;
+                lines.push((offset, line, column));
+            }
+
+            debug.functions.insert(name, FunctionDebug { file, locals, lines });
+        }
+
+        match get_operand_bytes(section, &mut idx, 1) {
+            Some(_) => None,
+            None => Some(debug)
+        }
+    }
+
+    // Where the instruction at `offset` in `function` came from
+    pub fn position(&self, function: &str, offset: usize) -> Option<Position> {
+        let debug = self.functions.get(function)?;
+        let index = debug.lines.partition_point(|(start, _, _)| *start <= offset).checked_sub(1)?;
+        let (_, line, column) = debug.lines[index];
+
+        Some(Position {
+            file: self.files.get(debug.file)?.to_string(),
+            line,
+            column
+        })
+    }
+}
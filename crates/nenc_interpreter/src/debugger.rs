@@ -0,0 +1,331 @@
+use std::{ collections::HashMap, fs, io::{ BufRead, Write }, mem };
+
+use { Frame, Hook, Instruction, Position, RuntimeError, Value };
+
+// Where the debugger stops the program
+#[derive(Debug, Clone, PartialEq)]
+pub enum Breakpoint {
+    // Once a function has stored its arguments in its locals
+    Function(String),
+    // Before the first instruction of a line, in the file the debugger is stopped in if no file is given
+    Line { file: Option<String>, line: usize }
+}
+
+impl Breakpoint {
+    // Parses `name`, `line` or `file:line`
+    pub fn parse(target: &str) -> Breakpoint {
+        if let Ok(line) = target.parse::<usize>() {
+            return Breakpoint::Line { file: None, line };
+        }
+
+        match target.rsplit_once(':').map(|(file, line)| (file, line.parse::<usize>())) {
+            Some((file, Ok(line))) => Breakpoint::Line { file: Some(file.to_string()), line },
+            _ => Breakpoint::Function(target.to_string())
+        }
+    }
+
+    fn matches(&self, frame: &Frame, position: Option<&Position>, entered: bool) -> bool {
+        match (self, position) {
+            (Breakpoint::Function(name), _) => entered && frame.function == name,
+            (Breakpoint::Line { file, line }, Some(p)) => {
+                p.line == *line && file.as_ref().is_none_or(|f| p.file == *f || p.file.ends_with(&format!("/{}", f)))
+            },
+            (Breakpoint::Line { .. }, None) => false
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Mode {
+    // Stop at the next line, including in the function it calls
+    Step,
+    // Stop at the next line of the function at this depth, or of the one it returns to
+    Next(usize),
+    // Stop once the function at this depth returns
+    Finish(usize),
+    Continue
+}
+
+// A call that the program is in, along with the file and line it is at
+pub struct Call {
+    pub function: String,
+    pub offset: usize,
+    line: Option<(String, usize)>
+}
+
+// Where the program was stopped
+pub struct Stop {
+    pub position: Option<Position>,
+    pub breakpoint: bool
+}
+
+// Decides when to stop the program, for both the command-line debugger and the debug adapter.
+// Without debug info, it steps through instructions rather than lines.
+pub struct Stepper {
+    // Removed breakpoints are `None`, so that the others keep their numbers
+    pub breakpoints: Vec<Option<Breakpoint>>,
+    pub mode: Mode,
+    // From the outermost call inwards
+    pub calls: Vec<Call>,
+    // The depth, file and line that the program was last stopped at
+    stopped: Option<(usize, String, usize)>,
+    // Whether the current call is still storing its arguments
+    prologue: bool
+}
+
+impl Stepper {
+    pub fn new(mode: Mode) -> Self {
+        Stepper {
+            breakpoints: Vec::<Option<Breakpoint>>::new(),
+            mode,
+            calls: Vec::<Call>::new(),
+            stopped: None,
+            prologue: false
+        }
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> usize {
+        self.breakpoints.push(Some(breakpoint));
+        self.breakpoints.len()
+    }
+
+    // Called before each instruction, and returns where the program is if it should stop there
+    pub fn stop(&mut self, frame: &Frame, instruction: &Instruction) -> Option<Stop> {
+        // Every call starts at the first instruction of its function
+        self.calls.truncate(frame.depth);
+        if frame.offset == 0 {
+            self.calls.truncate(frame.depth - 1);
+            self.calls.push(Call { function: frame.function.to_string(), offset: 0, line: None });
+            // Calls start by storing their arguments, which isn't stopped at so that they can be seen
+            self.prologue = true;
+        }
+        let depth = frame.depth;
+        self.calls[depth - 1].offset = frame.offset;
+
+        match instruction {
+            Instruction::StoreLocal(_) if self.prologue => return None,
+            _ => {}
+        }
+        let entered = mem::replace(&mut self.prologue, false);
+
+        let position = frame.debug.and_then(|d| d.position(frame.function, frame.offset));
+        let line = position.as_ref().map(|p| (p.file.to_string(), p.line));
+        // Without debug info, every instruction is its own line
+        let new_line = line.is_none() || line != self.calls[depth - 1].line;
+        let stopped = line.clone().map(|(file, line)| (depth, file, line));
+        let moved = stopped.is_none() || stopped != self.stopped;
+        self.calls[depth - 1].line = line;
+
+        let breakpoint = new_line && self.breakpoints.iter().flatten().any(|b| b.matches(frame, position.as_ref(), entered));
+        let stop = breakpoint || match self.mode {
+            Mode::Step => moved,
+            Mode::Next(depth) => frame.depth < depth || (frame.depth == depth && moved),
+            Mode::Finish(depth) => frame.depth < depth,
+            Mode::Continue => false
+        };
+
+        if !stop {
+            return None;
+        }
+
+        self.stopped = stopped;
+        Some(Stop { position, breakpoint })
+    }
+}
+
+// An interactive debugger, which reads commands from `commands` whenever it stops the program
+pub struct Debugger<'d> {
+    commands: Box<dyn BufRead + 'd>,
+    output: Box<dyn Write + 'd>,
+    stepper: Stepper,
+    sources: HashMap<String, Option<Vec<String>>>
+}
+
+const HELP: &str = "\
+Commands:
+  break <function | line | file:line>  Stop when the program gets there
+  delete <number>                      Remove a breakpoint
+  step                                 Run until the next line, stepping into calls
+  next                                 Run until the next line, stepping over calls
+  finish                               Run until the current function returns
+  continue                             Run until the next breakpoint
+  stack                                Show the values on the stack
+  locals                               Show the locals of the current function
+  backtrace                            Show the calls the program is in
+  list                                 Show the source around the current line
+  quit                                 Stop the program
+";
+
+impl<'d> Debugger<'d> {
+    // The debugger stops before the first instruction, so that breakpoints can be set
+    pub fn new(commands: impl BufRead + 'd, output: impl Write + 'd) -> Self {
+        Debugger {
+            commands: Box::new(commands),
+            output: Box::new(output),
+            stepper: Stepper::new(Mode::Step),
+            sources: HashMap::<String, Option<Vec<String>>>::new()
+        }
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> usize {
+        self.stepper.add_breakpoint(breakpoint)
+    }
+
+    // The lines of a source file, read the first time they are shown
+    fn source_line(&mut self, file: &str, line: usize) -> Option<String> {
+        let lines = self.sources.entry(file.to_string()).or_insert_with(|| {
+            fs::read_to_string(file).ok().map(|s| s.lines().map(str::to_string).collect())
+        });
+
+        lines.as_ref()?.get(line.checked_sub(1)?).cloned()
+    }
+
+    fn show_line(&mut self, file: &str, line: usize, current: bool) -> Result<(), RuntimeError> {
+        if let Some(source) = self.source_line(file, line) {
+            let marker = if current { ">" } else { " " };
+            writeln!(self.output, "{} {:>4} | {}", marker, line, source)?;
+        }
+        Ok(())
+    }
+
+    fn show_stop(&mut self, frame: &Frame, position: Option<&Position>, instruction: &Instruction) -> Result<(), RuntimeError> {
+        match position {
+            Some(p) => {
+                writeln!(self.output, "{} ({}:{}:{})", frame.function, p.file, p.line, p.column)?;
+                self.show_line(&p.file, p.line, true)?;
+            },
+            None => writeln!(self.output, "{} (offset {}): {}", frame.function, frame.offset, instruction)?
+        }
+        Ok(())
+    }
+
+    fn show_locals(&mut self, frame: &Frame) -> Result<(), RuntimeError> {
+        let names = frame.debug.and_then(|d| d.functions.get(frame.function)).map(|f| &f.locals);
+
+        if frame.locals.is_empty() {
+            writeln!(self.output, "  No locals")?;
+        }
+        for (index, value) in frame.locals.iter().enumerate() {
+            match names.and_then(|n| n.get(index)) {
+                Some(name) => writeln!(self.output, "  {} = {}", name, describe(value))?,
+                None => writeln!(self.output, "  local {} = {}", index, describe(value))?
+            }
+        }
+        Ok(())
+    }
+
+    fn show_stack(&mut self, frame: &Frame) -> Result<(), RuntimeError> {
+        if frame.stack.is_empty() {
+            writeln!(self.output, "  The stack is empty")?;
+        }
+        // From the top of the stack down
+        for (index, value) in frame.stack.iter().enumerate().rev() {
+            writeln!(self.output, "  {}: {}", index, describe(value))?;
+        }
+        Ok(())
+    }
+
+    fn show_backtrace(&mut self, frame: &Frame) -> Result<(), RuntimeError> {
+        for call in self.stepper.calls.iter().rev() {
+            match frame.debug.and_then(|d| d.position(&call.function, call.offset)) {
+                Some(p) => writeln!(self.output, "  at {} ({}:{}:{})", call.function, p.file, p.line, p.column)?,
+                None => writeln!(self.output, "  at {} (offset {})", call.function, call.offset)?
+            }
+        }
+        Ok(())
+    }
+
+    fn show_breakpoints(&mut self) -> Result<(), RuntimeError> {
+        for (index, breakpoint) in self.stepper.breakpoints.iter().enumerate() {
+            match breakpoint {
+                Some(Breakpoint::Function(name)) => writeln!(self.output, "  {}: {}", index + 1, name)?,
+                Some(Breakpoint::Line { file: Some(file), line }) => writeln!(self.output, "  {}: {}:{}", index + 1, file, line)?,
+                Some(Breakpoint::Line { file: None, line }) => writeln!(self.output, "  {}: line {}", index + 1, line)?,
+                None => {}
+            }
+        }
+        Ok(())
+    }
+
+    // Reads commands until one of them runs the program again
+    fn prompt(&mut self, frame: &Frame, position: Option<&Position>) -> Result<(), RuntimeError> {
+        loop {
+            write!(self.output, "(nen) ")?;
+            self.output.flush()?;
+
+            let mut line = String::new();
+            if self.commands.read_line(&mut line)? == 0 {
+                return Err(RuntimeError::Interrupted);
+            }
+
+            let mut words = line.split_whitespace();
+            match (words.next(), words.next()) {
+                (None, _) => {},
+                (Some("s" | "step"), _) => {
+                    self.stepper.mode = Mode::Step;
+                    return Ok(());
+                },
+                (Some("n" | "next"), _) => {
+                    self.stepper.mode = Mode::Next(frame.depth);
+                    return Ok(());
+                },
+                (Some("f" | "finish"), _) => {
+                    self.stepper.mode = Mode::Finish(frame.depth);
+                    return Ok(());
+                },
+                (Some("c" | "continue"), _) => {
+                    self.stepper.mode = Mode::Continue;
+                    return Ok(());
+                },
+                (Some("q" | "quit"), _) => return Err(RuntimeError::Interrupted),
+                (Some("b" | "break"), None) => self.show_breakpoints()?,
+                (Some("b" | "break"), Some(target)) => {
+                    let breakpoint = match (Breakpoint::parse(target), position) {
+                        (Breakpoint::Line { file: None, line }, Some(p)) => Breakpoint::Line { file: Some(p.file.to_string()), line },
+                        (breakpoint, _) => breakpoint
+                    };
+                    let number = self.add_breakpoint(breakpoint);
+                    writeln!(self.output, "Breakpoint {} at {}", number, target)?;
+                },
+                (Some("d" | "delete"), Some(number)) => {
+                    match number.parse::<usize>().ok().and_then(|n| self.stepper.breakpoints.get_mut(n.wrapping_sub(1))) {
+                        Some(breakpoint @ Some(_)) => *breakpoint = None,
+                        _ => writeln!(self.output, "No breakpoint {}", number)?
+                    }
+                },
+                (Some("stack"), _) => self.show_stack(frame)?,
+                (Some("locals"), _) => self.show_locals(frame)?,
+                (Some("bt" | "backtrace"), _) => self.show_backtrace(frame)?,
+                (Some("l" | "list"), _) => match position {
+                    Some(p) => {
+                        for line in p.line.saturating_sub(3).max(1)..=p.line + 3 {
+                            self.show_line(&p.file, line, line == p.line)?;
+                        }
+                    },
+                    None => writeln!(self.output, "The program has no debug info")?
+                },
+                (Some("h" | "help"), _) => write!(self.output, "{}", HELP)?,
+                (Some(command), _) => writeln!(self.output, "Unknown command '{}', see `help`", command)?
+            }
+        }
+    }
+}
+
+impl<'d> Hook for Debugger<'d> {
+    fn instruction(&mut self, frame: &Frame, instruction: &Instruction) -> Result<(), RuntimeError> {
+        match self.stepper.stop(frame, instruction) {
+            Some(stop) => {
+                self.show_stop(frame, stop.position.as_ref(), instruction)?;
+                self.prompt(frame, stop.position.as_ref())
+            },
+            None => Ok(())
+        }
+    }
+}
+
+pub fn describe(value: &Value) -> String {
+    match value {
+        Value::String(s) => format!("{:?}", s),
+        Value::Int(i) => i.to_string()
+    }
+}
@@ -0,0 +1,85 @@
+use std::{ collections::HashMap, convert::TryFrom, env };
+
+use { Hook, Vm, RuntimeError, Value };
+
+// The environment variables a program can see. Programs see the environment
+// of the interpreter by default, which can be cleared for reproducible runs.
+#[derive(Debug, Clone)]
+pub struct Environment {
+    pub inherit: bool,
+    // Variables that are set in place of, or in addition to, the inherited ones
+    pub overrides: Vec<(String, String)>
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Environment {
+            inherit: true,
+            overrides: Vec::<(String, String)>::new()
+        }
+    }
+}
+
+impl Environment {
+    pub fn variables(&self) -> HashMap<String, String> {
+        let mut variables = HashMap::<String, String>::new();
+
+        if self.inherit {
+            for (name, value) in env::vars_os() {
+                variables.insert(name.to_string_lossy().to_string(), value.to_string_lossy().to_string());
+            }
+        }
+
+        for (name, value) in &self.overrides {
+            variables.insert(name.to_string(), value.to_string());
+        }
+
+        variables
+    }
+}
+
+// The arguments of the program, separated by new lines
+pub fn native_args<H: Hook>(vm: &mut Vm<H>) -> Result<(), RuntimeError> {
+    let args = vm.args.join("\n");
+    vm.push(Value::String(args))?;
+    Ok(())
+}
+
+pub fn native_arg<H: Hook>(vm: &mut Vm<H>) -> Result<(), RuntimeError> {
+    let index = vm.pop_int()?;
+
+    match usize::try_from(index).ok().and_then(|i| vm.args.get(i)) {
+        Some(arg) => {
+            let arg = arg.to_string();
+            vm.push(Value::String(arg))?;
+            Ok(())
+        },
+        None => Err(RuntimeError::ArgumentOutOfRange { index, count: vm.args.len() })
+    }
+}
+
+pub fn native_arg_count<H: Hook>(vm: &mut Vm<H>) -> Result<(), RuntimeError> {
+    let count = vm.args.len() as i64;
+    vm.push(Value::Int(count))?;
+    Ok(())
+}
+
+// Variables that aren't set are empty
+pub fn native_env<H: Hook>(vm: &mut Vm<H>) -> Result<(), RuntimeError> {
+    let name = vm.pop_string()?;
+
+    let value = vm.environment.get(&name).cloned().unwrap_or_default();
+    vm.push(Value::String(value))?;
+    Ok(())
+}
+
+// Every variable as `NAME=value`, sorted and separated by new lines
+pub fn native_env_vars<H: Hook>(vm: &mut Vm<H>) -> Result<(), RuntimeError> {
+    let mut variables = vm.environment.iter()
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect::<Vec<String>>();
+    variables.sort();
+
+    vm.push(Value::String(variables.join("\n")))?;
+    Ok(())
+}
@@ -0,0 +1,202 @@
+use std::{ fmt, io, time::Duration };
+
+use debug::Position;
+use filesystem::Access;
+use limits;
+
+// Where a runtime error happened, as the function and the byte offset of the instruction
+// within it, along with where that instruction came from if the program has debug info
+#[derive(Debug, Clone, PartialEq)]
+pub struct Location {
+    pub function: String,
+    pub offset: usize,
+    pub position: Option<Position>
+}
+
+// Errors from loading bytecode, before any of it is run
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    // The file doesn't start with the `NENC` magic bytes
+    NotBytecode,
+    // A length in the file goes past its end
+    Truncated,
+    UnknownOpcode { function: String, offset: usize, opcode: u8 },
+    // An operand is cut off, or isn't valid for its instruction
+    InvalidOperand { function: String, offset: usize },
+    MissingMain,
+    // A function calls a built-in that this VM doesn't implement with that many arguments,
+    // such as when the program was compiled by a newer compiler
+    UnknownBuiltin { function: String, name: String, arguments: usize },
+    // The debug section is cut off or malformed
+    InvalidDebugInfo
+}
+
+// Any error from running a program, as returned by `interpret`
+#[derive(Debug)]
+pub enum Error {
+    Load(LoadError),
+    // Along with the calls it happened in, from the innermost call outwards
+    Runtime(RuntimeError, Vec<Location>)
+}
+
+#[derive(Debug)]
+pub enum RuntimeError {
+    // Input was read after the end of standard input was reached
+    EndOfInput,
+    InvalidInteger(String),
+    PermissionDenied { access: Access, path: String },
+    ArgumentOutOfRange { index: i64, count: usize },
+    UnknownFunction(String),
+    // The host called a function with a different number of arguments than it takes
+    WrongArgumentCount { function: String, expected: usize, found: usize },
+    UnknownNative(String),
+    // A native function was declared in nen with a different number of
+    // parameters, or as pure when it was registered as impure
+    NativeMismatch(String),
+    // Raised by native functions of the host
+    Native(String),
+    // An instruction needed more values than were on the stack
+    StackUnderflow,
+    // An instruction or native function was given a value of the wrong type
+    WrongType { expected: &'static str },
+    // A local was read before anything was stored in it
+    UninitializedLocal(usize),
+    // The program called `exit`, which unwinds every call
+    Exit(i64),
+    // The program exited with a status outside of 0 to 255, which would be truncated
+    InvalidExitStatus(i64),
+    // The program ran more instructions than it was allowed to
+    OutOfFuel,
+    // The program ran for longer than it was allowed to
+    Timeout(Duration),
+    // Calls were nested too deeply, such as by a function that always calls itself
+    StackOverflow,
+    // The values held by the program took up more memory than it was allowed to use
+    OutOfMemory(usize),
+    // The program was stopped by a hook, such as by quitting the debugger
+    Interrupted,
+    // An error from accessing a file, along with its path
+    File(String, io::Error),
+    Io(io::Error)
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuntimeError::EndOfInput => write!(f, "Tried to read input, but the end of input was reached"),
+            RuntimeError::InvalidInteger(line) => write!(f, "Expected an integer as input, found '{}'", line),
+            RuntimeError::PermissionDenied { access: Access::Read, path } => {
+                write!(f, "Permission denied to read '{}', run with --allow-read={} to allow it", path, path)
+            },
+            RuntimeError::PermissionDenied { access: Access::Write, path } => {
+                write!(f, "Permission denied to write '{}', run with --allow-write={} to allow it", path, path)
+            },
+            RuntimeError::ArgumentOutOfRange { index, count } => {
+                write!(f, "Tried to get argument {}, but the program was only given {} arguments", index, count)
+            },
+            RuntimeError::UnknownFunction(name) => write!(f, "Function '{}' is not defined", name),
+            RuntimeError::WrongArgumentCount { function, expected, found } => {
+                write!(f, "Function '{}' takes {} arguments, but was called with {}", function, expected, found)
+            },
+            RuntimeError::UnknownNative(name) => write!(f, "Native function '{}' was called, but not registered", name),
+            RuntimeError::NativeMismatch(name) => write!(f, "Native function '{}' was declared differently to how it was registered", name),
+            RuntimeError::Native(message) => write!(f, "{}", message),
+            RuntimeError::StackUnderflow => write!(f, "Tried to take a value from the stack, but it was empty"),
+            RuntimeError::WrongType { expected } => write!(f, "Expected {} on the stack", expected),
+            RuntimeError::UninitializedLocal(index) => write!(f, "Local {} was read before it was stored", index),
+            RuntimeError::Exit(code) => write!(f, "Exited with status {}", code),
+            RuntimeError::InvalidExitStatus(code) => write!(f, "Exit status must be between 0 and 255, found {}", code),
+            RuntimeError::OutOfFuel => write!(f, "The program ran more instructions than it was allowed to"),
+            RuntimeError::Timeout(timeout) => write!(f, "The program ran for longer than {:?}", timeout),
+            RuntimeError::OutOfMemory(limit) => write!(f, "The program used more than {} bytes of memory", limit),
+            RuntimeError::StackOverflow => write!(f, "Calls were nested more than {} deep", limits::MAX_CALL_DEPTH),
+            RuntimeError::Interrupted => write!(f, "The program was stopped before it finished"),
+            RuntimeError::File(path, e) => write!(f, "Could not access '{}': {}", path, e),
+            RuntimeError::Io(e) => write!(f, "{}", e)
+        }
+    }
+}
+
+impl From<io::Error> for RuntimeError {
+    fn from(error: io::Error) -> Self {
+        RuntimeError::Io(error)
+    }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io(e) => write!(f, "Could not read bytecode: {}", e),
+            LoadError::NotBytecode => write!(f, "The file is not nen bytecode"),
+            LoadError::Truncated => write!(f, "The bytecode is cut off"),
+            LoadError::UnknownOpcode { function, offset, opcode } => {
+                write!(f, "Unknown opcode {:#04x} in function '{}' at offset {}", opcode, function, offset)
+            },
+            LoadError::InvalidOperand { function, offset } => {
+                write!(f, "Invalid operand in function '{}' at offset {}", function, offset)
+            },
+            LoadError::MissingMain => write!(f, "The program has no main function"),
+            LoadError::UnknownBuiltin { function, name, arguments } => {
+                write!(f, "Function '{}' calls built-in '{}' with {} arguments, which this interpreter doesn't implement", function, name, arguments)
+            },
+            LoadError::InvalidDebugInfo => write!(f, "The debug info of the bytecode is invalid")
+        }
+    }
+}
+
+impl From<io::Error> for LoadError {
+    fn from(error: io::Error) -> Self {
+        LoadError::Io(error)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Load(e) => write!(f, "{}", e),
+            Error::Runtime(e, _) => write!(f, "{}", e)
+        }
+    }
+}
+
+impl Error {
+    // Each kind of error exits with its own status, so scripts can tell them apart.
+    // These are above the statuses that programs usually exit with themselves, but
+    // a program can still exit with any of them, so they don't prove an error happened.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Load(LoadError::Io(_)) => 100,
+            Error::Load(LoadError::NotBytecode) => 101,
+            Error::Load(LoadError::Truncated) => 102,
+            Error::Load(LoadError::UnknownOpcode { .. }) => 103,
+            Error::Load(LoadError::InvalidOperand { .. }) => 104,
+            Error::Load(LoadError::MissingMain) => 105,
+            Error::Load(LoadError::InvalidDebugInfo) => 106,
+            Error::Load(LoadError::UnknownBuiltin { .. }) => 107,
+            Error::Runtime(e, _) => match e {
+                RuntimeError::Exit(code) => *code as i32,
+                RuntimeError::EndOfInput => 110,
+                RuntimeError::InvalidInteger(_) => 111,
+                RuntimeError::PermissionDenied { .. } => 112,
+                RuntimeError::ArgumentOutOfRange { .. } => 113,
+                RuntimeError::File(_, _) => 114,
+                RuntimeError::Io(_) => 115,
+                RuntimeError::InvalidExitStatus(_) => 116,
+                RuntimeError::OutOfFuel => 120,
+                RuntimeError::Timeout(_) => 121,
+                RuntimeError::StackOverflow => 122,
+                RuntimeError::OutOfMemory(_) => 123,
+                RuntimeError::Interrupted => 124,
+                RuntimeError::UnknownFunction(_) => 130,
+                RuntimeError::UnknownNative(_) => 131,
+                RuntimeError::NativeMismatch(_) => 132,
+                RuntimeError::Native(_) => 133,
+                RuntimeError::WrongArgumentCount { .. } => 134,
+                RuntimeError::StackUnderflow => 140,
+                RuntimeError::WrongType { .. } => 141,
+                RuntimeError::UninitializedLocal(_) => 142
+            }
+        }
+    }
+}
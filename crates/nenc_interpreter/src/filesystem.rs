@@ -0,0 +1,156 @@
+use std::{ env, fs, io::Write, path::{ Component, Path, PathBuf } };
+
+use { Hook, Vm, RuntimeError, Value };
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Access {
+    Read,
+    Write
+}
+
+// Paths that a program is allowed to access, such as from `--allow-read=./data`.
+// Nothing can be accessed by default.
+#[derive(Debug, Clone, Default)]
+pub struct Capability {
+    // Set by `--allow-read` without a path
+    pub all: bool,
+    pub paths: Vec<PathBuf>
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Permissions {
+    pub read: Capability,
+    pub write: Capability
+}
+
+impl Capability {
+    fn allows(&self, path: &Path) -> bool {
+        self.all || self.paths.iter().filter_map(|allowed| resolve(allowed)).any(|allowed| path.starts_with(allowed))
+    }
+}
+
+impl Permissions {
+    pub fn check(&self, access: Access, path: &str) -> Result<PathBuf, RuntimeError> {
+        let capability = match access {
+            Access::Read => &self.read,
+            Access::Write => &self.write
+        };
+
+        match resolve(Path::new(path)) {
+            Some(resolved) if capability.allows(&resolved) => Ok(resolved),
+            _ => Err(RuntimeError::PermissionDenied { access, path: path.to_string() })
+        }
+    }
+}
+
+// Resolves symbolic links and `..` in as much of the path as exists, so
+// that a path can't escape an allowed directory through either of them.
+// `None` if part of the path is a link that can't be resolved, such as one to
+// a file that doesn't exist yet, which writing would create wherever it points.
+fn resolve(path: &Path) -> Option<PathBuf> {
+    let absolute = env::current_dir().unwrap_or_default().join(path);
+    let mut existing = absolute.as_path();
+    let mut rest = Vec::<Component>::new();
+
+    loop {
+        if let Ok(canonical) = fs::canonicalize(existing) {
+            let mut resolved = canonical;
+            for component in rest.iter().rev() {
+                match component {
+                    Component::ParentDir => { resolved.pop(); },
+                    Component::Normal(name) => resolved.push(name),
+                    _ => {}
+                }
+            }
+            return Some(resolved);
+        }
+
+        // Something is there, but couldn't be followed
+        if fs::symlink_metadata(existing).is_ok() {
+            return None;
+        }
+
+        match (existing.parent(), existing.components().next_back()) {
+            (Some(parent), Some(component)) => {
+                rest.push(component);
+                existing = parent;
+            },
+            _ => return Some(absolute)
+        }
+    }
+}
+
+pub fn native_read_file<H: Hook>(vm: &mut Vm<H>) -> Result<(), RuntimeError> {
+    let path = vm.pop_string()?;
+    let resolved = vm.permissions.check(Access::Read, &path)?;
+
+    // Checked before reading, so that reading a large file can't use up the host's memory
+    let size = fs::metadata(&resolved).map_err(|e| RuntimeError::File(path.clone(), e))?.len();
+    vm.budget.reserve(size as usize)?;
+
+    let contents = fs::read_to_string(resolved).map_err(|e| RuntimeError::File(path, e))?;
+    vm.push(Value::String(contents))?;
+    Ok(())
+}
+
+pub fn native_write_file<H: Hook>(vm: &mut Vm<H>) -> Result<(), RuntimeError> {
+    let contents = vm.pop_string()?;
+    let path = vm.pop_string()?;
+    let resolved = vm.permissions.check(Access::Write, &path)?;
+
+    fs::write(resolved, contents).map_err(|e| RuntimeError::File(path, e))?;
+    Ok(())
+}
+
+pub fn native_append_file<H: Hook>(vm: &mut Vm<H>) -> Result<(), RuntimeError> {
+    let contents = vm.pop_string()?;
+    let path = vm.pop_string()?;
+    let resolved = vm.permissions.check(Access::Write, &path)?;
+
+    let mut file = fs::OpenOptions::new().append(true).create(true).open(resolved)
+        .map_err(|e| RuntimeError::File(path.clone(), e))?;
+    file.write_all(contents.as_bytes()).map_err(|e| RuntimeError::File(path, e))?;
+    Ok(())
+}
+
+// The names of the entries in a directory, sorted and separated by new lines
+pub fn native_list_dir<H: Hook>(vm: &mut Vm<H>) -> Result<(), RuntimeError> {
+    let path = vm.pop_string()?;
+    let resolved = vm.permissions.check(Access::Read, &path)?;
+
+    let mut names = Vec::<String>::new();
+    for entry in fs::read_dir(resolved).map_err(|e| RuntimeError::File(path.clone(), e))? {
+        let entry = entry.map_err(|e| RuntimeError::File(path.clone(), e))?;
+        names.push(entry.file_name().to_string_lossy().to_string());
+    }
+    names.sort();
+
+    // Names aren't escaped, so one containing a new line reads as several
+    vm.push(Value::String(names.join("\n")))?;
+    Ok(())
+}
+
+pub fn native_delete_file<H: Hook>(vm: &mut Vm<H>) -> Result<(), RuntimeError> {
+    let path = vm.pop_string()?;
+    let resolved = vm.permissions.check(Access::Write, &path)?;
+
+    fs::remove_file(resolved).map_err(|e| RuntimeError::File(path, e))?;
+    Ok(())
+}
+
+pub fn native_create_dir<H: Hook>(vm: &mut Vm<H>) -> Result<(), RuntimeError> {
+    let path = vm.pop_string()?;
+    let resolved = vm.permissions.check(Access::Write, &path)?;
+
+    fs::create_dir_all(resolved).map_err(|e| RuntimeError::File(path, e))?;
+    Ok(())
+}
+
+// Only empty directories can be deleted
+pub fn native_delete_dir<H: Hook>(vm: &mut Vm<H>) -> Result<(), RuntimeError> {
+    let path = vm.pop_string()?;
+    let resolved = vm.permissions.check(Access::Write, &path)?;
+
+    fs::remove_dir(resolved).map_err(|e| RuntimeError::File(path, e))?;
+    Ok(())
+}
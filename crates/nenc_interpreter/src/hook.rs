@@ -0,0 +1,88 @@
+use { DebugInfo, Instruction, RuntimeError, Value };
+
+// What a hook can see of the call that is running
+pub struct Frame<'f> {
+    pub function: &'f str,
+    // The byte offset of the instruction within the function
+    pub offset: usize,
+    // How many calls deep the function is, starting from 1 for the function the host called
+    pub depth: usize,
+    pub locals: &'f [Value],
+    pub stack: &'f [Value],
+    pub debug: Option<&'f DebugInfo>
+}
+
+// Called by the VM as it runs a program, such as by the debugger. The VM is
+// generic over its hook, so that running without one costs nothing.
+pub trait Hook {
+    // Called before each instruction is run, and stops the program if it returns an error
+    fn instruction(&mut self, _frame: &Frame, _instruction: &Instruction) -> Result<(), RuntimeError> {
+        Ok(())
+    }
+
+    // Called when a function of the program is called, before its first instruction
+    fn enter(&mut self, _function: &str) {}
+
+    // Called when a call ends, including when an error ends it
+    fn leave(&mut self, _function: &str) {}
+}
+
+// The hook of a VM that isn't being watched
+pub struct NoHook;
+
+impl Hook for NoHook {}
+
+// So that the host can keep its hook, and look at what it recorded once the program has run
+impl<H: Hook> Hook for &mut H {
+    fn instruction(&mut self, frame: &Frame, instruction: &Instruction) -> Result<(), RuntimeError> {
+        (**self).instruction(frame, instruction)
+    }
+
+    fn enter(&mut self, function: &str) {
+        (**self).enter(function)
+    }
+
+    fn leave(&mut self, function: &str) {
+        (**self).leave(function)
+    }
+}
+
+// A hook that can be turned on and off, such as by a command-line flag
+impl<H: Hook> Hook for Option<H> {
+    fn instruction(&mut self, frame: &Frame, instruction: &Instruction) -> Result<(), RuntimeError> {
+        match self {
+            Some(hook) => hook.instruction(frame, instruction),
+            None => Ok(())
+        }
+    }
+
+    fn enter(&mut self, function: &str) {
+        if let Some(hook) = self {
+            hook.enter(function);
+        }
+    }
+
+    fn leave(&mut self, function: &str) {
+        if let Some(hook) = self {
+            hook.leave(function);
+        }
+    }
+}
+
+// Both hooks are called, in order
+impl<A: Hook, B: Hook> Hook for (A, B) {
+    fn instruction(&mut self, frame: &Frame, instruction: &Instruction) -> Result<(), RuntimeError> {
+        self.0.instruction(frame, instruction)?;
+        self.1.instruction(frame, instruction)
+    }
+
+    fn enter(&mut self, function: &str) {
+        self.0.enter(function);
+        self.1.enter(function);
+    }
+
+    fn leave(&mut self, function: &str) {
+        self.0.leave(function);
+        self.1.leave(function);
+    }
+}
@@ -1,184 +1,859 @@
-use std::{io::Read, collections::HashMap};
+mod coverage;
+mod dap;
+mod debug;
+mod debugger;
+mod environment;
+mod error;
+mod filesystem;
+mod hook;
+mod json;
+mod limits;
+mod profiler;
+mod stats;
+mod trace;
+
+use std::{io::{ self, BufRead, BufReader, BufWriter, Read, Write }, collections::HashMap, fmt, mem, rc::Rc};
+
+use environment::{ native_arg, native_arg_count, native_args, native_env, native_env_vars };
+use filesystem::{ native_append_file, native_create_dir, native_delete_dir, native_delete_file, native_list_dir, native_read_file, native_write_file };
+pub use coverage::Coverage;
+pub use dap::serve_dap;
+pub use debug::{ DebugInfo, FunctionDebug, Position };
+pub use debugger::{ Breakpoint, Debugger };
+pub use environment::Environment;
+pub use error::{ Error, LoadError, Location, RuntimeError };
+pub use filesystem::{ Access, Capability, Permissions };
+pub use hook::{ Frame, Hook, NoHook };
+pub use limits::{ parse_duration, parse_size, Limits };
+pub use profiler::{ FunctionProfile, Profiler };
+pub use stats::Stats;
+pub use trace::Tracer;
+use limits::Budget;
+
+#[derive(Debug, Clone, Default)]
+pub struct InterpreterOptions {
+    // Paths the program is allowed to read and write
+    pub permissions: Permissions,
+    // Arguments passed to the program, such as with `nen run prog.nen -- a b`
+    pub args: Vec<String>,
+    pub environment: Environment,
+    pub limits: Limits
+}
+
+// A loaded program, which the host can call the functions of any number of times
+pub struct Vm<'a, H: Hook = NoHook> {
+    stack: Vec<Value>,
+    // The instructions of each function, with their byte offsets
+    scope: HashMap<String, Rc<Vec<(usize, Instruction)>>>,
+    // The number of parameters of each function, so that calls from the host can be checked
+    parameters: HashMap<String, usize>,
+    // Native functions registered by the host, by name
+    natives: HashMap<String, HostFunction<'a>>,
+    input: Box<dyn BufRead + 'a>,
+    output: Box<dyn Write + 'a>,
+    // Where `eprint` writes to, which is standard error by default
+    error_output: Box<dyn Write + 'a>,
+    permissions: Permissions,
+    args: Vec<String>,
+    environment: HashMap<String, String>,
+    limits: Limits,
+    // What is left of the limits of the current call
+    budget: Budget,
+    // Where the last runtime error happened, from the innermost call outwards
+    trace: Vec<Location>,
+    debug: Option<DebugInfo>,
+    hook: H
+}
+
+// Called with the arguments of the call, in order, and
+// returns the return value of the function, if it has one
+pub type HostCallback<'a> = Box<dyn FnMut(&[Value]) -> Result<Option<Value>, RuntimeError> + 'a>;
+
+struct HostFunction<'a> {
+    arity: usize,
+    impure: bool,
+    callback: HostCallback<'a>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Int(i64)
+}
 
-#[derive(Debug)]
-struct Interpreter {
-    stack: Vec<StackElement>,
-    scope: HashMap<String, Vec<Instruction>>
+impl Value {
+    // The bytes of memory the value holds, which count towards the memory limit
+    fn size(&self) -> usize {
+        mem::size_of::<Value>() + match self {
+            Value::String(s) => s.capacity(),
+            Value::Int(_) => 0
+        }
+    }
 }
 
+#[derive(Debug, Clone, Copy)]
+enum Alignment {
+    Default,
+    Left,
+    Right,
+    Center
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct FormatSpec {
+    align: Alignment,
+    width: usize,
+    precision: Option<usize>
+}
+
+// How a native function was declared, so that host functions can be checked against it
 #[derive(Debug, Clone)]
-enum StackElement {
-    String(String)
+pub struct NativeCall {
+    name: String,
+    arguments: usize,
+    impure: bool,
+    returns_value: bool
 }
 
 #[derive(Debug, Clone)]
-enum Instruction {
+pub enum Instruction {
     PushString(String),
+    PushInt(i64),
     Write,
-    Call(String)
+    ReadLine,
+    ReadAll,
+    ReadInt,
+    ReadFullLine,
+    AtEof,
+    WriteError,
+    Flush,
+    Call(String),
+    CallNative(NativeCall),
+    ToString(FormatSpec),
+    Concat(usize),
+    LoadLocal(usize),
+    StoreLocal(usize),
+    Pop,
+    Return,
+    Exit
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Instruction::PushString(s) => write!(f, "PushString {:?}", s),
+            Instruction::PushInt(i) => write!(f, "PushInt {}", i),
+            Instruction::Call(name) => write!(f, "Call {}", name),
+            Instruction::CallNative(call) => write!(f, "CallNative {} {}", call.name, call.arguments),
+            Instruction::Concat(count) => write!(f, "Concat {}", count),
+            Instruction::LoadLocal(index) => write!(f, "LoadLocal {}", index),
+            Instruction::StoreLocal(index) => write!(f, "StoreLocal {}", index),
+            _ => write!(f, "{}", self.name())
+        }
+    }
+}
+
+impl Instruction {
+    // The name of the instruction's opcode, without its operands
+    pub fn name(&self) -> &'static str {
+        match self {
+            Instruction::PushString(_) => "PushString",
+            Instruction::PushInt(_) => "PushInt",
+            Instruction::Write => "Write",
+            Instruction::ReadLine => "ReadLine",
+            Instruction::ReadAll => "ReadAll",
+            Instruction::ReadFullLine => "ReadFullLine",
+            Instruction::AtEof => "AtEof",
+            Instruction::ReadInt => "ReadInt",
+            Instruction::WriteError => "WriteError",
+            Instruction::Flush => "Flush",
+            Instruction::Call(_) => "Call",
+            Instruction::CallNative(_) => "CallNative",
+            Instruction::ToString(_) => "ToString",
+            Instruction::Concat(_) => "Concat",
+            Instruction::LoadLocal(_) => "LoadLocal",
+            Instruction::StoreLocal(_) => "StoreLocal",
+            Instruction::Pop => "Pop",
+            Instruction::Return => "Return",
+            Instruction::Exit => "Exit"
+        }
+    }
 }
 
 fn instruction_from_opcode(opcode: u8) -> Option<Instruction> {
    match opcode {
         0x12 => Some(Instruction::Write),
+        0x13 => Some(Instruction::ReadLine),
+        0x14 => Some(Instruction::ReadAll),
+        0x15 => Some(Instruction::ReadInt),
+        0x16 => Some(Instruction::WriteError),
+        0x17 => Some(Instruction::Flush),
+        0x18 => Some(Instruction::AtEof),
+        0x19 => Some(Instruction::ReadFullLine),
         0xA1 => Some(Instruction::Call(String::new())),
+        0xA2 => Some(Instruction::CallNative(NativeCall { name: String::new(), arguments: 0, impure: false, returns_value: false })),
         0xE1 => Some(Instruction::PushString(String::new())),
+        0xE2 => Some(Instruction::PushInt(0)),
+        0x30 => Some(Instruction::ToString(FormatSpec { align: Alignment::Default, width: 0, precision: None })),
+        0x31 => Some(Instruction::Concat(0)),
+        0x20 => Some(Instruction::LoadLocal(0)),
+        0x21 => Some(Instruction::StoreLocal(0)),
+        0x01 => Some(Instruction::Pop),
+        0x02 => Some(Instruction::Return),
+        0x03 => Some(Instruction::Exit),
         _ => None
     } 
 }
 
-fn get_byte_string_from_idx(code: &[u8], idx: &mut usize) -> String {
-    let string_len_bytes = &code[*idx..*idx+2];
-    let string_len = u16::from_be_bytes([
-        string_len_bytes[0],
-        string_len_bytes[1]
-    ]) as usize;
+// Operands that are cut off or invalid are `None`, and reported along with where they are
 
-    if *idx + 2 + string_len > code.len() {
-        panic!("String out of bounds!");
-    } 
+fn get_operand_bytes<'a>(code: &'a [u8], idx: &mut usize, length: usize) -> Option<&'a [u8]> {
+    let bytes = code.get(*idx..*idx + length)?;
+    *idx += length;
+
+    Some(bytes)
+}
+
+fn get_u16_from_idx(code: &[u8], idx: &mut usize) -> Option<u16> {
+    let bytes = get_operand_bytes(code, idx, 2)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+fn get_u32_from_idx(code: &[u8], idx: &mut usize) -> Option<u32> {
+    let bytes = get_operand_bytes(code, idx, 4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn get_i64_from_idx(code: &[u8], idx: &mut usize) -> Option<i64> {
+    let bytes = get_operand_bytes(code, idx, 8)?;
+    let mut int_bytes = [0u8; 8];
+    int_bytes.copy_from_slice(bytes);
+    Some(i64::from_be_bytes(int_bytes))
+}
+
+fn get_byte_string_from_idx(code: &[u8], idx: &mut usize) -> Option<String> {
+    let string_len = get_u16_from_idx(code, idx)? as usize;
+    let string_bytes = get_operand_bytes(code, idx, string_len)?;
+
+    std::str::from_utf8(string_bytes).ok().map(str::to_string)
+}
+
+fn get_format_spec_from_idx(code: &[u8], idx: &mut usize) -> Option<FormatSpec> {
+    let align = match get_operand_bytes(code, idx, 1)?[0] {
+        0 => Alignment::Default,
+        1 => Alignment::Left,
+        2 => Alignment::Right,
+        3 => Alignment::Center,
+        _ => return None
+    };
+    let width = get_u16_from_idx(code, idx)? as usize;
+    let precision = match get_u16_from_idx(code, idx)? {
+        u16::MAX => None,
+        p => Some(p as usize)
+    };
+
+    Some(FormatSpec { align, width, precision })
+}
+
+fn format_element(element: Value, format: FormatSpec) -> String {
+    let (string, default_align) = match element {
+        Value::String(mut s) => {
+            // Precision truncates strings to a number of characters
+            if let Some((end, _)) = format.precision.and_then(|p| s.char_indices().nth(p)) {
+                s.truncate(end);
+            }
+            (s, Alignment::Left)
+        },
+        Value::Int(i) => (i.to_string(), Alignment::Right)
+    };
+
+    let length = string.chars().count();
+    if length >= format.width {
+        return string;
+    }
 
-    *idx += 2;
+    let padding = format.width - length;
+    let (before, after) = match (format.align, default_align) {
+        (Alignment::Left, _) | (Alignment::Default, Alignment::Left) => (0, padding),
+        (Alignment::Center, _) => (padding / 2, padding - padding / 2),
+        _ => (padding, 0)
+    };
+
+    let mut padded = String::with_capacity(string.len() + padding);
+    padded.extend(std::iter::repeat_n(' ', before));
+    padded.push_str(&string);
+    padded.extend(std::iter::repeat_n(' ', after));
+    padded
+}
 
-    let string_bytes = &code[*idx..*idx+string_len];
-    let string = std::str::from_utf8(string_bytes).unwrap();
+type NativeFunction<H> = fn(&mut Vm<H>) -> Result<(), RuntimeError>;
+
+// Functions implemented by the VM, called with the `CallNative` instruction,
+// along with the number of arguments each takes. Calls to the compiler's native
+// built-ins are checked against these when a program is loaded, and these take
+// priority over native functions registered by the host.
+fn native_function<H: Hook>(name: &str) -> Option<(usize, NativeFunction<H>)> {
+    match name {
+        "length" => Some((1, native_length)),
+        "read_file" => Some((1, native_read_file)),
+        "write_file" => Some((2, native_write_file)),
+        "append_file" => Some((2, native_append_file)),
+        "list_dir" => Some((1, native_list_dir)),
+        "delete_file" => Some((1, native_delete_file)),
+        "create_dir" => Some((1, native_create_dir)),
+        "delete_dir" => Some((1, native_delete_dir)),
+        "args" => Some((0, native_args)),
+        "arg" => Some((1, native_arg)),
+        "arg_count" => Some((0, native_arg_count)),
+        "env" => Some((1, native_env)),
+        "env_vars" => Some((0, native_env_vars)),
+        _ => None
+    }
+}
 
-    *idx += string_len;
+// The number of arguments taken by a function implemented by the VM,
+// so that the compiler's built-ins can be checked against them
+pub fn native_arity(name: &str) -> Option<usize> {
+    native_function::<NoHook>(name).map(|(arity, _)| arity)
+}
 
-    string.to_string()
+fn native_length<H: Hook>(vm: &mut Vm<H>) -> Result<(), RuntimeError> {
+    let string = vm.pop_string()?;
+    vm.push(Value::Int(string.chars().count() as i64))
 }
 
-fn parse_instructions(instructions: &[u8]) -> Vec<Instruction> {
-    let mut instructions_vec = Vec::<Instruction>::new();
+// Each instruction is paired with its byte offset within the function, to report where errors happen
+fn parse_instructions(function: &str, instructions: &[u8]) -> Result<Vec<(usize, Instruction)>, LoadError> {
+    let mut instructions_vec = Vec::<(usize, Instruction)>::new();
 
     let mut idx = 0;
 
     while idx < instructions.len() {
+        let offset = idx;
+        let opcode = instructions[idx];
         idx += 1;
-        match instruction_from_opcode(instructions[idx - 1]) {
-            Some(inst) => {
-                match inst {
-                    Instruction::Write => instructions_vec.push(Instruction::Write),
-                    Instruction::Call(_) => {
-                        let str = get_byte_string_from_idx(instructions, &mut idx);
-                        instructions_vec.push(Instruction::Call(str));
+
+        let inst = match instruction_from_opcode(opcode) {
+            Some(inst) => inst,
+            None => return Err(LoadError::UnknownOpcode { function: function.to_string(), offset, opcode })
+        };
+
+        let parsed = match inst {
+            Instruction::Write |
+            Instruction::ReadLine |
+            Instruction::ReadAll |
+            Instruction::AtEof |
+            Instruction::ReadFullLine |
+            Instruction::ReadInt |
+            Instruction::WriteError |
+            Instruction::Flush |
+            Instruction::Pop |
+            Instruction::Return |
+            Instruction::Exit => Some(inst),
+            Instruction::Call(_) => get_byte_string_from_idx(instructions, &mut idx).map(Instruction::Call),
+            Instruction::CallNative(_) => {
+                let name = get_byte_string_from_idx(instructions, &mut idx);
+                let operands = get_operand_bytes(instructions, &mut idx, 2);
+                match (name, operands) {
+                    (Some(name), Some(&[arguments, flags])) => {
+                        // Calls to built-ins are checked now, so that a built-in
+                        // missing from this VM is found before the program runs
+                        if flags & 0b100 != 0 && native_arity(&name) != Some(arguments as usize) {
+                            return Err(LoadError::UnknownBuiltin { function: function.to_string(), name, arguments: arguments as usize });
+                        }
+                        Some(Instruction::CallNative(NativeCall {
+                            name,
+                            arguments: arguments as usize,
+                            impure: flags & 0b001 != 0,
+                            returns_value: flags & 0b010 != 0
+                        }))
                     },
-                    Instruction::PushString(_) => {
-                        let str = get_byte_string_from_idx(instructions, &mut idx);
-                        instructions_vec.push(Instruction::PushString(str));
-                    }
+                    _ => None
                 }
             },
-            None => panic!("Unrecognized opcode: {:?}", instructions[idx - 1])
+            Instruction::PushString(_) => get_byte_string_from_idx(instructions, &mut idx).map(Instruction::PushString),
+            Instruction::PushInt(_) => get_i64_from_idx(instructions, &mut idx).map(Instruction::PushInt),
+            Instruction::ToString(_) => get_format_spec_from_idx(instructions, &mut idx).map(Instruction::ToString),
+            Instruction::Concat(_) => get_u16_from_idx(instructions, &mut idx).map(|count| Instruction::Concat(count as usize)),
+            Instruction::LoadLocal(_) => get_u16_from_idx(instructions, &mut idx).map(|index| Instruction::LoadLocal(index as usize)),
+            Instruction::StoreLocal(_) => get_u16_from_idx(instructions, &mut idx).map(|index| Instruction::StoreLocal(index as usize))
+        };
+
+        match parsed {
+            Some(inst) => instructions_vec.push((offset, inst)),
+            None => return Err(LoadError::InvalidOperand { function: function.to_string(), offset })
         }
     }
 
-    instructions_vec
+    Ok(instructions_vec)
 }
 
-impl Interpreter {
-    fn new() -> Self {
-        Interpreter {
-            stack: Vec::<StackElement>::new(),
-            scope: HashMap::<String, Vec<Instruction>>::new()
+impl<'a> Vm<'a> {
+    // Loads the bytecode read from `readable`, using standard input, output and error.
+    // Standard output is buffered, and flushed when a call returns or input is read.
+    pub fn load(readable: impl Read, options: InterpreterOptions) -> Result<Vm<'a>, LoadError> {
+        Vm::load_with_hook(readable, options, NoHook)
+    }
+}
+
+impl<'a, H: Hook> Vm<'a, H> {
+    // Loads the bytecode like `load`, with `hook` called as the program runs
+    pub fn load_with_hook(mut readable: impl Read, options: InterpreterOptions, hook: H) -> Result<Vm<'a, H>, LoadError> {
+        let mut nenc: Vec<u8> = Vec::<u8>::new();
+        readable.read_to_end(&mut nenc)?;
+
+        let mut vm = Vm {
+            stack: Vec::<Value>::new(),
+            scope: HashMap::<String, Rc<Vec<(usize, Instruction)>>>::new(),
+            parameters: HashMap::<String, usize>::new(),
+            natives: HashMap::<String, HostFunction>::new(),
+            // Not locked, so that the host can still use them while the VM is loaded
+            input: Box::new(BufReader::new(io::stdin())),
+            output: Box::new(BufWriter::new(io::stdout())),
+            error_output: Box::new(io::stderr()),
+            permissions: options.permissions,
+            args: options.args,
+            environment: options.environment.variables(),
+            limits: options.limits,
+            budget: Budget::new(Limits::default()),
+            trace: Vec::<Location>::new(),
+            debug: None,
+            hook
+        };
+
+        vm.load_code(nenc)?;
+
+        Ok(vm)
+    }
+
+    pub fn set_input(&mut self, input: impl BufRead + 'a) {
+        self.input = Box::new(input);
+    }
+
+    pub fn set_output(&mut self, output: impl Write + 'a) {
+        self.output = Box::new(output);
+    }
+
+    pub fn set_error_output(&mut self, error_output: impl Write + 'a) {
+        self.error_output = Box::new(error_output);
+    }
+
+    pub fn flush(&mut self) -> Result<(), RuntimeError> {
+        self.output.flush()?;
+        self.error_output.flush()?;
+        Ok(())
+    }
+
+    // Registers a function that nen code can call once it is declared, such as
+    // with `extern impure func log(message: string);`. Functions that aren't
+    // impure must not have side-effects, as they can be called from pure code.
+    pub fn register_native(&mut self, name: &str, arity: usize, impure: bool, callback: impl FnMut(&[Value]) -> Result<Option<Value>, RuntimeError> + 'a) {
+        self.natives.insert(name.to_string(), HostFunction { arity, impure, callback: Box::new(callback) });
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    pub fn has_function(&self, name: &str) -> bool {
+        self.scope.contains_key(name)
+    }
+
+    // The names of every function of the program, sorted
+    pub fn functions(&self) -> Vec<&str> {
+        let mut names = self.scope.keys().map(String::as_str).collect::<Vec<&str>>();
+        names.sort();
+        names
+    }
+
+    // The calls that the error returned by the last call happened in, from the innermost call outwards
+    pub fn trace(&self) -> &[Location] {
+        &self.trace
+    }
+
+    // The debug info of the program, if it was compiled with it
+    pub fn debug_info(&self) -> Option<&DebugInfo> {
+        self.debug.as_ref()
+    }
+
+    pub fn hook(&self) -> &H {
+        &self.hook
+    }
+
+    pub fn hook_mut(&mut self) -> &mut H {
+        &mut self.hook
+    }
+
+    // Calls a function of the program with `arguments`, and returns its return value, if it has one
+    pub fn call(&mut self, function: &str, arguments: &[Value]) -> Result<Option<Value>, RuntimeError> {
+        let expected = match self.parameters.get(function) {
+            Some(parameters) => *parameters,
+            None => return Err(RuntimeError::UnknownFunction(function.to_string()))
+        };
+
+        // Otherwise arguments would be left over as a return value, or missing from the locals
+        if arguments.len() != expected {
+            return Err(RuntimeError::WrongArgumentCount { function: function.to_string(), expected, found: arguments.len() });
         }
+
+        self.stack.clear();
+        self.budget = Budget::new(self.limits);
+        self.trace.clear();
+        for argument in arguments {
+            self.push(argument.clone())?;
+        }
+
+        // An error from running the function is reported over one from flushing its output
+        let result = self.run(function);
+        let flushed = self.flush();
+        result?;
+        flushed?;
+
+        Ok(self.pop())
     }
 
-    fn interpret_code(&mut self, bytes: Vec<u8>) {
-       let mut idx = 0;
+    // Calls the program's `main` function, and returns the exit status of the program
+    pub fn run_main(&mut self) -> Result<i32, Error> {
+        if !self.has_function("main") {
+            return Err(Error::Load(LoadError::MissingMain));
+        }
+
+        // The exit status is either given to `exit`, or returned from `main`
+        let status = match self.call("main", &[]) {
+            Ok(Some(Value::Int(code))) | Err(RuntimeError::Exit(code)) => code,
+            Ok(_) => 0,
+            Err(e) => return Err(Error::Runtime(e, mem::take(&mut self.trace)))
+        };
 
-        if bytes.len() < 4 {
-            panic!("Invalid NENC file");
+        // Operating systems only keep the lowest 8 bits of the status
+        if !(0..=255).contains(&status) {
+            return Err(Error::Runtime(RuntimeError::InvalidExitStatus(status), mem::take(&mut self.trace)));
         }
 
-        let magic_bytes = &bytes[idx..idx+4];
-        assert_eq!(magic_bytes, &[0x4E, 0x45, 0x4E, 0x43]);
-        idx += 4;
+        Ok(status as i32)
+    }
 
-        if idx + 4 > bytes.len() {
-            panic!("Body length not found");
+    // Values are pushed and popped through these, so that the memory they hold is counted
+    fn push(&mut self, value: Value) -> Result<(), RuntimeError> {
+        self.budget.allocate(value.size())?;
+        self.stack.push(value);
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Option<Value> {
+        let value = self.stack.pop()?;
+        self.budget.free(value.size());
+        Some(value)
+    }
+
+    fn pop_many(&mut self, count: usize) -> Result<Vec<Value>, RuntimeError> {
+        if count > self.stack.len() {
+            return Err(RuntimeError::StackUnderflow);
         }
 
-        let body_len_bytes = &bytes[idx..idx+4];
-        let _body_len = u32::from_be_bytes([
-            body_len_bytes[0],
-            body_len_bytes[1],
-            body_len_bytes[2],
-            body_len_bytes[3],
-        ]);
-        idx += 4;
+        let values = self.stack.split_off(self.stack.len() - count);
+        self.budget.free(values.iter().map(Value::size).sum());
+        Ok(values)
+    }
 
-       
-        while idx < bytes.len() {
-            let function_name_length_bytes = &bytes[idx..idx+2];
-            let function_name_length = u16::from_be_bytes(
-                [function_name_length_bytes[0], function_name_length_bytes[1]]
-            ) as usize;
+    fn pop_value(&mut self) -> Result<Value, RuntimeError> {
+        self.pop().ok_or(RuntimeError::StackUnderflow)
+    }
 
-            if idx + 2 + function_name_length > bytes.len() {
-                panic!("Length of function was longer than function body");
-            }
+    fn pop_string(&mut self) -> Result<String, RuntimeError> {
+        match self.pop_value()? {
+            Value::String(s) => Ok(s),
+            Value::Int(_) => Err(RuntimeError::WrongType { expected: "a string" })
+        }
+    }
+
+    fn pop_int(&mut self) -> Result<i64, RuntimeError> {
+        match self.pop_value()? {
+            Value::Int(i) => Ok(i),
+            Value::String(_) => Err(RuntimeError::WrongType { expected: "an int" })
+        }
+    }
 
-            idx += 2;
-            let function_name_bytes = &bytes[idx..idx+function_name_length];
-            let function_name = std::str::from_utf8(function_name_bytes).unwrap();
-            idx += function_name_length;
-
-            let function_body_length_bytes = &bytes[idx..idx+4];
-            let function_body_length = u32::from_be_bytes([
-                function_body_length_bytes[0],
-                function_body_length_bytes[1],
-                function_body_length_bytes[2],
-                function_body_length_bytes[3],
-            ]) as usize;
-
-            if idx + 4 + function_body_length > bytes.len() {
-                panic!("Length of function was longer than function body");
+    fn call_host_function(&mut self, call: &NativeCall) -> Result<(), RuntimeError> {
+        let arguments = self.pop_many(call.arguments)?;
+        let function = match self.natives.get_mut(&call.name) {
+            Some(f) => f,
+            None => return Err(RuntimeError::UnknownNative(call.name.to_string()))
+        };
+
+        if function.arity != call.arguments || (function.impure && !call.impure) {
+            return Err(RuntimeError::NativeMismatch(call.name.to_string()));
+        }
+
+        match (function.callback)(&arguments)? {
+            Some(value) if call.returns_value => self.push(value)?,
+            None if call.returns_value => return Err(RuntimeError::NativeMismatch(call.name.to_string())),
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    // Reads a line along with its line ending, or `None` at the end of input
+    fn read_full_line(&mut self) -> Result<Option<String>, RuntimeError> {
+        // So that prompts are shown before waiting for input
+        self.output.flush()?;
+
+        let mut bytes = Vec::<u8>::new();
+        match self.budget.remaining() {
+            Some(remaining) => self.input.by_ref().take(remaining as u64 + 1).read_until(b'\n', &mut bytes)?,
+            None => self.input.read_until(b'\n', &mut bytes)?
+        };
+        if bytes.is_empty() {
+            return Ok(None);
+        }
+
+        self.decode_input(bytes).map(Some)
+    }
+
+    // Reads a line without its line ending, or `None` at the end of input
+    fn read_line(&mut self) -> Result<Option<String>, RuntimeError> {
+        let mut line = match self.read_full_line()? {
+            Some(line) => line,
+            None => return Ok(None)
+        };
+
+        if line.ends_with('\n') {
+            line.pop();
+            if line.ends_with('\r') {
+                line.pop();
             }
+        }
+
+        Ok(Some(line))
+    }
+
+    // Input is read as bytes, and checked against the memory limit before it is decoded,
+    // since reading stops at the limit, which can be partway through a character
+    fn decode_input(&self, bytes: Vec<u8>) -> Result<String, RuntimeError> {
+        self.budget.reserve(bytes.len())?;
+        String::from_utf8(bytes).map_err(|e| RuntimeError::Io(io::Error::new(io::ErrorKind::InvalidData, e)))
+    }
+
+    fn load_code(&mut self, bytes: Vec<u8>) -> Result<(), LoadError> {
+        let mut idx = 0;
+
+        match get_operand_bytes(&bytes, &mut idx, 4) {
+            Some(&[0x4E, 0x45, 0x4E, 0x43]) => {},
+            _ => return Err(LoadError::NotBytecode)
+        }
 
-            idx += 4;
+        let body_len = get_u32_from_idx(&bytes, &mut idx).ok_or(LoadError::Truncated)? as usize;
+        let body_end = idx.checked_add(body_len).filter(|end| *end <= bytes.len()).ok_or(LoadError::Truncated)?;
 
-            let function_body = parse_instructions(&bytes[idx..idx+function_body_length]);
-            
-            idx += function_body_length;
+        while idx < body_end {
+            let function_name = get_byte_string_from_idx(&bytes, &mut idx).ok_or(LoadError::Truncated)?;
+            let parameters = get_u16_from_idx(&bytes, &mut idx).ok_or(LoadError::Truncated)? as usize;
+            let function_body_length = get_u32_from_idx(&bytes, &mut idx).ok_or(LoadError::Truncated)? as usize;
+            let function_body = get_operand_bytes(&bytes, &mut idx, function_body_length).ok_or(LoadError::Truncated)?;
 
-            self.scope.insert(function_name.to_string(), function_body);
-      }
+            let instructions = parse_instructions(&function_name, function_body)?;
+            self.parameters.insert(function_name.clone(), parameters);
+            self.scope.insert(function_name, Rc::new(instructions));
+        }
+
+        if idx != body_end {
+            return Err(LoadError::Truncated);
+        }
+
+        // Anything after the functions is the debug section, which is optional
+        if idx < bytes.len() {
+            match get_operand_bytes(&bytes, &mut idx, 4) {
+                Some(b"DBUG") => {},
+                _ => return Err(LoadError::InvalidDebugInfo)
+            }
+            let length = get_u32_from_idx(&bytes, &mut idx).ok_or(LoadError::InvalidDebugInfo)? as usize;
+            let section = get_operand_bytes(&bytes, &mut idx, length).ok_or(LoadError::InvalidDebugInfo)?;
+            self.debug = Some(DebugInfo::parse(section).ok_or(LoadError::InvalidDebugInfo)?);
+        }
+
+        Ok(())
     }
 
-    fn run_instruction(&mut self, instruction: &Instruction) {
+    // `locals` belong to the call of the function being run
+    fn run_instruction(&mut self, instruction: &Instruction, locals: &mut Vec<Value>) -> Result<(), RuntimeError> {
         match instruction {
-            Instruction::Call(s) => self.run(s),
-            Instruction::PushString(s) => self.stack.push(StackElement::String(s.to_string())),
+            Instruction::Call(_) => unreachable!("Calls are run by `run`"),
+            Instruction::CallNative(call) => {
+                match native_function(&call.name) {
+                    Some((arity, _)) if arity != call.arguments => {
+                        return Err(RuntimeError::NativeMismatch(call.name.to_string()));
+                    },
+                    Some((_, function)) => function(self)?,
+                    None => self.call_host_function(call)?
+                }
+            },
+            Instruction::PushString(s) => self.push(Value::String(s.to_string()))?,
+            Instruction::PushInt(i) => self.push(Value::Int(*i))?,
             Instruction::Write => {
-                match self.stack.pop() {
-                    Some(StackElement::String(s)) => print!("{s}"),
-                    None => panic!("Stack underflow @ write instruction")
+                match self.pop_value()? {
+                    Value::String(s) => write!(self.output, "{s}")?,
+                    Value::Int(i) => write!(self.output, "{i}")?
                 }
+            },
+            Instruction::WriteError => {
+                // Keeps output in order when both are shown in the same terminal
+                self.output.flush()?;
+                match self.pop_value()? {
+                    Value::String(s) => write!(self.error_output, "{s}")?,
+                    Value::Int(i) => write!(self.error_output, "{i}")?
+                }
+            },
+            Instruction::Flush => self.flush()?,
+            Instruction::ReadLine => {
+                match self.read_line()? {
+                    Some(line) => self.push(Value::String(line))?,
+                    None => return Err(RuntimeError::EndOfInput)
+                }
+            },
+            Instruction::ReadAll => {
+                // Reading everything that is left is never an error, even if nothing is left
+                let mut bytes = Vec::<u8>::new();
+                self.output.flush()?;
+                match self.budget.remaining() {
+                    // Reads one byte more than is allowed, so that going over the limit is noticed
+                    Some(remaining) => self.input.by_ref().take(remaining as u64 + 1).read_to_end(&mut bytes)?,
+                    None => self.input.read_to_end(&mut bytes)?
+                };
+                let text = self.decode_input(bytes)?;
+                self.push(Value::String(text))?;
+            },
+            Instruction::ReadFullLine => {
+                // An empty line still has its line ending, so only the end of input is empty
+                let line = self.read_full_line()?.unwrap_or_default();
+                self.push(Value::String(line))?;
+            },
+            Instruction::AtEof => {
+                // So that prompts are shown before waiting for input
+                self.output.flush()?;
+                let at_eof = self.input.fill_buf()?.is_empty();
+                self.push(Value::Int(at_eof as i64))?;
+            },
+            Instruction::ReadInt => {
+                match self.read_line()? {
+                    Some(line) => match line.trim().parse::<i64>() {
+                        Ok(i) => self.push(Value::Int(i))?,
+                        Err(_) => return Err(RuntimeError::InvalidInteger(line))
+                    },
+                    None => return Err(RuntimeError::EndOfInput)
+                }
+            },
+            Instruction::Pop => {
+                self.pop_value()?;
+            },
+            // Returning is handled by `run`, which stops running the current function
+            Instruction::Return => unreachable!("Returns are run by `run`"),
+            Instruction::Exit => return Err(RuntimeError::Exit(self.pop_int()?)),
+            Instruction::LoadLocal(index) => {
+                match locals.get(*index) {
+                    Some(element) => self.push(element.clone())?,
+                    None => return Err(RuntimeError::UninitializedLocal(*index))
+                }
+            },
+            Instruction::StoreLocal(index) => {
+                let element = self.pop_value()?;
+                if *index >= locals.len() {
+                    self.budget.allocate((*index + 1 - locals.len()) * Value::Int(0).size())?;
+                    locals.resize(*index + 1, Value::Int(0));
+                }
+                self.budget.allocate(element.size())?;
+                self.budget.free(locals[*index].size());
+                locals[*index] = element;
+            },
+            Instruction::ToString(format) => {
+                let element = self.pop_value()?;
+                self.push(Value::String(format_element(element, *format)))?;
+            },
+            Instruction::Concat(count) => {
+                // Build the result in one allocation, rather than
+                // concatenating each part onto the last
+                let parts = self.pop_many(*count)?;
+                let length = parts.iter().map(|p| match p {
+                    Value::String(s) => s.len(),
+                    Value::Int(_) => 20
+                }).sum();
+
+                let mut string = String::with_capacity(length);
+                for part in parts {
+                    match part {
+                        Value::String(s) => string.push_str(&s),
+                        Value::Int(i) => string.push_str(&i.to_string())
+                    }
+                }
+                self.push(Value::String(string))?;
             }
-        } 
+        }
+
+        Ok(())
     }
 
-    fn run(&mut self, function: &str) {
-        let instructions = self.scope.get(function).expect("Couldn't find function!"); 
+    fn run(&mut self, function: &str) -> Result<(), RuntimeError> {
+        let instructions = match self.scope.get(function) {
+            Some(instructions) => Rc::clone(instructions),
+            None => return Err(RuntimeError::UnknownFunction(function.to_string()))
+        };
+        let mut locals = Vec::<Value>::new();
+        self.budget.enter()?;
+        self.hook.enter(function);
+
+        for (offset, instruction) in instructions.iter() {
+            let hooked = self.hook.instruction(&Frame {
+                function,
+                offset: *offset,
+                depth: self.budget.depth(),
+                locals: &locals,
+                stack: &self.stack,
+                debug: self.debug.as_ref()
+            }, instruction);
+
+            // Calls are run here rather than by `run_instruction`,
+            // so that nested calls use less of the interpreter's stack
+            let result = hooked.and_then(|_| match instruction {
+                Instruction::Return => Ok(()),
+                Instruction::Call(f) => self.budget.consume().and_then(|_| self.run(f)),
+                _ => self.budget.consume().and_then(|_| self.run_instruction(instruction, &mut locals))
+            });
+
+            // Each call that an error unwinds through adds itself to the trace
+            if let Err(e) = result {
+                let position = self.debug.as_ref().and_then(|d| d.position(function, *offset));
+                self.trace.push(Location { function: function.to_string(), offset: *offset, position });
+                self.hook.leave(function);
+                return Err(e);
+            }
 
-        for instruction in instructions.clone().iter() {
-            self.run_instruction(instruction);
+            if let Instruction::Return = instruction {
+                break;
+            }
         }
+
+        self.budget.free(locals.iter().map(Value::size).sum());
+        self.budget.leave();
+        self.hook.leave(function);
+        Ok(())
     }
 }
 
-pub fn interpret(mut readable: impl Read) {
-    let mut nenc: Vec<u8> = Vec::<u8>::new();
-    readable.read_to_end(&mut nenc).expect("Couldn't read file");
-
-    let mut interpreter = Interpreter::new();
+// Runs the bytecode read from `readable`, with standard input and output,
+// and returns the exit status of the program
+pub fn interpret(readable: impl Read, options: InterpreterOptions) -> Result<i32, Error> {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
 
-    interpreter.interpret_code(nenc);
+    interpret_with(readable, options, &mut stdin.lock(), &mut BufWriter::new(stdout.lock()))
+}
 
-    if interpreter.scope.get("main").is_none() {
-        panic!("No main function found!");
-    }
+// Runs the bytecode read from `readable`, reading input from `input`
+// and writing output to `output` in place of standard input and output
+pub fn interpret_with(readable: impl Read, options: InterpreterOptions, input: &mut dyn BufRead, output: &mut dyn Write) -> Result<i32, Error> {
+    let mut vm = Vm::load(readable, options).map_err(Error::Load)?;
+    vm.set_input(input);
+    vm.set_output(output);
 
-    interpreter.run("main");
+    vm.run_main()
 }
@@ -0,0 +1,143 @@
+use std::time::{ Duration, Instant };
+
+use RuntimeError;
+
+// How often the clock is checked, since checking it is slower than running most instructions
+const CLOCK_INTERVAL: u64 = 1024;
+
+// The deepest calls can be nested, since each call is run on the interpreter's own stack
+pub const MAX_CALL_DEPTH: usize = 1000;
+
+// Limits on how long a program can run for, so that untrusted programs
+// can't run forever. Each call into the program gets the full limits.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Limits {
+    // The most instructions a call can run, such as from `--max-instructions 1000000`
+    pub max_instructions: Option<u64>,
+    // The longest a call can run for, such as from `--timeout 2s`
+    pub timeout: Option<Duration>,
+    // The most bytes that the values held by a call can take up, such as from `--max-memory 64M`
+    pub max_memory: Option<usize>
+}
+
+// What is left of the limits during a call
+pub struct Budget {
+    fuel: Option<u64>,
+    deadline: Option<Instant>,
+    timeout: Duration,
+    executed: u64,
+    depth: usize,
+    memory: usize,
+    max_memory: Option<usize>
+}
+
+impl Budget {
+    pub fn new(limits: Limits) -> Self {
+        Budget {
+            fuel: limits.max_instructions,
+            // A timeout too long for the clock to represent never runs out
+            deadline: limits.timeout.and_then(|timeout| Instant::now().checked_add(timeout)),
+            timeout: limits.timeout.unwrap_or_default(),
+            executed: 0,
+            depth: 0,
+            memory: 0,
+            max_memory: limits.max_memory
+        }
+    }
+
+    // Fails if holding `bytes` more would go over the memory limit, without holding them
+    pub fn reserve(&self, bytes: usize) -> Result<(), RuntimeError> {
+        match self.max_memory {
+            Some(max) if self.memory.saturating_add(bytes) > max => Err(RuntimeError::OutOfMemory(max)),
+            _ => Ok(())
+        }
+    }
+
+    pub fn allocate(&mut self, bytes: usize) -> Result<(), RuntimeError> {
+        self.reserve(bytes)?;
+        self.memory += bytes;
+        Ok(())
+    }
+
+    pub fn free(&mut self, bytes: usize) {
+        self.memory -= bytes;
+    }
+
+    // The bytes that can still be held, if there is a memory limit
+    pub fn remaining(&self) -> Option<usize> {
+        self.max_memory.map(|max| max.saturating_sub(self.memory))
+    }
+
+    pub fn enter(&mut self) -> Result<(), RuntimeError> {
+        if self.depth == MAX_CALL_DEPTH {
+            return Err(RuntimeError::StackOverflow);
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    pub fn leave(&mut self) {
+        self.depth -= 1;
+    }
+
+    // How many calls deep the running function is
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    // Called before every instruction is run
+    pub fn consume(&mut self) -> Result<(), RuntimeError> {
+        if let Some(fuel) = &mut self.fuel {
+            if *fuel == 0 {
+                return Err(RuntimeError::OutOfFuel);
+            }
+            *fuel -= 1;
+        }
+
+        self.executed += 1;
+        if let Some(deadline) = self.deadline {
+            if self.executed.is_multiple_of(CLOCK_INTERVAL) && Instant::now() >= deadline {
+                return Err(RuntimeError::Timeout(self.timeout));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Parses a duration such as `2s`, `500ms` or `1m`, where a number on its own is in seconds
+pub fn parse_duration(text: &str) -> Option<Duration> {
+    let (number, unit) = match text.find(|c: char| !c.is_ascii_digit() && c != '.') {
+        Some(i) => text.split_at(i),
+        None => (text, "s")
+    };
+
+    let number = number.parse::<f64>().ok().filter(|n| n.is_finite())?;
+    let seconds = match unit {
+        "ms" => number / 1000.0,
+        "s" => number,
+        "m" => number * 60.0,
+        _ => return None
+    };
+
+    // Too long a duration is rejected like any other invalid one
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
+// Parses a number of bytes such as `64M`, `512K` or `1G`, where a number on its own is in bytes
+pub fn parse_size(text: &str) -> Option<usize> {
+    let (number, unit) = match text.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => text.split_at(i),
+        None => (text, "")
+    };
+
+    let multiplier: usize = match unit.to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" => 1 << 10,
+        "M" | "MB" => 1 << 20,
+        "G" | "GB" => 1 << 30,
+        _ => return None
+    };
+
+    number.parse::<usize>().ok()?.checked_mul(multiplier)
+}
@@ -0,0 +1,125 @@
+use std::{ collections::HashMap, io::{ self, Write }, time::{ Duration, Instant } };
+
+use { Frame, Hook, Instruction, RuntimeError };
+
+// What a function did across all of its calls
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FunctionProfile {
+    pub calls: u64,
+    // Instructions run by the function itself, not counting the functions it called
+    pub instructions: u64,
+    // Time spent in the function, including the functions it called. Calls that
+    // are nested within another call of the same function are only counted once.
+    pub inclusive: Duration,
+    // Time spent in the function itself
+    pub exclusive: Duration
+}
+
+// A call that is being timed
+struct Call {
+    function: String,
+    // The names of the functions the call is nested in, and its own, separated by `;`
+    stack: String,
+    start: Instant,
+    // Time spent in the functions that the call made
+    children: Duration,
+    instructions: u64
+}
+
+// Records how often each function is called, and how long it runs for
+pub struct Profiler {
+    calls: Vec<Call>,
+    functions: HashMap<String, FunctionProfile>,
+    // The time spent in each stack of calls itself
+    stacks: HashMap<String, Duration>
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Profiler::new()
+    }
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Profiler {
+            calls: Vec::<Call>::new(),
+            functions: HashMap::<String, FunctionProfile>::new(),
+            stacks: HashMap::<String, Duration>::new()
+        }
+    }
+
+    // Every function that was called, with the one that spent the longest running itself first
+    pub fn functions(&self) -> Vec<(&str, FunctionProfile)> {
+        let mut functions = self.functions.iter().map(|(name, profile)| (name.as_str(), *profile)).collect::<Vec<_>>();
+        functions.sort_by(|(a_name, a), (b_name, b)| b.exclusive.cmp(&a.exclusive).then(a_name.cmp(b_name)));
+        functions
+    }
+
+    // Writes each stack of calls with the nanoseconds spent in it, such as `main;greet 1200`,
+    // which tools such as `flamegraph.pl` and `inferno` draw as flame graphs
+    pub fn write_folded(&self, output: &mut dyn Write) -> io::Result<()> {
+        let mut stacks = self.stacks.iter().collect::<Vec<_>>();
+        stacks.sort();
+
+        for (stack, time) in stacks {
+            writeln!(output, "{} {}", stack, time.as_nanos())?;
+        }
+        Ok(())
+    }
+
+    pub fn write_summary(&self, output: &mut dyn Write) -> io::Result<()> {
+        writeln!(output, "{:>10} {:>14} {:>14} {:>14}  function", "calls", "instructions", "inclusive", "exclusive")?;
+        for (name, profile) in self.functions() {
+            writeln!(output, "{:>10} {:>14} {:>14} {:>14}  {}",
+                profile.calls,
+                profile.instructions,
+                format!("{:.3}ms", profile.inclusive.as_secs_f64() * 1000.0),
+                format!("{:.3}ms", profile.exclusive.as_secs_f64() * 1000.0),
+                name
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Hook for Profiler {
+    fn instruction(&mut self, _frame: &Frame, _instruction: &Instruction) -> Result<(), RuntimeError> {
+        if let Some(call) = self.calls.last_mut() {
+            call.instructions += 1;
+        }
+        Ok(())
+    }
+
+    fn enter(&mut self, function: &str) {
+        let stack = match self.calls.last() {
+            Some(caller) => format!("{};{}", caller.stack, function),
+            None => function.to_string()
+        };
+
+        self.functions.entry(function.to_string()).or_default().calls += 1;
+        self.calls.push(Call { function: function.to_string(), stack, start: Instant::now(), children: Duration::ZERO, instructions: 0 });
+    }
+
+    fn leave(&mut self, _function: &str) {
+        let call = match self.calls.pop() {
+            Some(call) => call,
+            None => return
+        };
+
+        let inclusive = call.start.elapsed();
+        let exclusive = inclusive.saturating_sub(call.children);
+        if let Some(caller) = self.calls.last_mut() {
+            caller.children += inclusive;
+        }
+        let recursive = self.calls.iter().any(|c| c.function == call.function);
+
+        let profile = self.functions.entry(call.function).or_default();
+        profile.instructions += call.instructions;
+        profile.exclusive += exclusive;
+        if !recursive {
+            profile.inclusive += inclusive;
+        }
+        *self.stacks.entry(call.stack).or_default() += exclusive;
+    }
+}
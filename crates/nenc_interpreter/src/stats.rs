@@ -0,0 +1,89 @@
+use std::{ collections::HashMap, io::{ self, Write } };
+
+use { Frame, Hook, Instruction, RuntimeError };
+
+// Counts what a program did as it ran
+pub struct Stats {
+    opcodes: HashMap<&'static str, u64>,
+    calls: HashMap<String, u64>,
+    // The most values that were on the stack at once
+    max_stack: usize,
+    // The most calls that were running at once
+    max_depth: usize
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Stats::new()
+    }
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Stats {
+            opcodes: HashMap::<&'static str, u64>::new(),
+            calls: HashMap::<String, u64>::new(),
+            max_stack: 0,
+            max_depth: 0
+        }
+    }
+
+    // How many times each opcode was run, with the most common first
+    pub fn opcodes(&self) -> Vec<(&'static str, u64)> {
+        let mut opcodes = self.opcodes.iter().map(|(name, count)| (*name, *count)).collect::<Vec<_>>();
+        opcodes.sort_by(|(a_name, a), (b_name, b)| b.cmp(a).then(a_name.cmp(b_name)));
+        opcodes
+    }
+
+    // How many times each function was called, with the most called first
+    pub fn calls(&self) -> Vec<(&str, u64)> {
+        let mut calls = self.calls.iter().map(|(name, count)| (name.as_str(), *count)).collect::<Vec<_>>();
+        calls.sort_by(|(a_name, a), (b_name, b)| b.cmp(a).then(a_name.cmp(b_name)));
+        calls
+    }
+
+    pub fn max_stack(&self) -> usize {
+        self.max_stack
+    }
+
+    pub fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+
+    pub fn write_summary(&self, output: &mut dyn Write) -> io::Result<()> {
+        let opcodes = self.opcodes();
+        writeln!(output, "{:>14}  opcode", "instructions")?;
+        for (name, count) in &opcodes {
+            writeln!(output, "{:>14}  {}", count, name)?;
+        }
+        writeln!(output, "{:>14}  total", opcodes.iter().map(|(_, count)| count).sum::<u64>())?;
+
+        writeln!(output)?;
+        writeln!(output, "{:>14}  function", "calls")?;
+        for (name, count) in self.calls() {
+            writeln!(output, "{:>14}  {}", count, name)?;
+        }
+
+        writeln!(output)?;
+        writeln!(output, "Max stack depth: {} values", self.max_stack)?;
+        writeln!(output, "Max call depth: {} calls", self.max_depth)
+    }
+}
+
+impl Hook for Stats {
+    fn instruction(&mut self, frame: &Frame, instruction: &Instruction) -> Result<(), RuntimeError> {
+        *self.opcodes.entry(instruction.name()).or_default() += 1;
+        self.max_stack = self.max_stack.max(frame.stack.len());
+        self.max_depth = self.max_depth.max(frame.depth);
+        Ok(())
+    }
+
+    fn enter(&mut self, function: &str) {
+        match self.calls.get_mut(function) {
+            Some(calls) => *calls += 1,
+            None => {
+                self.calls.insert(function.to_string(), 1);
+            }
+        }
+    }
+}
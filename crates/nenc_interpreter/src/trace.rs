@@ -0,0 +1,29 @@
+use std::io::Write;
+
+use debugger::describe;
+use { Frame, Hook, Instruction, RuntimeError };
+
+// Writes each instruction as it is run, such as `main+12 PushString "Ada"  top: "Hello"`
+pub struct Tracer<'t> {
+    output: Box<dyn Write + 't>
+}
+
+impl<'t> Tracer<'t> {
+    pub fn new(output: impl Write + 't) -> Self {
+        Tracer { output: Box::new(output) }
+    }
+}
+
+impl Hook for Tracer<'_> {
+    fn instruction(&mut self, frame: &Frame, instruction: &Instruction) -> Result<(), RuntimeError> {
+        let top = match frame.stack.last() {
+            Some(value) => describe(value),
+            None => "-".to_string()
+        };
+        let location = format!("{}+{}", frame.function, frame.offset);
+
+        // The trace is only for the user to read, so failing to write it doesn't stop the program
+        let _ = writeln!(self.output, "{:<24} {:<32} top: {}", location, instruction.to_string(), top);
+        Ok(())
+    }
+}
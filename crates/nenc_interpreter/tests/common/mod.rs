@@ -0,0 +1,40 @@
+// Each test file only uses some of these helpers
+#![allow(dead_code)]
+
+use std::{ env, fs };
+
+use nenc_interpreter::{ interpret_with, Error, InterpreterOptions, RuntimeError };
+
+// Compiles `source`, returning its bytecode
+pub fn compile(name: &str, source: &str) -> Vec<u8> {
+    compile_with(name, source, false)
+}
+
+pub fn compile_with(name: &str, source: &str, debug_info: bool) -> Vec<u8> {
+    let output_path = env::temp_dir().join(format!("nen-test-{}-{}.nenc", std::process::id(), name));
+
+    nenc::compile(source.as_bytes(), nenc::CompilerOptions {
+        output: output_path.clone(),
+        debug_info,
+        ..nenc::CompilerOptions::default()
+    }).unwrap_or_else(|e| panic!("{}", e));
+
+    let bytecode = fs::read(&output_path).expect("Bytecode should have been written");
+    fs::remove_file(&output_path).ok();
+
+    bytecode
+}
+
+// Compiles `source` and runs it with `input` piped in, returning what it printed
+pub fn run(name: &str, source: &str, options: InterpreterOptions, input: &str) -> (Result<i32, RuntimeError>, String) {
+    let bytecode = compile(name, source);
+
+    let mut output = Vec::<u8>::new();
+    let result = match interpret_with(&bytecode[..], options, &mut input.as_bytes(), &mut output) {
+        Ok(status) => Ok(status),
+        Err(Error::Runtime(e, _)) => Err(e),
+        Err(Error::Load(e)) => panic!("Bytecode should load: {}", e)
+    };
+
+    (result, String::from_utf8(output).expect("Output should be UTF-8"))
+}
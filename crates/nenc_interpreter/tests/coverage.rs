@@ -0,0 +1,84 @@
+extern crate nenc;
+extern crate nenc_interpreter;
+
+use std::{ env, fs };
+
+use nenc_interpreter::{ Coverage, InterpreterOptions, Vm };
+
+const SOURCE: &str = "func greet(name: string): string {
+    return \"Hello, {name}!\";
+}
+
+func unused(): string {
+    return \"Never called\";
+}
+
+impure func main() {
+    println(greet(\"Ada\"));
+    println(greet(\"Grace\"));
+}
+
+func empty() {
+}
+";
+
+// Runs the program from a file, since programs compiled from memory have no file to report
+fn lcov(name: &str) -> (String, String) {
+    let source = env::temp_dir().join(format!("nen-test-{}-{}.nen", std::process::id(), name));
+    let output = source.with_extension("nenc");
+    fs::write(&source, SOURCE).unwrap();
+    nenc::compile_file(&source, nenc::CompilerOptions {
+        output: output.clone(),
+        debug_info: true,
+        ..nenc::CompilerOptions::default()
+    }).unwrap_or_else(|e| panic!("{}", e));
+    let bytecode = fs::read(&output).unwrap();
+
+    let mut coverage = Coverage::new();
+    let debug = {
+        let mut vm = Vm::load_with_hook(&bytecode[..], InterpreterOptions::default(), &mut coverage).unwrap();
+        vm.set_output(Vec::<u8>::new());
+        vm.run_main().unwrap();
+        vm.debug_info().cloned().unwrap()
+    };
+
+    let mut report = Vec::<u8>::new();
+    coverage.write_lcov(&debug, &mut report).unwrap();
+    let path = fs::canonicalize(&source).unwrap().display().to_string();
+    fs::remove_file(&source).ok();
+    fs::remove_file(&output).ok();
+
+    (String::from_utf8(report).unwrap(), path)
+}
+
+#[test]
+fn functions_are_counted_by_their_calls() {
+    let (report, path) = lcov("coverage_functions");
+
+    assert!(report.starts_with(&format!("TN:\nSF:{}\n", path)), "{}", report);
+    assert!(report.contains("FN:1,greet\nFN:6,unused\nFN:10,main\n"), "{}", report);
+    assert!(report.contains("FNDA:2,greet\nFNDA:0,unused\nFNDA:1,main\n"), "{}", report);
+    assert!(report.contains("FNF:3\nFNH:2\n"), "{}", report);
+    assert!(report.ends_with("end_of_record\n"), "{}", report);
+    // The prelude isn't reported
+    assert_eq!(report.matches("SF:").count(), 1);
+    // Neither are functions without any code
+    assert!(!report.contains("empty"), "{}", report);
+}
+
+#[test]
+fn lines_are_counted_by_how_often_they_ran() {
+    let (report, _) = lcov("coverage_lines");
+
+    let lines = report.lines()
+        .filter_map(|line| line.strip_prefix("DA:"))
+        .map(|line| line.split_once(',').unwrap())
+        .collect::<Vec<(&str, &str)>>();
+    let hits = |line: &str| lines.iter().find(|(l, _)| *l == line).map(|(_, h)| *h);
+
+    assert_eq!(hits("2"), Some("2"));
+    assert_eq!(hits("6"), Some("0"));
+    assert_eq!(hits("10"), Some("1"));
+    assert_eq!(hits("11"), Some("1"));
+    assert!(report.contains(&format!("LF:{}\nLH:{}\n", lines.len(), lines.iter().filter(|(_, h)| *h != "0").count())));
+}
@@ -0,0 +1,166 @@
+extern crate nenc;
+extern crate nenc_interpreter;
+
+use std::{ env, fs, path::PathBuf };
+
+use nenc_interpreter::{ serve_dap, InterpreterOptions };
+
+fn example(name: &str) -> String {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../../examples").join(name);
+    fs::canonicalize(path).unwrap().display().to_string()
+}
+
+// Compiles a program with debug info, as `nen dap` does
+fn load(program: &str) -> Result<Vec<u8>, String> {
+    let output = env::temp_dir().join(format!("nen-test-{}-dap-{}.nenc", std::process::id(), program.len()));
+    nenc::compile_file(&PathBuf::from(program), nenc::CompilerOptions {
+        output: output.clone(),
+        debug_info: true,
+        ..nenc::CompilerOptions::default()
+    }).map_err(|e| e.plain())?;
+
+    let bytecode = fs::read(&output).map_err(|e| e.to_string());
+    fs::remove_file(&output).ok();
+    bytecode
+}
+
+// Sends each request in turn, as a client would, and returns every message the adapter sent back
+fn session(requests: &[(&str, String)]) -> Vec<String> {
+    let mut input = String::new();
+    for (seq, (command, arguments)) in requests.iter().enumerate() {
+        let content = format!("{{\"seq\":{},\"type\":\"request\",\"command\":\"{}\",\"arguments\":{}}}", seq + 1, command, arguments);
+        input.push_str(&format!("Content-Length: {}\r\n\r\n{}", content.len(), content));
+    }
+
+    let mut output = Vec::<u8>::new();
+    serve_dap(input.as_bytes(), &mut output, InterpreterOptions::default(), load).unwrap();
+
+    let output = String::from_utf8(output).unwrap();
+    let mut messages = Vec::<String>::new();
+    let mut rest = output.as_str();
+    while let Some(header_end) = rest.find("\r\n\r\n") {
+        let length = rest[..header_end].trim_start_matches("Content-Length: ").parse::<usize>().unwrap();
+        let start = header_end + 4;
+        messages.push(rest[start..start + length].to_string());
+        rest = &rest[start + length..];
+    }
+    assert_eq!(rest, "");
+
+    messages
+}
+
+// Each expected fragment is in a message after the one before it
+fn assert_in_order(messages: &[String], expected: &[&str]) {
+    let mut index = 0;
+    for fragment in expected {
+        match messages[index..].iter().position(|m| m.contains(fragment)) {
+            Some(found) => index += found + 1,
+            None => panic!("Expected {} after message {}, in:\n{}", fragment, index, messages.join("\n"))
+        }
+    }
+}
+
+#[test]
+fn breakpoints_are_stopped_at_and_stepped_over() {
+    let program = example("hello.nen");
+    let messages = session(&[
+        ("initialize", r#"{"adapterID":"nen"}"#.to_string()),
+        ("launch", format!(r#"{{"program":"{}"}}"#, program)),
+        ("setBreakpoints", format!(r#"{{"source":{{"path":"{}"}},"breakpoints":[{{"line":2}},{{"line":4}}]}}"#, program)),
+        ("configurationDone", "{}".to_string()),
+        ("stackTrace", r#"{"threadId":1}"#.to_string()),
+        ("next", r#"{"threadId":1}"#.to_string()),
+        ("stackTrace", r#"{"threadId":1}"#.to_string()),
+        ("continue", r#"{"threadId":1}"#.to_string()),
+        ("disconnect", "{}".to_string())
+    ]);
+
+    assert_in_order(&messages, &[
+        r#""command":"initialize","body":{"supportsConfigurationDoneRequest":true"#,
+        r#""success":true,"command":"launch""#,
+        r#""event":"initialized""#,
+        r#""breakpoints":[{"id":1,"verified":true,"line":2},{"id":2,"verified":false,"line":4}]"#,
+        r#""reason":"breakpoint""#,
+        &format!(r#""name":"main","source":{{"name":"hello.nen","path":"{}"}},"line":2"#, program),
+        r#""output":"This is all ""#,
+        r#""reason":"step""#,
+        &format!(r#""name":"main","source":{{"name":"hello.nen","path":"{}"}},"line":3"#, program),
+        r#""output":"on one line!""#,
+        r#""event":"exited","body":{"exitCode":0}"#,
+        r#""event":"terminated""#,
+        r#""command":"disconnect""#
+    ]);
+}
+
+#[test]
+fn locals_can_be_inspected() {
+    let program = example("names.nen");
+    let messages = session(&[
+        ("initialize", r#"{"adapterID":"nen"}"#.to_string()),
+        ("launch", format!(r#"{{"program":"{}","stopOnEntry":true}}"#, program)),
+        ("setFunctionBreakpoints", r#"{"breakpoints":[{"name":"greet"}]}"#.to_string()),
+        ("configurationDone", "{}".to_string()),
+        ("continue", r#"{"threadId":1}"#.to_string()),
+        ("stackTrace", r#"{"threadId":1}"#.to_string()),
+        ("scopes", r#"{"frameId":2}"#.to_string()),
+        ("variables", r#"{"variablesReference":1}"#.to_string()),
+        ("evaluate", r#"{"expression":"name","frameId":2}"#.to_string()),
+        ("stepOut", r#"{"threadId":1}"#.to_string()),
+        ("stackTrace", r#"{"threadId":1}"#.to_string()),
+        ("disconnect", "{}".to_string())
+    ]);
+
+    assert_in_order(&messages, &[
+        r#""breakpoints":[{"id":1,"verified":true}]"#,
+        r#""reason":"entry""#,
+        r#""reason":"breakpoint""#,
+        r#""totalFrames":2,"stackFrames":[{"id":2,"name":"greet""#,
+        r#""scopes":[{"name":"Locals","variablesReference":1"#,
+        r#""variables":[{"name":"name","value":"\"Ada\"""#,
+        r#""result":"\"Ada\"""#,
+        r#""output":"Hello, Ada!""#,
+        r#""reason":"step""#,
+        r#""totalFrames":1,"stackFrames":[{"id":1,"name":"main""#,
+        r#""command":"disconnect""#
+    ]);
+    // The program was stopped before it finished
+    assert!(!messages.iter().any(|m| m.contains("Hello, Grace!")));
+}
+
+#[test]
+fn compile_errors_fail_the_launch() {
+    let program = env::temp_dir().join(format!("nen-test-{}-dap-broken.nen", std::process::id()));
+    fs::write(&program, "impure func main() {\n    println(\"Hello\")\n}\n").unwrap();
+    let program = program.display().to_string();
+
+    let messages = session(&[
+        ("initialize", r#"{"adapterID":"nen"}"#.to_string()),
+        ("launch", format!(r#"{{"program":"{}"}}"#, program)),
+        ("disconnect", "{}".to_string())
+    ]);
+    fs::remove_file(&program).ok();
+
+    assert_in_order(&messages, &[
+        &format!(r#""success":false,"command":"launch","message":"ERROR {}:3:1: Expected `;`, found `}}`"#, program),
+        r#""command":"disconnect""#
+    ]);
+    // The error is shown by the editor, so it has no terminal colors
+    assert!(!messages.iter().any(|m| m.contains("\\u001b") || m.contains('\u{001b}')));
+    assert!(!messages.iter().any(|m| m.contains(r#""event":"initialized""#)));
+}
+
+#[test]
+fn malformed_requests_are_rejected() {
+    let serve = |input: String| {
+        serve_dap(input.as_bytes(), Vec::<u8>::new(), InterpreterOptions::default(), load).unwrap_err().to_string()
+    };
+
+    let content = r#"{"seq":1,"type":"request","command":"initialize"}"#;
+    assert_eq!(serve(format!("\r\n{}", content)), "A request has no Content-Length");
+    assert_eq!(serve("Content-Length: many\r\n\r\n".to_string()), "A request has an invalid Content-Length");
+    assert_eq!(serve("Content-Length: 99999999999\r\n\r\n".to_string()), "A request is too long");
+
+    // Nesting too deeply is invalid, rather than overflowing the stack
+    let nested = format!("{}{}", "[".repeat(100_000), "]".repeat(100_000));
+    assert_eq!(serve(format!("Content-Length: {}\r\n\r\n{}", nested.len(), nested)), "A request is not valid JSON");
+}
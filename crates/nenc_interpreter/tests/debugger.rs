@@ -0,0 +1,75 @@
+extern crate nenc;
+extern crate nenc_interpreter;
+
+mod common;
+
+use nenc_interpreter::{ Debugger, Error, InterpreterOptions, RuntimeError, Vm };
+
+const SOURCE: &str = "func greet(name: string): string {
+    return \"Hello, {name}!\";
+}
+
+impure func main() {
+    println(greet(\"Ada\"));
+    println(\"Done\");
+}
+";
+
+// Runs `source` in the debugger with `commands`, returning what the debugger and the program wrote
+fn debug(name: &str, debug_info: bool, commands: &str) -> (Result<i32, Error>, String, String) {
+    let bytecode = common::compile_with(name, SOURCE, debug_info);
+    let mut transcript = Vec::<u8>::new();
+    let mut output = Vec::<u8>::new();
+
+    let result = {
+        let debugger = Debugger::new(commands.as_bytes(), &mut transcript);
+        let mut vm = Vm::load_with_hook(&bytecode[..], InterpreterOptions::default(), debugger).unwrap();
+        vm.set_output(&mut output);
+        vm.run_main()
+    };
+
+    (result, String::from_utf8(transcript).unwrap(), String::from_utf8(output).unwrap())
+}
+
+#[test]
+fn breakpoints_show_locals_and_calls() {
+    let (result, transcript, output) = debug("debugger_break", true, "break greet\ncontinue\nlocals\nbacktrace\ncontinue\n");
+
+    assert!(matches!(result, Ok(0)));
+    assert!(transcript.contains("greet (<input>:2:12)"), "{}", transcript);
+    assert!(transcript.contains("name = \"Ada\""), "{}", transcript);
+    assert!(transcript.contains("at main (<input>:6:"), "{}", transcript);
+    assert_eq!(output, "Hello, Ada!\nDone\n");
+}
+
+#[test]
+fn next_steps_over_calls() {
+    let (result, transcript, output) = debug("debugger_next", true, "next\nstack\nquit\n");
+
+    assert!(matches!(result, Err(Error::Runtime(RuntimeError::Interrupted, _))));
+    assert!(transcript.contains("main (<input>:7:"), "{}", transcript);
+    assert!(!transcript.contains("greet (<input>"), "{}", transcript);
+    assert!(transcript.contains("The stack is empty"), "{}", transcript);
+    assert_eq!(output, "Hello, Ada!\n");
+}
+
+#[test]
+fn finish_returns_from_a_line_breakpoint() {
+    let (result, transcript, output) = debug("debugger_finish", true, "break 2\ncontinue\nfinish\nstack\ncontinue\n");
+
+    assert!(matches!(result, Ok(0)));
+    assert!(transcript.contains("greet (<input>:2:12)\n(nen) main (<input>:6:"), "{}", transcript);
+    assert!(transcript.contains("0: \"Hello, Ada!\""), "{}", transcript);
+    assert_eq!(output, "Hello, Ada!\nDone\n");
+}
+
+#[test]
+fn instructions_are_stepped_without_debug_info() {
+    let (result, transcript, output) = debug("debugger_instructions", false, "step\nstack\n");
+
+    // Running out of commands stops the program
+    assert!(matches!(result, Err(Error::Runtime(RuntimeError::Interrupted, _))));
+    assert!(transcript.contains("main (offset 0): PushString \"Ada\""), "{}", transcript);
+    assert!(transcript.contains("0: \"Ada\""), "{}", transcript);
+    assert_eq!(output, "");
+}
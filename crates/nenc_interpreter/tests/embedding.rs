@@ -0,0 +1,110 @@
+extern crate nenc;
+extern crate nenc_interpreter;
+
+mod common;
+
+use std::{ cell::RefCell, rc::Rc };
+
+use nenc_interpreter::{ InterpreterOptions, RuntimeError, Value, Vm };
+
+const SOURCE: &str = r#"
+    extern func double(value: int): int;
+    extern impure func log(message: string);
+
+    func greet(name: string): string { return "Hello, {name}!"; }
+    impure func report(value: int) { log("{double(value)}"); }
+    impure func main() {}
+"#;
+
+#[test]
+fn functions_can_be_called_repeatedly() {
+    let bytecode = common::compile("embedding_call", SOURCE);
+    let mut vm = Vm::load(&bytecode[..], InterpreterOptions::default()).unwrap();
+
+    for name in ["Ada", "Grace"] {
+        let greeting = vm.call("greet", &[Value::String(name.to_string())]).unwrap();
+        assert_eq!(greeting, Some(Value::String(format!("Hello, {}!", name))));
+    }
+
+    assert!(matches!(vm.call("missing", &[]), Err(RuntimeError::UnknownFunction(_))));
+}
+
+#[test]
+fn host_functions_are_called_with_their_arguments() {
+    let bytecode = common::compile("embedding_natives", SOURCE);
+    let logged = Rc::new(RefCell::new(Vec::<String>::new()));
+
+    let mut vm = Vm::load(&bytecode[..], InterpreterOptions::default()).unwrap();
+    vm.register_native("double", 1, false, |arguments| match arguments {
+        [Value::Int(i)] => Ok(Some(Value::Int(i * 2))),
+        _ => Err(RuntimeError::Native("Expected an int".to_string()))
+    });
+
+    let log = Rc::clone(&logged);
+    vm.register_native("log", 1, true, move |arguments| {
+        if let [Value::String(message)] = arguments {
+            log.borrow_mut().push(message.to_string());
+        }
+        Ok(None)
+    });
+
+    vm.call("report", &[Value::Int(4)]).unwrap();
+    vm.call("report", &[Value::Int(21)]).unwrap();
+
+    assert_eq!(*logged.borrow(), vec!["8".to_string(), "42".to_string()]);
+}
+
+#[test]
+fn host_functions_must_match_their_declaration() {
+    let bytecode = common::compile("embedding_mismatch", SOURCE);
+
+    let mut vm = Vm::load(&bytecode[..], InterpreterOptions::default()).unwrap();
+    assert!(matches!(vm.call("report", &[Value::Int(1)]), Err(RuntimeError::UnknownNative(_))));
+
+    // `double` is declared as pure, so can't be implemented by an impure function
+    vm.register_native("double", 1, true, |_| Ok(Some(Value::Int(0))));
+    assert!(matches!(vm.call("report", &[Value::Int(1)]), Err(RuntimeError::NativeMismatch(_))));
+}
+
+#[test]
+fn calls_must_have_the_right_number_of_arguments() {
+    let bytecode = common::compile("embedding_arity", SOURCE);
+    let mut vm = Vm::load(&bytecode[..], InterpreterOptions::default()).unwrap();
+
+    assert!(matches!(
+        vm.call("greet", &[]),
+        Err(RuntimeError::WrongArgumentCount { ref function, expected: 1, found: 0 }) if function == "greet"
+    ));
+    assert!(matches!(
+        vm.call("greet", &[Value::String("Ada".to_string()), Value::Int(1)]),
+        Err(RuntimeError::WrongArgumentCount { expected: 1, found: 2, .. })
+    ));
+    assert!(matches!(vm.call("main", &[Value::Int(1)]), Err(RuntimeError::WrongArgumentCount { expected: 0, found: 1, .. })));
+
+    // Only functions without a return type return nothing
+    assert_eq!(vm.call("main", &[]).unwrap(), None);
+    assert!(vm.call("greet", &[Value::String("Ada".to_string())]).unwrap().is_some());
+}
+
+// Output that can be written, but never flushed
+struct Unflushable;
+
+impl std::io::Write for Unflushable {
+    fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Err(std::io::Error::other("can't flush"))
+    }
+}
+
+#[test]
+fn errors_from_the_call_are_kept_over_errors_from_flushing() {
+    let bytecode = common::compile("embedding_flush", r#"impure func fail() { print("partial"); arg(5); } impure func main() {}"#);
+    let mut vm = Vm::load(&bytecode[..], InterpreterOptions::default()).unwrap();
+    vm.set_output(Unflushable);
+
+    assert!(matches!(vm.call("fail", &[]), Err(RuntimeError::ArgumentOutOfRange { index: 5, .. })));
+    assert!(matches!(vm.call("main", &[]), Err(RuntimeError::Io(_))));
+}
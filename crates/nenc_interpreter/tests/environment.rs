@@ -0,0 +1,39 @@
+extern crate nenc;
+extern crate nenc_interpreter;
+
+mod common;
+
+use nenc_interpreter::{ InterpreterOptions, RuntimeError };
+
+#[test]
+fn arguments_are_passed_to_the_program() {
+    let source = r#"impure func main() { println("{arg_count()}: {arg(1)}"); print(args()); }"#;
+    let options = InterpreterOptions {
+        args: vec!["first".to_string(), "second one".to_string()],
+        ..InterpreterOptions::default()
+    };
+    let (result, output) = common::run("env_args", source, options, "");
+
+    assert!(result.is_ok());
+    assert_eq!(output, "2: second one\nfirst\nsecond one");
+}
+
+#[test]
+fn missing_arguments_are_an_error() {
+    let source = r#"impure func main() { println(arg(0)); }"#;
+    let (result, _) = common::run("env_missing_arg", source, InterpreterOptions::default(), "");
+
+    assert!(matches!(result, Err(RuntimeError::ArgumentOutOfRange { index: 0, count: 0 })));
+}
+
+#[test]
+fn environment_can_be_cleared_and_overridden() {
+    let source = r#"impure func main() { println("[{env("NEN_TEST")}] [{env("PATH")}]"); print(env_vars()); }"#;
+    let mut options = InterpreterOptions::default();
+    options.environment.inherit = false;
+    options.environment.overrides.push(("NEN_TEST".to_string(), "value".to_string()));
+    let (result, output) = common::run("env_vars", source, options, "");
+
+    assert!(result.is_ok());
+    assert_eq!(output, "[value] []\nNEN_TEST=value");
+}
@@ -0,0 +1,139 @@
+extern crate nenc;
+extern crate nenc_interpreter;
+
+mod common;
+
+use nenc_interpreter::{ interpret_with, Error, InterpreterOptions, LoadError, Location, RuntimeError, Value, Vm };
+
+// Bytecode with a single function, named `name`, with no parameters and the given body
+fn bytecode(name: &str, body: &[u8]) -> Vec<u8> {
+    bytecode_with_parameters(name, 0, body)
+}
+
+fn bytecode_with_parameters(name: &str, parameters: u16, body: &[u8]) -> Vec<u8> {
+    let mut function = Vec::<u8>::new();
+    function.extend((name.len() as u16).to_be_bytes());
+    function.extend(name.as_bytes());
+    function.extend(parameters.to_be_bytes());
+    function.extend((body.len() as u32).to_be_bytes());
+    function.extend(body);
+
+    let mut bytes = b"NENC".to_vec();
+    bytes.extend((function.len() as u32).to_be_bytes());
+    bytes.extend(function);
+    bytes
+}
+
+fn run(bytes: &[u8]) -> Result<i32, Error> {
+    interpret_with(bytes, InterpreterOptions::default(), &mut &b""[..], &mut Vec::<u8>::new())
+}
+
+#[test]
+fn invalid_bytecode_fails_to_load() {
+    assert!(matches!(run(b"not bytecode"), Err(Error::Load(LoadError::NotBytecode))));
+    assert!(matches!(run(&bytecode("main", &[0x01])[..12]), Err(Error::Load(LoadError::Truncated))));
+    assert!(matches!(run(&bytecode("helper", &[0x02])), Err(Error::Load(LoadError::MissingMain))));
+
+    match run(&bytecode("main", &[0xE2, 0, 0, 0, 0, 0, 0, 0, 1, 0x99])) {
+        Err(Error::Load(LoadError::UnknownOpcode { function, offset: 9, opcode: 0x99 })) => assert_eq!(function, "main"),
+        result => panic!("Expected an unknown opcode, found {:?}", result)
+    }
+
+    // A string operand that is longer than the function
+    assert!(matches!(run(&bytecode("main", &[0xE1, 0, 9, b'a'])), Err(Error::Load(LoadError::InvalidOperand { offset: 0, .. }))));
+}
+
+// The program would otherwise only fail once it reached the call
+#[test]
+fn calls_to_missing_built_ins_fail_to_load() {
+    let call = |name: &str, arguments: u8, flags: u8| {
+        let mut body = vec![0xA2];
+        body.extend((name.len() as u16).to_be_bytes());
+        body.extend(name.as_bytes());
+        body.extend([arguments, flags, 0x02]);
+        run(&bytecode("main", &body))
+    };
+
+    match call("teleport", 0, 0b100) {
+        Err(Error::Load(LoadError::UnknownBuiltin { function, name, arguments: 0 })) => {
+            assert_eq!((function.as_str(), name.as_str()), ("main", "teleport"));
+        },
+        result => panic!("Expected an unknown built-in, found {:?}", result)
+    }
+    assert!(matches!(call("length", 2, 0b110), Err(Error::Load(LoadError::UnknownBuiltin { arguments: 2, .. }))));
+
+    // Functions of the host are only looked for when they are called
+    assert!(matches!(call("teleport", 0, 0b000), Err(Error::Runtime(RuntimeError::UnknownNative(_), _))));
+}
+
+// The number of parameters is taken from the function's header, rather than from its instructions
+#[test]
+fn calls_are_checked_against_the_declared_parameters() {
+    let bytes = bytecode_with_parameters("two", 2, &[0x01, 0x01, 0x02]);
+    let mut vm = Vm::load(&bytes[..], InterpreterOptions::default()).unwrap();
+
+    assert!(matches!(vm.call("two", &[Value::Int(1)]), Err(RuntimeError::WrongArgumentCount { expected: 2, found: 1, .. })));
+    assert_eq!(vm.call("two", &[Value::Int(1), Value::Int(2)]).unwrap(), None);
+}
+
+#[test]
+fn malformed_instructions_are_runtime_errors() {
+    assert!(matches!(run(&bytecode("main", &[0x01, 0x02])), Err(Error::Runtime(RuntimeError::StackUnderflow, _))));
+    assert!(matches!(run(&bytecode("main", &[0x20, 0, 3, 0x02])), Err(Error::Runtime(RuntimeError::UninitializedLocal(3), _))));
+}
+
+#[test]
+fn runtime_errors_report_where_they_happened() {
+    let source = "impure func second(): string { return arg(5); }\nimpure func main() { println(second()); }\n";
+
+    match run(&common::compile("errors_location", source)) {
+        Err(error @ Error::Runtime(RuntimeError::ArgumentOutOfRange { .. }, _)) => {
+            assert_eq!(error.exit_code(), 113);
+            if let Error::Runtime(_, trace) = error {
+                assert_eq!(trace, vec![
+                    Location { function: "second".to_string(), offset: 9, position: None },
+                    Location { function: "main".to_string(), offset: 0, position: None }
+                ]);
+            }
+        },
+        result => panic!("Expected an argument out of range, found {:?}", result)
+    }
+
+    match run(&common::compile_with("errors_debug_info", source, true)) {
+        Err(Error::Runtime(_, trace)) => {
+            let positions = trace.into_iter()
+                .map(|l| l.position.map(|p| (p.file, p.line, p.column)))
+                .collect::<Vec<_>>();
+            assert_eq!(positions, vec![
+                Some(("<input>".to_string(), 1, 39)),
+                Some(("<input>".to_string(), 2, 30))
+            ]);
+        },
+        result => panic!("Expected a runtime error, found {:?}", result)
+    }
+}
+
+#[test]
+fn error_positions_use_display_columns() {
+    // `日本` is six bytes but four columns wide, and the tab is four columns
+    let source = "impure func main() {\n\tprintln(\"日本 {arg(0)}\");\n}\n";
+
+    match run(&common::compile_with("errors_columns", source, true)) {
+        Err(Error::Runtime(RuntimeError::ArgumentOutOfRange { .. }, trace)) => {
+            let position = trace[0].position.as_ref().expect("Should have a position");
+            // The byte column would be 19
+            assert_eq!((position.line, position.column), (2, 20));
+        },
+        result => panic!("Expected an argument out of range, found {:?}", result)
+    }
+}
+
+#[test]
+fn each_error_exits_with_its_own_status() {
+    assert_eq!(run(b"not bytecode").unwrap_err().exit_code(), 101);
+    assert_eq!(run(&bytecode("main", &[0x01, 0x02])).unwrap_err().exit_code(), 140);
+
+    // A program exiting with the same status as an error isn't an error itself
+    let source = "impure func main() { exit(113); }";
+    assert_eq!(run(&common::compile("errors_same_status", source)).ok(), Some(113));
+}
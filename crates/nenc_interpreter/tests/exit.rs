@@ -0,0 +1,51 @@
+extern crate nenc;
+extern crate nenc_interpreter;
+
+mod common;
+
+use nenc_interpreter::{ InterpreterOptions, RuntimeError };
+
+#[test]
+fn main_returns_the_exit_status() {
+    let source = r#"impure func main(): int { println("done"); return 3; }"#;
+    let (result, output) = common::run("exit_main", source, InterpreterOptions::default(), "");
+
+    assert_eq!(result.ok(), Some(3));
+    assert_eq!(output, "done\n");
+}
+
+#[test]
+fn exit_stops_every_call_and_flushes_output() {
+    let source = r#"
+        impure func stop() { print("stopping"); exit(42); print("unreachable"); }
+        impure func main(): int { stop(); println("unreachable"); return 0; }
+    "#;
+    let (result, output) = common::run("exit_builtin", source, InterpreterOptions::default(), "");
+
+    assert_eq!(result.ok(), Some(42));
+    assert_eq!(output, "stopping");
+}
+
+#[test]
+fn main_without_a_return_type_exits_successfully() {
+    let source = r#"impure func main() { length("unused"); }"#;
+    let (result, _) = common::run("exit_void", source, InterpreterOptions::default(), "");
+
+    assert_eq!(result.ok(), Some(0));
+}
+
+#[test]
+fn exit_statuses_must_fit_in_a_byte() {
+    let source = r#"impure func main(): int { return 255; }"#;
+    let (result, _) = common::run("exit_largest", source, InterpreterOptions::default(), "");
+    assert_eq!(result.ok(), Some(255));
+
+    let source = r#"impure func main(): int { println("done"); return 256; }"#;
+    let (result, output) = common::run("exit_too_large", source, InterpreterOptions::default(), "");
+    assert!(matches!(result, Err(RuntimeError::InvalidExitStatus(256))));
+    assert_eq!(output, "done\n");
+
+    let source = r#"impure func main() { exit(4294967296); }"#;
+    let (result, _) = common::run("exit_truncated", source, InterpreterOptions::default(), "");
+    assert!(matches!(result, Err(RuntimeError::InvalidExitStatus(4294967296))));
+}
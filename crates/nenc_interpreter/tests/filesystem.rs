@@ -0,0 +1,88 @@
+extern crate nenc;
+extern crate nenc_interpreter;
+
+mod common;
+
+use std::{ env, fs, path::PathBuf };
+
+use nenc_interpreter::{ Access, InterpreterOptions, RuntimeError };
+
+fn directory(name: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("nen-fs-{}-{}", std::process::id(), name));
+    fs::create_dir_all(&path).expect("Should be able to create a temporary directory");
+    path
+}
+
+#[test]
+fn files_cannot_be_accessed_by_default() {
+    let dir = directory("default");
+    let source = format!(r#"impure func main() {{ write_file("{}/file.txt", "text"); }}"#, dir.display());
+    let (result, _) = common::run("fs_default", &source, InterpreterOptions::default(), "");
+
+    assert!(matches!(result, Err(RuntimeError::PermissionDenied { access: Access::Write, .. })));
+    assert!(!dir.join("file.txt").exists());
+    fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn allowed_directories_can_be_read_and_written() {
+    let dir = directory("allowed");
+    let source = format!(r#"impure func main() {{
+        write_file("{0}/file.txt", "one ");
+        append_file("{0}/file.txt", "two");
+        println(read_file("{0}/file.txt"));
+        println(list_dir("{0}"));
+        create_dir("{0}/empty");
+        println("[{{list_dir("{0}/empty")}}]");
+        write_file("{0}/a.txt", "");
+        println(list_dir("{0}"));
+    }}"#, dir.display());
+
+    let mut options = InterpreterOptions::default();
+    options.permissions.read.paths.push(dir.clone());
+    options.permissions.write.paths.push(dir.clone());
+    let (result, output) = common::run("fs_allowed", &source, options, "");
+
+    assert!(result.is_ok());
+    assert_eq!(output, "one two\nfile.txt\n[]\na.txt\nempty\nfile.txt\n");
+    fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn parent_directories_cannot_escape_an_allowed_directory() {
+    let dir = directory("escape");
+    fs::create_dir_all(dir.join("allowed")).ok();
+    fs::write(dir.join("secret.txt"), "secret").ok();
+    let source = format!(r#"impure func main() {{ println(read_file("{}/allowed/../secret.txt")); }}"#, dir.display());
+
+    let mut options = InterpreterOptions::default();
+    options.permissions.read.paths.push(dir.join("allowed"));
+    let (result, output) = common::run("fs_escape", &source, options, "");
+
+    assert!(matches!(result, Err(RuntimeError::PermissionDenied { access: Access::Read, .. })));
+    assert_eq!(output, "");
+    fs::remove_dir_all(dir).ok();
+}
+
+// Writing through a link that points outside of the allowed directory would create its target
+#[cfg(unix)]
+#[test]
+fn dangling_links_cannot_escape_an_allowed_directory() {
+    let dir = directory("dangling");
+    fs::create_dir_all(dir.join("allowed")).ok();
+    std::os::unix::fs::symlink(dir.join("outside.txt"), dir.join("allowed/link.txt")).unwrap();
+    std::os::unix::fs::symlink(dir.join("missing"), dir.join("allowed/link")).unwrap();
+
+    for path in ["link.txt", "link/file.txt"] {
+        let source = format!(r#"impure func main() {{ write_file("{}/allowed/{}", "text"); }}"#, dir.display(), path);
+
+        let mut options = InterpreterOptions::default();
+        options.permissions.write.paths.push(dir.join("allowed"));
+        let (result, _) = common::run("fs_dangling", &source, options, "");
+
+        assert!(matches!(result, Err(RuntimeError::PermissionDenied { access: Access::Write, .. })), "{}: {:?}", path, result);
+    }
+    assert!(!dir.join("outside.txt").exists());
+    assert!(!dir.join("missing").exists());
+    fs::remove_dir_all(dir).ok();
+}
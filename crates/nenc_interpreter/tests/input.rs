@@ -0,0 +1,102 @@
+extern crate nenc;
+extern crate nenc_interpreter;
+
+mod common;
+
+use nenc_interpreter::{ InterpreterOptions, RuntimeError, Value, Vm };
+
+fn run(name: &str, source: &str, input: &str) -> (Result<i32, RuntimeError>, String) {
+    common::run(name, source, InterpreterOptions::default(), input)
+}
+
+#[test]
+fn read_line_strips_line_endings() {
+    let source = r#"impure func main() { println("[{read_line()}]"); println("[{read_line()}]"); }"#;
+    let (result, output) = run("read_line", source, "first\r\nsecond\n");
+
+    assert!(result.is_ok());
+    assert_eq!(output, "[first]\n[second]\n");
+}
+
+#[test]
+fn read_line_at_end_of_input_is_an_error() {
+    let source = r#"impure func main() { println(read_line()); println(read_line()); }"#;
+    let (result, output) = run("read_line_eof", source, "only line");
+
+    assert!(matches!(result, Err(RuntimeError::EndOfInput)));
+    assert_eq!(output, "only line\n");
+}
+
+#[test]
+fn read_int_parses_a_line() {
+    let source = r#"impure func main() { println("{read_int():>5}"); }"#;
+    let (result, output) = run("read_int", source, "  42 \n");
+
+    assert!(result.is_ok());
+    assert_eq!(output, "   42\n");
+}
+
+#[test]
+fn read_int_rejects_other_text() {
+    let source = r#"impure func main() { println("{read_int()}"); }"#;
+    let (result, _) = run("read_int_invalid", source, "forty two\n");
+
+    assert!(matches!(result, Err(RuntimeError::InvalidInteger(line)) if line == "forty two"));
+}
+
+#[test]
+fn read_all_reads_the_rest_of_input() {
+    let source = r#"impure func main() { read_line(); print("[{read_all()}]"); print("[{read_all()}]"); }"#;
+    let (result, output) = run("read_all", source, "skipped\nrest\nof input");
+
+    assert!(result.is_ok());
+    assert_eq!(output, "[rest\nof input][]");
+}
+
+#[test]
+fn read_full_line_tells_empty_lines_from_end_of_input() {
+    let source = r#"impure func main() { print("[{read_full_line()}]"); print("[{read_full_line()}]"); print("[{read_full_line()}]"); print("[{read_full_line()}]"); }"#;
+    let (result, output) = run("read_full_line", source, "first\r\n\nlast");
+
+    assert!(result.is_ok());
+    assert_eq!(output, "[first\r\n][\n][last][]");
+}
+
+#[test]
+fn at_eof_is_set_once_input_runs_out() {
+    let source = r#"impure func main() { print("{at_eof()}"); read_line(); print("{at_eof()}"); read_line(); print("{at_eof()}"); }"#;
+    let (result, output) = run("at_eof", source, "first\nsecond");
+
+    assert!(result.is_ok());
+    assert_eq!(output, "001");
+
+    let (_, output) = run("at_eof_empty", source, "");
+    assert_eq!(output, "1");
+}
+
+#[test]
+fn read_loop_stops_at_end_of_input() {
+    let source = r#"
+        impure func at_end(): int { return at_eof(); }
+        impure func echo() { println("> {read_line()}"); }
+        impure func main() {}
+    "#;
+    let bytecode = common::compile("read_loop", source);
+
+    let mut output = Vec::<u8>::new();
+    {
+        let mut vm = Vm::load(&bytecode[..], InterpreterOptions::default()).unwrap();
+        vm.set_input("one\ntwo\r\n\nthree".as_bytes());
+        vm.set_output(&mut output);
+
+        let mut lines = 0;
+        while vm.call("at_end", &[]).unwrap() == Some(Value::Int(0)) {
+            vm.call("echo", &[]).unwrap();
+            lines += 1;
+        }
+        vm.flush().unwrap();
+        assert_eq!(lines, 4);
+    }
+
+    assert_eq!(String::from_utf8(output).unwrap(), "> one\n> two\n> \n> three\n");
+}
@@ -0,0 +1,29 @@
+extern crate nenc;
+extern crate nenc_interpreter;
+
+mod common;
+
+use nenc_interpreter::InterpreterOptions;
+
+#[test]
+fn values_are_formatted_by_their_spec() {
+    let (result, output) = common::run("interpolation_specs", r#"
+        impure func main() {
+            println("[{"nen"}] [{42}] [{"a"}{1}{"b"}]");
+            println("[{"nen":>6}] [{"nen":<6}] [{"nen":^7}] [{"nen":^6}]");
+            println("[{42:6}] [{42:<6}] [{"nen":6}]");
+            println("[{"compiler":.4}] [{"nen":^8.2}] [{"nen":.0}]");
+            println("[{"wider than the width":3}] {{literal}}");
+        }
+    "#, InterpreterOptions::default(), "");
+
+    assert!(matches!(result, Ok(0)));
+    assert_eq!(output, [
+        "[nen] [42] [a1b]",
+        "[   nen] [nen   ] [  nen  ] [ nen  ]",
+        "[    42] [42    ] [nen   ]",
+        "[comp] [   ne   ] []",
+        "[wider than the width] {literal}",
+        ""
+    ].join("\n"));
+}
@@ -0,0 +1,119 @@
+extern crate nenc;
+extern crate nenc_interpreter;
+
+mod common;
+
+use std::time::Duration;
+
+use nenc_interpreter::{ parse_duration, parse_size, InterpreterOptions, Limits, RuntimeError, Value, Vm };
+
+const SOURCE: &str = r#"
+    func forever(n: int): int { return forever(n); }
+    func deep(n: int): int { return n; }
+    impure func main() { println("started"); forever(0); }
+"#;
+
+#[test]
+fn running_out_of_fuel_stops_the_program() {
+    let options = InterpreterOptions {
+        limits: Limits { max_instructions: Some(500), ..Limits::default() },
+        ..InterpreterOptions::default()
+    };
+    let (result, output) = common::run("limits_fuel", SOURCE, options, "");
+
+    assert!(matches!(result, Err(RuntimeError::OutOfFuel)));
+    assert_eq!(output, "started\n");
+}
+
+#[test]
+fn running_past_the_timeout_stops_the_program() {
+    let options = InterpreterOptions {
+        limits: Limits { timeout: Some(Duration::ZERO), ..Limits::default() },
+        ..InterpreterOptions::default()
+    };
+    let (result, _) = common::run("limits_timeout", SOURCE, options, "");
+
+    assert!(matches!(result, Err(RuntimeError::Timeout(_))));
+}
+
+#[test]
+fn recursing_forever_is_an_error() {
+    let (result, _) = common::run("limits_depth", SOURCE, InterpreterOptions::default(), "");
+
+    assert!(matches!(result, Err(RuntimeError::StackOverflow)));
+}
+
+#[test]
+fn each_call_gets_the_full_budget() {
+    let bytecode = common::compile("limits_budget", SOURCE);
+    let mut vm = Vm::load(&bytecode[..], InterpreterOptions::default()).unwrap();
+    vm.set_limits(Limits { max_instructions: Some(10), timeout: Some(Duration::from_secs(10)), max_memory: Some(4096) });
+
+    for i in 0..100 {
+        assert_eq!(vm.call("deep", &[Value::Int(i)]).unwrap(), Some(Value::Int(i)));
+    }
+}
+
+#[test]
+fn timeouts_past_what_the_clock_can_hold_never_run_out() {
+    let bytecode = common::compile("limits_long_timeout", SOURCE);
+    let mut vm = Vm::load(&bytecode[..], InterpreterOptions::default()).unwrap();
+    vm.set_limits(Limits { timeout: Some(Duration::MAX), ..Limits::default() });
+
+    assert_eq!(vm.call("deep", &[Value::Int(3)]).unwrap(), Some(Value::Int(3)));
+}
+
+#[test]
+fn holding_too_much_memory_stops_the_program() {
+    let source = r#"impure func main() { println("{read_all()}{read_all()}"); }"#;
+    let options = InterpreterOptions {
+        limits: Limits { max_memory: Some(4096), ..Limits::default() },
+        ..InterpreterOptions::default()
+    };
+
+    let (result, _) = common::run("limits_small_input", source, options.clone(), "small");
+    assert_eq!(result.ok(), Some(0));
+
+    let (result, output) = common::run("limits_large_input", source, options.clone(), &"x".repeat(10_000));
+    assert!(matches!(result, Err(RuntimeError::OutOfMemory(4096))));
+    assert_eq!(output, "");
+
+    // The limit falls partway through a character, which isn't reported as invalid input
+    for (name, source) in [("all", "read_all()"), ("line", "read_line()"), ("full_line", "read_full_line()")] {
+        let source = format!("impure func main() {{ println({}); }}", source);
+        let (result, _) = common::run(&format!("limits_multibyte_{}", name), &source, options.clone(), &"é".repeat(5_000));
+        assert!(matches!(result, Err(RuntimeError::OutOfMemory(4096))), "{}: {:?}", name, result);
+    }
+}
+
+#[test]
+fn memory_is_freed_between_calls() {
+    let source = r#"func pad(value: string): string { return "{value:1000}"; } impure func main() {}"#;
+    let bytecode = common::compile("limits_memory_calls", source);
+    let mut vm = Vm::load(&bytecode[..], InterpreterOptions::default()).unwrap();
+    vm.set_limits(Limits { max_memory: Some(4096), ..Limits::default() });
+
+    for _ in 0..100 {
+        assert!(vm.call("pad", &[Value::String("x".to_string())]).is_ok());
+    }
+}
+
+#[test]
+fn sizes_have_units() {
+    assert_eq!(parse_size("512"), Some(512));
+    assert_eq!(parse_size("64K"), Some(64 * 1024));
+    assert_eq!(parse_size("2MB"), Some(2 * 1024 * 1024));
+    assert_eq!(parse_size("1g"), Some(1024 * 1024 * 1024));
+    assert_eq!(parse_size("lots"), None);
+}
+
+#[test]
+fn durations_have_units() {
+    assert_eq!(parse_duration("2s"), Some(Duration::from_secs(2)));
+    assert_eq!(parse_duration("500ms"), Some(Duration::from_millis(500)));
+    assert_eq!(parse_duration("1m"), Some(Duration::from_secs(60)));
+    assert_eq!(parse_duration("3"), Some(Duration::from_secs(3)));
+    assert_eq!(parse_duration("2 days"), None);
+    assert_eq!(parse_duration("18446744073709551616"), None);
+    assert_eq!(parse_duration(&"9".repeat(400)), None);
+}
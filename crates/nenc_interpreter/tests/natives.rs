@@ -0,0 +1,21 @@
+extern crate nenc;
+extern crate nenc_interpreter;
+
+use nenc_interpreter::native_arity;
+
+#[test]
+fn every_native_builtin_is_implemented_by_the_vm() {
+    let builtins = nenc::native_builtins();
+    assert!(!builtins.is_empty());
+
+    for (name, parameters) in builtins {
+        assert_eq!(native_arity(name), Some(parameters), "{}", name);
+    }
+}
+
+#[test]
+fn other_functions_are_not_implemented_by_the_vm() {
+    assert_eq!(native_arity("not_a_builtin"), None);
+    // Internal primitives are compiled into instructions instead
+    assert_eq!(native_arity("__write"), None);
+}
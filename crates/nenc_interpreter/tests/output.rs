@@ -0,0 +1,53 @@
+extern crate nenc;
+extern crate nenc_interpreter;
+
+mod common;
+
+use std::io::{ self, Write };
+
+use nenc_interpreter::{ InterpreterOptions, RuntimeError, Vm };
+
+// Fails every write, like standard output piped into a program that has exited
+struct BrokenPipe;
+
+impl Write for BrokenPipe {
+    fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+        Err(io::Error::new(io::ErrorKind::BrokenPipe, "Broken pipe"))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn output_and_error_output_are_separate() {
+    let source = r#"impure func main() { println("out"); eprintln("error {length("abc")}"); flush(); }"#;
+    let bytecode = common::compile("output_sinks", source);
+
+    let mut output = Vec::<u8>::new();
+    let mut error_output = Vec::<u8>::new();
+    {
+        let mut vm = Vm::load(&bytecode[..], InterpreterOptions::default()).unwrap();
+        vm.set_output(&mut output);
+        vm.set_error_output(&mut error_output);
+        vm.call("main", &[]).unwrap();
+    }
+
+    assert_eq!(output, b"out\n");
+    assert_eq!(error_output, b"error 3\n");
+}
+
+#[test]
+fn failed_writes_are_an_error() {
+    let source = r#"impure func main() { println("lost"); }"#;
+    let bytecode = common::compile("output_broken_pipe", source);
+
+    let mut vm = Vm::load(&bytecode[..], InterpreterOptions::default()).unwrap();
+    vm.set_output(BrokenPipe);
+
+    match vm.call("main", &[]) {
+        Err(RuntimeError::Io(e)) => assert_eq!(e.kind(), io::ErrorKind::BrokenPipe),
+        result => panic!("Expected a broken pipe, found {:?}", result)
+    }
+}
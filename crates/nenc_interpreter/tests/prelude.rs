@@ -0,0 +1,52 @@
+extern crate nenc;
+extern crate nenc_interpreter;
+
+mod common;
+
+use nenc_interpreter::{ InterpreterOptions, Value, Vm };
+
+#[test]
+fn shadowed_built_ins_can_be_called_with_std() {
+    let source = r#"
+        impure func print(value: string) { std::print("> {value}"); }
+        func length(value: string): int { return 42; }
+        impure func main() {
+            print("hi");
+            std::print(" {length("abc")} {std::length("abc")}");
+            std::println("");
+        }
+    "#;
+    let (result, output) = common::run("std_shadowed", source, InterpreterOptions::default(), "");
+
+    assert!(matches!(result, Ok(0)));
+    assert_eq!(output, "> hi 42 3\n");
+}
+
+#[test]
+fn std_refers_to_built_ins_without_shadowing() {
+    let source = r#"impure func main() { std::println("{std::length("héllo")}"); }"#;
+    let (result, output) = common::run("std_plain", source, InterpreterOptions::default(), "");
+
+    assert!(matches!(result, Ok(0)));
+    assert_eq!(output, "5\n");
+}
+
+#[test]
+fn prelude_functions_used_outside_of_main_are_kept() {
+    let source = r#"
+        impure func greet(name: string) { println("Hello, {name}!"); }
+        impure func main() {}
+    "#;
+    let bytecode = common::compile("prelude_unused", source);
+
+    let mut output = Vec::<u8>::new();
+    {
+        let mut vm = Vm::load(&bytecode[..], InterpreterOptions::default()).unwrap();
+        vm.set_output(&mut output);
+        vm.call("greet", &[Value::String("Ada".to_string())]).unwrap();
+        vm.flush().unwrap();
+        assert!(!vm.has_function("std::eprintln"));
+    }
+
+    assert_eq!(String::from_utf8(output).unwrap(), "Hello, Ada!\n");
+}
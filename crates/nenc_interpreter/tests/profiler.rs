@@ -0,0 +1,60 @@
+extern crate nenc;
+extern crate nenc_interpreter;
+
+mod common;
+
+use nenc_interpreter::{ InterpreterOptions, Profiler, Vm };
+
+const SOURCE: &str = r#"
+    func greet(name: string): string { return "Hello, {name}!"; }
+    impure func show(name: string) { println(greet(name)); }
+    impure func main() {
+        show("Ada");
+        show("Grace");
+        exit(3);
+    }
+"#;
+
+fn profile(name: &str) -> (Profiler, i32) {
+    let bytecode = common::compile(name, SOURCE);
+    let mut profiler = Profiler::new();
+
+    let status = {
+        let mut vm = Vm::load_with_hook(&bytecode[..], InterpreterOptions::default(), &mut profiler).unwrap();
+        vm.set_output(Vec::<u8>::new());
+        vm.run_main().unwrap()
+    };
+
+    (profiler, status)
+}
+
+#[test]
+fn calls_and_instructions_are_counted() {
+    let (profiler, status) = profile("profiler_counts");
+    assert_eq!(status, 3);
+
+    let functions = profiler.functions();
+    let calls = |name: &str| functions.iter().find(|(n, _)| *n == name).map(|(_, p)| p.calls);
+    assert_eq!(calls("main"), Some(1));
+    assert_eq!(calls("show"), Some(2));
+    assert_eq!(calls("greet"), Some(2));
+    assert_eq!(calls("std::println"), Some(2));
+
+    for (name, profile) in functions {
+        assert!(profile.instructions > 0, "{} ran no instructions", name);
+        assert!(profile.inclusive >= profile.exclusive, "{} spent longer in itself than in total", name);
+    }
+}
+
+#[test]
+fn stacks_are_folded() {
+    let (profiler, _) = profile("profiler_folded");
+
+    let mut folded = Vec::<u8>::new();
+    profiler.write_folded(&mut folded).unwrap();
+    let folded = String::from_utf8(folded).unwrap();
+
+    let stacks = folded.lines().map(|line| line.rsplit_once(' ').unwrap().0).collect::<Vec<&str>>();
+    // Calls that end with `exit` are still recorded
+    assert_eq!(stacks, vec!["main", "main;show", "main;show;greet", "main;show;std::println"]);
+}
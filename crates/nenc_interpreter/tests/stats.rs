@@ -0,0 +1,36 @@
+extern crate nenc;
+extern crate nenc_interpreter;
+
+mod common;
+
+use nenc_interpreter::{ InterpreterOptions, Stats, Vm };
+
+#[test]
+fn opcodes_calls_and_depths_are_counted() {
+    let bytecode = common::compile("stats_counts", r#"
+        func greet(name: string): string { return "Hello, {name}!"; }
+        impure func show(name: string) { println(greet(name)); }
+        impure func main() {
+            show("Ada");
+            show("Grace");
+        }
+    "#);
+
+    let mut stats = Stats::new();
+    {
+        let mut vm = Vm::load_with_hook(&bytecode[..], InterpreterOptions::default(), &mut stats).unwrap();
+        vm.set_output(Vec::<u8>::new());
+        assert_eq!(vm.run_main().unwrap(), 0);
+    }
+
+    assert_eq!(stats.calls(), vec![("greet", 2), ("show", 2), ("std::println", 2), ("main", 1)]);
+    let opcodes = stats.opcodes();
+    let count = |name: &str| opcodes.iter().find(|(n, _)| *n == name).map(|(_, c)| *c);
+    assert_eq!(count("Call"), Some(6));
+    assert_eq!(count("Concat"), Some(2));
+    assert_eq!(count("Exit"), None);
+    // main, show, and greet or println
+    assert_eq!(stats.max_depth(), 3);
+    // The three parts of the greeting, before they are joined
+    assert_eq!(stats.max_stack(), 3);
+}
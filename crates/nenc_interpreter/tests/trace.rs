@@ -0,0 +1,28 @@
+extern crate nenc;
+extern crate nenc_interpreter;
+
+mod common;
+
+use nenc_interpreter::{ InterpreterOptions, Tracer, Vm };
+
+#[test]
+fn instructions_are_traced_with_the_top_of_the_stack() {
+    let bytecode = common::compile("trace_lines", r#"
+        func greet(name: string): string { return "Hello, {name}!"; }
+        impure func main() { print(greet("Ada")); }
+    "#);
+
+    let mut trace = Vec::<u8>::new();
+    {
+        let mut vm = Vm::load_with_hook(&bytecode[..], InterpreterOptions::default(), Tracer::new(&mut trace)).unwrap();
+        vm.set_output(Vec::<u8>::new());
+        assert_eq!(vm.run_main().unwrap(), 0);
+    }
+    let trace = String::from_utf8(trace).unwrap();
+    let lines = trace.lines().map(|line| line.split_whitespace().collect::<Vec<&str>>().join(" ")).collect::<Vec<String>>();
+
+    assert_eq!(lines[0], "main+0 PushString \"Ada\" top: -");
+    assert_eq!(lines[1], "main+6 Call greet top: \"Ada\"");
+    assert!(lines.contains(&"greet+0 StoreLocal 0 top: \"Ada\"".to_string()), "{}", trace);
+    assert!(lines.iter().any(|line| line.starts_with("greet+") && line.ends_with("Return top: \"Hello, Ada!\"")), "{}", trace);
+}
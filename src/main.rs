@@ -1,7 +1,15 @@
 extern crate nenc;
 extern crate nenc_interpreter;
+extern crate serde;
+extern crate toml;
 
-use std::{ env, path::PathBuf, process::exit, fs::File };
+mod manifest;
+
+use std::{ env, io::{ self, BufReader }, path::{ Path, PathBuf }, process::exit, fs::File };
+
+use nenc_interpreter::{ Capability, Coverage, DebugInfo, Debugger, Error, Hook, InterpreterOptions, Profiler, RuntimeError, Stats, Tracer, Vm };
+
+use manifest::Manifest;
 
 const RED: &str = "\u{001b}[91m";
 const RESET: &str = "\u{001b}[0m";
@@ -10,13 +18,108 @@ const RESET: &str = "\u{001b}[0m";
 enum CliAction {
     #[default]
     Compile,
-    Interpret
+    Interpret,
+    // Compile the project described by the nearest `nen.toml`
+    Build,
+    // Build the project, then interpret the output
+    Run,
+    // Interpret a program, stopping it at breakpoints
+    Debug,
+    // Serve the debug adapter protocol over standard input and output, for editors
+    Dap,
+    // Run the `test_` functions of a file or project
+    Test
 }
 
 #[derive(Default)]
 struct CliOptions {
     action: CliAction,
-    input_file: String
+    input_file: String,
+    interpreter: InterpreterOptions,
+    // Set by `-g` or `--strip`, otherwise debug info is only included by `nen run`
+    debug_info: Option<bool>,
+    recording: Recording
+}
+
+// What is recorded about the program as it runs, and where it is written to
+#[derive(Default)]
+struct Recording {
+    // Folded stacks, from `--profile`
+    profile: Option<PathBuf>,
+    // An lcov report, from `--coverage`
+    coverage: Option<PathBuf>,
+    // Each instruction as it runs, from `--trace`
+    trace: bool,
+    // Totals of what the program did, from `--stats`
+    stats: bool
+}
+
+// Each hook is only called when its flag was given
+type Hooks = ((Option<Profiler>, Option<Coverage>), (Option<Tracer<'static>>, Option<Stats>));
+
+impl Recording {
+    fn is_empty(&self) -> bool {
+        self.profile.is_none() && self.coverage.is_none() && !self.trace && !self.stats
+    }
+
+    fn hooks(&self) -> Hooks {
+        (
+            (self.profile.as_ref().map(|_| Profiler::new()), self.coverage.as_ref().map(|_| Coverage::new())),
+            (self.trace.then(|| Tracer::new(io::stderr())), self.stats.then(Stats::new))
+        )
+    }
+
+    // Reports are written to files, and summaries to standard error so that they aren't mixed into the program's output
+    fn write(&self, hooks: &Hooks, debug: Option<&DebugInfo>) {
+        let ((profiler, coverage), (_, stats)) = hooks;
+
+        if let Some(stats) = stats {
+            let _ = stats.write_summary(&mut io::stderr());
+        }
+
+        if let (Some(path), Some(profiler)) = (&self.profile, profiler) {
+            if let Err(e) = File::create(path).and_then(|mut f| profiler.write_folded(&mut f)) {
+                eprintln!("{RED}ERROR{RESET} Could not write profile '{}': {e}.", path.display());
+                exit(1);
+            }
+            let _ = profiler.write_summary(&mut io::stderr());
+            eprintln!("Folded stacks were written to '{}'.", path.display());
+        }
+
+        if let (Some(path), Some(coverage)) = (&self.coverage, coverage) {
+            let debug = match debug {
+                Some(d) => d,
+                None => {
+                    eprintln!("{RED}ERROR{RESET} Coverage can only be reported for programs compiled with debug info (`-g`).");
+                    exit(1);
+                }
+            };
+            if let Err(e) = File::create(path).and_then(|mut f| coverage.write_lcov(debug, &mut f)) {
+                eprintln!("{RED}ERROR{RESET} Could not write coverage '{}': {e}.", path.display());
+                exit(1);
+            }
+            eprintln!("Coverage was written to '{}'.", path.display());
+        }
+    }
+}
+
+// `--allow-read` allows every path, and `--allow-read=path` allows only that path
+fn allow(capability: &mut Capability, path: Option<String>) {
+    match path {
+        Some(path) => capability.paths.push(PathBuf::from(path)),
+        None => capability.all = true
+    }
+}
+
+// Flags that take a value can either be written as `--flag value` or `--flag=value`
+fn flag_value(args: &[String], idx: &mut usize, value: Option<&str>) -> String {
+    match value {
+        Some(v) => v.to_string(),
+        None => {
+            *idx += 1;
+            args.get(*idx - 1).cloned().unwrap_or_default()
+        }
+    }
 }
 
 fn parse_arguments() -> CliOptions {
@@ -28,13 +131,88 @@ fn parse_arguments() -> CliOptions {
         let arg = &args[idx];
         idx += 1;
 
+        // Everything after `--` is passed on to the program
+        if arg == "--" {
+            options.interpreter.args = args[idx..].to_vec();
+            break;
+        }
+
         if arg.starts_with("-") {
-            match arg.as_str() {
+            let (flag, value) = match arg.split_once('=') {
+                Some((flag, value)) => (flag, Some(value)),
+                None => (arg.as_str(), None)
+            };
+
+            match flag {
                 "-i" | "--interpret" => {
                     options.action = CliAction::Interpret;
                 }, 
-                _ => todo!("Unknown flag")
+                // Only `--allow-read=path` takes a path, so that a file after the flag isn't taken for one
+                "--allow-read" => allow(&mut options.interpreter.permissions.read, value.map(str::to_string)),
+                "--allow-write" => allow(&mut options.interpreter.permissions.write, value.map(str::to_string)),
+                "-g" | "--debug" => options.debug_info = Some(true),
+                "--strip" => options.debug_info = Some(false),
+                "--clear-env" => options.interpreter.environment.inherit = false,
+                "--profile" => options.recording.profile = Some(PathBuf::from(value.unwrap_or("profile.folded"))),
+                "--coverage" => options.recording.coverage = Some(PathBuf::from(value.unwrap_or("lcov.info"))),
+                "--trace" => options.recording.trace = true,
+                "--stats" => options.recording.stats = true,
+                "--env" => {
+                    let variable = flag_value(&args, &mut idx, value);
+
+                    match variable.split_once('=') {
+                        Some((name, value)) => options.interpreter.environment.overrides.push((name.to_string(), value.to_string())),
+                        None => {
+                            eprintln!("{RED}ERROR{RESET} Expected `--env NAME=value`, found '{}'.", variable);
+                            exit(1);
+                        }
+                    }
+                },
+                "--max-instructions" => {
+                    let count = flag_value(&args, &mut idx, value);
+                    match count.parse::<u64>() {
+                        Ok(c) => options.interpreter.limits.max_instructions = Some(c),
+                        Err(_) => {
+                            eprintln!("{RED}ERROR{RESET} Expected a number of instructions for `--max-instructions`, found '{}'.", count);
+                            exit(1);
+                        }
+                    }
+                },
+                "--max-memory" => {
+                    let size = flag_value(&args, &mut idx, value);
+                    match nenc_interpreter::parse_size(&size) {
+                        Some(s) => options.interpreter.limits.max_memory = Some(s),
+                        None => {
+                            eprintln!("{RED}ERROR{RESET} Expected a size such as `64M` or `512K` for `--max-memory`, found '{}'.", size);
+                            exit(1);
+                        }
+                    }
+                },
+                "--timeout" => {
+                    let duration = flag_value(&args, &mut idx, value);
+                    match nenc_interpreter::parse_duration(&duration) {
+                        Some(d) => options.interpreter.limits.timeout = Some(d),
+                        None => {
+                            eprintln!("{RED}ERROR{RESET} Expected a duration such as `2s` or `500ms` for `--timeout`, found '{}'.", duration);
+                            exit(1);
+                        }
+                    }
+                },
+                _ => {
+                    eprintln!("{RED}ERROR{RESET} Unknown flag '{}'.", flag);
+                    exit(1);
+                }
             }
+        } else if idx == 1 && arg == "build" {
+            options.action = CliAction::Build;
+        } else if idx == 1 && arg == "run" {
+            options.action = CliAction::Run;
+        } else if idx == 1 && arg == "debug" {
+            options.action = CliAction::Debug;
+        } else if idx == 1 && arg == "dap" {
+            options.action = CliAction::Dap;
+        } else if idx == 1 && arg == "test" {
+            options.action = CliAction::Test;
         } else {
             options.input_file = arg.to_string();
         }
@@ -43,16 +221,288 @@ fn parse_arguments() -> CliOptions {
     options
 }
 
+fn load_manifest() -> Manifest {
+    let path = match manifest::find_manifest() {
+        Some(p) => p,
+        None => {
+            eprintln!("{RED}ERROR{RESET} Could not find `{}` in this directory or any parent directory.", manifest::MANIFEST_NAME);
+            exit(1);
+        }
+    };
+
+    match Manifest::load(&path) {
+        Ok(m) => m,
+        Err(e) => {
+            eprintln!("{RED}ERROR{RESET} {}", e);
+            exit(1);
+        }
+    }
+}
+
+fn build_project(debug_info: bool) -> PathBuf {
+    let manifest = load_manifest();
+
+    let packages = match manifest.packages() {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("{RED}ERROR{RESET} {}", e);
+            exit(1);
+        }
+    };
+
+    if let Some(parent) = manifest.output.parent() {
+        if let Err(e) = std::fs::create_dir_all(parent) {
+            eprintln!("{RED}ERROR{RESET} Could not create directory '{}': {e}.", parent.display());
+            exit(1);
+        }
+    }
+
+    compile_file(&manifest.entry, nenc::CompilerOptions {
+        output: manifest.output.clone(),
+        search_paths: manifest.source_dirs,
+        packages,
+        debug_info
+    });
+
+    manifest.output
+}
+
+// The compiler's errors and warnings already show the source they point to, so they are shown as they are
+fn compile_file(path: &Path, options: nenc::CompilerOptions) {
+    match nenc::compile_file(path, options) {
+        Ok(warnings) => eprint!("{}", warnings),
+        Err(e) => {
+            eprint!("{}", e);
+            exit(1);
+        }
+    }
+}
+
+// Returns the exit status of the program, which is the error's own status if it failed
+fn interpret(file: File, options: InterpreterOptions, recording: &Recording) -> i32 {
+    if recording.is_empty() {
+        return report(nenc_interpreter::interpret(file, options));
+    }
+
+    let mut hooks = recording.hooks();
+    let (result, debug) = match Vm::load_with_hook(file, options, &mut hooks) {
+        Ok(mut vm) => (vm.run_main(), vm.debug_info().cloned()),
+        Err(e) => (Err(Error::Load(e)), None)
+    };
+    let status = report(result);
+
+    recording.write(&hooks, debug.as_ref());
+    status
+}
+
+fn report(result: Result<i32, Error>) -> i32 {
+    match result {
+        Ok(status) => status,
+        Err(e) => {
+            match &e {
+                Error::Runtime(error, trace) => {
+                    // Shown as `file.nen:12:5` when the program has debug info
+                    match trace.first().and_then(|l| l.position.as_ref()) {
+                        Some(p) => eprintln!("{RED}ERROR{RESET} {}:{}:{}: {}", p.file, p.line, p.column, error),
+                        None => eprintln!("{RED}ERROR{RESET} {}", error)
+                    }
+
+                    for location in trace {
+                        match &location.position {
+                            Some(p) => eprintln!("    at {} ({}:{}:{})", location.function, p.file, p.line, p.column),
+                            None => eprintln!("    at {} (offset {})", location.function, location.offset)
+                        }
+                    }
+                },
+                Error::Load(error) => eprintln!("{RED}ERROR{RESET} {}", error)
+            }
+            e.exit_code()
+        }
+    }
+}
+
+fn interpret_file(path: &PathBuf, options: InterpreterOptions, recording: &Recording) -> i32 {
+    let file = match File::open(path) {
+        Ok(f) => f,
+        Err(e) => {
+            eprintln!("{RED}ERROR{RESET} Could not open file '{}': {e}.", path.display());
+            exit(1);
+        }
+    };
+
+    interpret(file, options, recording)
+}
+
+// Calls each function whose name starts with `test_`, which passes unless it stops with an error
+fn run_tests<H: Hook>(vm: &mut Vm<H>) -> bool {
+    let tests = vm.functions().into_iter()
+        .filter(|name| !name.starts_with("std::") && name.rsplit("::").next().is_some_and(|n| n.starts_with("test_")))
+        .map(str::to_string)
+        .collect::<Vec<String>>();
+
+    let mut failed = 0;
+    for test in &tests {
+        match vm.call(test, &[]) {
+            Ok(_) | Err(RuntimeError::Exit(0)) => println!("test {} ... ok", test),
+            Err(e) => {
+                failed += 1;
+                println!("test {} ... {RED}FAILED{RESET}", test);
+                println!("    {}", e);
+                for location in vm.trace() {
+                    match &location.position {
+                        Some(p) => println!("    at {} ({}:{}:{})", location.function, p.file, p.line, p.column),
+                        None => println!("    at {} (offset {})", location.function, location.offset)
+                    }
+                }
+            }
+        }
+    }
+
+    println!("{} tests, {} passed, {} failed", tests.len(), tests.len() - failed, failed);
+    failed == 0
+}
+
+fn test(path: &PathBuf, options: InterpreterOptions, recording: &Recording) -> i32 {
+    let file = match File::open(path) {
+        Ok(f) => f,
+        Err(e) => {
+            eprintln!("{RED}ERROR{RESET} Could not open file '{}': {e}.", path.display());
+            exit(1);
+        }
+    };
+
+    let mut hooks = recording.hooks();
+    let (passed, debug) = match Vm::load_with_hook(file, options, &mut hooks) {
+        Ok(mut vm) => (run_tests(&mut vm), vm.debug_info().cloned()),
+        Err(e) => return report(Err(Error::Load(e)))
+    };
+
+    recording.write(&hooks, debug.as_ref());
+    if passed { 0 } else { 1 }
+}
+
+// Commands are read from standard input, along with the program's own input
+fn debug(path: &PathBuf, options: InterpreterOptions) -> i32 {
+    let file = match File::open(path) {
+        Ok(f) => f,
+        Err(e) => {
+            eprintln!("{RED}ERROR{RESET} Could not open file '{}': {e}.", path.display());
+            exit(1);
+        }
+    };
+
+    let debugger = Debugger::new(BufReader::new(io::stdin()), io::stdout());
+    let mut vm = match Vm::load_with_hook(file, options, debugger) {
+        Ok(vm) => vm,
+        Err(e) => return report(Err(Error::Load(e)))
+    };
+    // Not buffered, so that the program's output is shown before the debugger stops it
+    vm.set_output(io::stdout());
+
+    if vm.debug_info().is_none() {
+        eprintln!("The program has no debug info, so it will be stepped through one instruction at a time.");
+    }
+
+    match vm.run_main() {
+        // Quitting the debugger isn't an error
+        Err(e @ Error::Runtime(RuntimeError::Interrupted, _)) => e.exit_code(),
+        result => report(result)
+    }
+}
+
+// The bytecode of a program launched by an editor, where source files are compiled with debug info.
+// Errors are sent to the editor, which shows them in place of starting the program.
+fn load_program(program: &str) -> Result<Vec<u8>, String> {
+    if !program.ends_with(".nen") {
+        return std::fs::read(program).map_err(|e| format!("Could not read '{}': {}", program, e));
+    }
+
+    let output = env::temp_dir().join(format!("nen-dap-{}.nenc", std::process::id()));
+    nenc::compile_file(&PathBuf::from(program), nenc::CompilerOptions {
+        output: output.clone(),
+        debug_info: true,
+        ..nenc::CompilerOptions::default()
+    }).map_err(|e| e.plain())?;
+
+    let bytecode = std::fs::read(&output).map_err(|e| format!("Could not read '{}': {}", output.display(), e));
+    let _ = std::fs::remove_file(&output);
+    bytecode
+}
+
 fn main() {
     let options = parse_arguments();
 
+    match options.action {
+        CliAction::Build => {
+            build_project(options.debug_info.unwrap_or(false));
+            return;
+        },
+        // `nen run` builds the project, while `nen run file.nen` compiles just that file
+        CliAction::Run if options.input_file.is_empty() => {
+            let output = build_project(options.debug_info.unwrap_or(true));
+            exit(interpret_file(&output, options.interpreter, &options.recording));
+        },
+        CliAction::Run => {
+            let output = env::temp_dir().join(format!("nen-run-{}.nenc", std::process::id()));
+            compile_file(&PathBuf::from(&options.input_file), nenc::CompilerOptions {
+                output: output.clone(),
+                debug_info: options.debug_info.unwrap_or(true),
+                ..nenc::CompilerOptions::default()
+            });
+
+            let status = interpret_file(&output, options.interpreter, &options.recording);
+            let _ = std::fs::remove_file(&output);
+            exit(status);
+        },
+        // Source files are compiled with debug info before they are debugged
+        CliAction::Debug if options.input_file.ends_with(".nen") => {
+            let output = env::temp_dir().join(format!("nen-debug-{}.nenc", std::process::id()));
+            compile_file(&PathBuf::from(&options.input_file), nenc::CompilerOptions {
+                output: output.clone(),
+                debug_info: true,
+                ..nenc::CompilerOptions::default()
+            });
+
+            let status = debug(&output, options.interpreter);
+            let _ = std::fs::remove_file(&output);
+            exit(status);
+        },
+        CliAction::Debug if !options.input_file.is_empty() => exit(debug(&PathBuf::from(&options.input_file), options.interpreter)),
+        // Tests are always compiled with debug info, so that failures show where they happened
+        CliAction::Test if options.input_file.is_empty() => {
+            let output = build_project(true);
+            exit(test(&output, options.interpreter, &options.recording));
+        },
+        CliAction::Test => {
+            let output = env::temp_dir().join(format!("nen-test-{}.nenc", std::process::id()));
+            compile_file(&PathBuf::from(&options.input_file), nenc::CompilerOptions {
+                output: output.clone(),
+                debug_info: true,
+                ..nenc::CompilerOptions::default()
+            });
+
+            let status = test(&output, options.interpreter, &options.recording);
+            let _ = std::fs::remove_file(&output);
+            exit(status);
+        },
+        // The program is given by the editor when it launches it
+        CliAction::Dap => {
+            if let Err(e) = nenc_interpreter::serve_dap(BufReader::new(io::stdin()), io::stdout(), options.interpreter, load_program) {
+                eprintln!("{RED}ERROR{RESET} The connection to the editor failed: {e}.");
+                exit(1);
+            }
+            return;
+        },
+        _ => {}
+    }
+
     if options.input_file.is_empty() {
         eprintln!("{RED}ERROR{RESET} No input file was provided.");
         exit(1);
     }
 
     let path = PathBuf::from(&options.input_file);
-    let file = match File::open(path) {
+    let file = match File::open(&path) {
         Ok(f) => f,
         Err(e) => {
             eprintln!("{RED}ERROR{RESET} Could not open file '{}': {e}.", &options.input_file);
@@ -61,9 +511,14 @@ fn main() {
     };
 
     match options.action {
+        // Imports are resolved relative to the file, so the compiler reads it itself
         CliAction::Compile => 
-            nenc::compile(file, nenc::CompilerOptions {}),
+            compile_file(&path, nenc::CompilerOptions {
+                debug_info: options.debug_info.unwrap_or(false),
+                ..nenc::CompilerOptions::default()
+            }),
         CliAction::Interpret =>
-            nenc_interpreter::interpret(file)
+            exit(interpret(file, options.interpreter, &options.recording)),
+        CliAction::Build | CliAction::Run | CliAction::Debug | CliAction::Dap | CliAction::Test => unreachable!()
     };
 }
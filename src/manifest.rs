@@ -0,0 +1,298 @@
+use std::{ collections::{ BTreeMap, HashMap }, env, fmt, fs, path::{ Path, PathBuf } };
+
+use nenc::Package;
+use serde::Deserialize;
+
+pub const MANIFEST_NAME: &str = "nen.toml";
+
+// A project manifest, such as:
+//
+// [package]
+// name = "hello"
+// entry = "src/main.nen"
+// source-dirs = ["src", "lib"]
+// output = "build/hello.nenc"
+//
+// [dependencies]
+// util = { path = "../util" }
+#[allow(dead_code)]
+#[derive(Debug)]
+pub struct Manifest {
+    // Directory containing the manifest, which all paths are relative to
+    pub root: PathBuf,
+    pub name: String,
+    pub entry: PathBuf,
+    pub source_dirs: Vec<PathBuf>,
+    pub output: PathBuf,
+    pub dependencies: Vec<Dependency>
+}
+
+#[derive(Debug)]
+pub struct Dependency {
+    pub name: String,
+    pub path: PathBuf
+}
+
+#[derive(Debug)]
+pub struct ManifestError {
+    pub path: PathBuf,
+    pub line: usize,
+    pub message: String
+}
+
+impl fmt::Display for ManifestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}: {}", self.path.display(), self.message)
+        } else {
+            write!(f, "{}:{}: {}", self.path.display(), self.line, self.message)
+        }
+    }
+}
+
+// The manifest as it is written, before paths are made relative to it
+#[derive(Deserialize)]
+struct ManifestFile {
+    package: PackageTable,
+    #[serde(default)]
+    dependencies: BTreeMap<String, DependencyTable>
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct PackageTable {
+    name: String,
+    entry: Option<String>,
+    source_dirs: Option<Vec<String>>,
+    output: Option<String>
+}
+
+#[derive(Deserialize)]
+#[serde(expecting = "a table such as { path = \"...\" }")]
+struct DependencyTable {
+    path: String
+}
+
+// Looks for a manifest in the working directory, then each of its parents
+pub fn find_manifest() -> Option<PathBuf> {
+    let mut directory = env::current_dir().ok()?;
+    loop {
+        let path = directory.join(MANIFEST_NAME);
+        if path.is_file() {
+            return Some(path);
+        }
+        if !directory.pop() {
+            return None;
+        }
+    }
+}
+
+impl Manifest {
+    pub fn load(path: &Path) -> Result<Manifest, ManifestError> {
+        let error = |line: usize, message: String| ManifestError { path: path.to_path_buf(), line, message };
+
+        let text = fs::read_to_string(path).map_err(|e| error(0, e.to_string()))?;
+        let file = toml::from_str::<ManifestFile>(&text).map_err(|e| {
+            let line = e.span().map_or(0, |span| text[..span.start].matches('\n').count() + 1);
+            // Some messages give what was expected on a second line
+            error(line, e.message().lines().collect::<Vec<&str>>().join(": "))
+        })?;
+
+        let root = path.parent().map_or(PathBuf::new(), Path::to_path_buf);
+        let PackageTable { name, entry, source_dirs, output } = file.package;
+
+        let entry = entry.unwrap_or_else(|| "src/main.nen".to_string());
+        let output = output.unwrap_or_else(|| format!("{}.nenc", name));
+        let source_dirs = source_dirs.unwrap_or_else(|| vec!["src".to_string()]);
+
+        // Sorted by name, since they are read into a `BTreeMap`
+        let dependencies = file.dependencies.into_iter()
+            .map(|(name, dependency)| Dependency { name, path: root.join(dependency.path) })
+            .collect::<Vec<Dependency>>();
+
+        Ok(Manifest {
+            entry: root.join(entry),
+            source_dirs: source_dirs.iter().map(|dir| root.join(dir)).collect(),
+            output: root.join(output),
+            root,
+            name,
+            dependencies
+        })
+    }
+
+    // Every package this one depends on, including the dependencies of those
+    // packages. Two different packages can't be depended on by the same name,
+    // since `import name;` could then refer to either of them.
+    pub fn packages(&self) -> Result<HashMap<String, Package>, ManifestError> {
+        let mut packages = HashMap::<String, Package>::new();
+        let mut directories = HashMap::<String, PathBuf>::new();
+        // Each dependency along with the manifest that depends on it
+        let mut pending = self.dependencies.iter()
+            .map(|d| (d.name.to_string(), d.path.clone(), self.root.join(MANIFEST_NAME)))
+            .collect::<Vec<(String, PathBuf, PathBuf)>>();
+
+        while let Some((name, directory, dependent)) = pending.pop() {
+            let directory = fs::canonicalize(&directory).unwrap_or(directory);
+
+            match directories.get(&name) {
+                Some(existing) if *existing == directory => continue,
+                Some(existing) => return Err(ManifestError {
+                    path: dependent,
+                    line: 0,
+                    message: format!(
+                        "Dependency `{}` refers to {}, but a different dependency with that name refers to {}",
+                        name, directory.display(), existing.display()
+                    )
+                }),
+                None => {}
+            }
+
+            let path = directory.join(MANIFEST_NAME);
+            let manifest = Manifest::load(&path)?;
+            pending.extend(manifest.dependencies.iter().map(|d| (d.name.to_string(), d.path.clone(), path.clone())));
+
+            directories.insert(name.clone(), directory);
+            packages.insert(name, Package {
+                entry: manifest.entry,
+                search_paths: manifest.source_dirs
+            });
+        }
+
+        Ok(packages)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Writes each of `files` to a new directory, returning its path
+    fn project(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let directory = env::temp_dir().join(format!("nen-manifest-test-{}-{}", std::process::id(), name));
+        for (path, text) in files {
+            let path = directory.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, text).unwrap();
+        }
+        directory
+    }
+
+    fn load(name: &str, text: &str) -> Result<Manifest, String> {
+        let directory = project(name, &[(MANIFEST_NAME, text)]);
+        let result = Manifest::load(&directory.join(MANIFEST_NAME)).map_err(|e| e.to_string());
+        fs::remove_dir_all(&directory).ok();
+        // Errors are relative to the manifest, so leave out the directory
+        result.map_err(|e| e.replace(&directory.display().to_string(), ""))
+    }
+
+    #[test]
+    fn missing_keys_have_defaults() {
+        let manifest = load("defaults", "[package]\nname = \"hello\"\n").unwrap();
+        assert_eq!(manifest.name, "hello");
+        assert_eq!(manifest.entry, manifest.root.join("src/main.nen"));
+        assert_eq!(manifest.source_dirs, vec![manifest.root.join("src")]);
+        assert_eq!(manifest.output, manifest.root.join("hello.nenc"));
+        assert!(manifest.dependencies.is_empty());
+    }
+
+    #[test]
+    fn every_key_is_parsed() {
+        let text = concat!(
+            "# A comment\n",
+            "[package]\n",
+            "name = \"hello\" # The name of the package\n",
+            "entry = \"lib/start.nen\"\n",
+            "\"source-dirs\" = [\"lib\", \"vendor/#1\", ]\n",
+            "output = \"build/hello.nenc\"\n",
+            "\n",
+            "[dependencies]\n",
+            "util = { path = \"../util\" }\n",
+            "core = {path=\"../core\"}\n"
+        );
+        let manifest = load("every_key", text).unwrap();
+        assert_eq!(manifest.entry, manifest.root.join("lib/start.nen"));
+        assert_eq!(manifest.source_dirs, vec![manifest.root.join("lib"), manifest.root.join("vendor/#1")]);
+        assert_eq!(manifest.output, manifest.root.join("build/hello.nenc"));
+
+        // Dependencies are sorted by name
+        let dependencies = manifest.dependencies.iter()
+            .map(|d| (d.name.as_str(), d.path.clone()))
+            .collect::<Vec<(&str, PathBuf)>>();
+        assert_eq!(dependencies, vec![
+            ("core", manifest.root.join("../core")),
+            ("util", manifest.root.join("../util"))
+        ]);
+    }
+
+    #[test]
+    fn errors_show_the_line_they_are_on() {
+        let error = |name: &str, text: &str| load(name, text).unwrap_err();
+
+        assert_eq!(error("no_package", "[dependencies]\n"), "/nen.toml:1: missing field `package`");
+        assert_eq!(error("no_name", "[package]\nentry = \"main.nen\"\n"), "/nen.toml:1: missing field `name`");
+        assert_eq!(error("header", "[package\nname = \"hello\"\n"), "/nen.toml:1: invalid table header: expected `.`, `]`");
+        assert_eq!(error("no_value", "[package]\nname\n"), "/nen.toml:2: expected `.`, `=`");
+        assert_eq!(error("unquoted", "[package]\nname = hello\n"), "/nen.toml:2: invalid string: expected `\"`, `'`");
+        assert_eq!(error("not_string", "[package]\nname = [\"hello\"]\n"), "/nen.toml:2: invalid type: sequence, expected a string");
+        assert_eq!(error("dirs", "[package]\nname = \"hello\"\nsource-dirs = \"src\"\n"), "/nen.toml:3: invalid type: string \"src\", expected a sequence");
+        assert_eq!(
+            error("dependency", "[package]\nname = \"hello\"\n\n[dependencies]\nutil = \"../util\"\n"),
+            "/nen.toml:5: invalid type: string \"../util\", expected a table such as { path = \"...\" }"
+        );
+        assert_eq!(
+            error("dependency_path", "[package]\nname = \"hello\"\n\n[dependencies]\nutil = { version = \"1\" }\n"),
+            "/nen.toml:5: missing field `path`"
+        );
+    }
+
+    #[test]
+    fn packages_include_dependencies_of_dependencies() {
+        let directory = project("packages", &[
+            ("app/nen.toml", "[package]\nname = \"app\"\n\n[dependencies]\nutil = { path = \"../util\" }\n"),
+            ("util/nen.toml", "[package]\nname = \"util\"\nentry = \"util.nen\"\nsource-dirs = [\"lib\"]\n\n[dependencies]\ncore = { path = \"../core\" }\n"),
+            ("core/nen.toml", "[package]\nname = \"core\"\n")
+        ]);
+        let directory = fs::canonicalize(&directory).unwrap();
+
+        let manifest = Manifest::load(&directory.join("app").join(MANIFEST_NAME)).unwrap();
+        let packages = manifest.packages().unwrap();
+        fs::remove_dir_all(&directory).ok();
+
+        // Paths are relative to the manifest of the package they are in
+        assert_eq!(packages.len(), 2);
+        assert_eq!(packages["util"].entry, directory.join("util/util.nen"));
+        assert_eq!(packages["util"].search_paths, vec![directory.join("util/lib")]);
+        assert_eq!(packages["core"].entry, directory.join("core/src/main.nen"));
+        assert_eq!(packages["core"].search_paths, vec![directory.join("core/src")]);
+    }
+
+    #[test]
+    fn different_packages_with_the_same_name_are_errors() {
+        let directory = project("conflict", &[
+            ("app/nen.toml", "[package]\nname = \"app\"\n\n[dependencies]\nutil = { path = \"../util\" }\ntext = { path = \"../text\" }\n"),
+            ("util/nen.toml", "[package]\nname = \"util\"\n\n[dependencies]\ntext = { path = \"../other\" }\n"),
+            ("text/nen.toml", "[package]\nname = \"text\"\n"),
+            ("other/nen.toml", "[package]\nname = \"text\"\n")
+        ]);
+        let directory = fs::canonicalize(&directory).unwrap();
+
+        let manifest = Manifest::load(&directory.join("app").join(MANIFEST_NAME)).unwrap();
+        let error = manifest.packages().unwrap_err().to_string();
+        fs::remove_dir_all(&directory).ok();
+
+        assert!(error.contains("Dependency `text` refers to "), "{}", error);
+        assert!(error.contains(", but a different dependency with that name refers to "), "{}", error);
+
+        // The same package depended on by several others is fine
+        let directory = project("shared", &[
+            ("app/nen.toml", "[package]\nname = \"app\"\n\n[dependencies]\nutil = { path = \"../util\" }\ntext = { path = \"../text\" }\n"),
+            ("util/nen.toml", "[package]\nname = \"util\"\n\n[dependencies]\ntext = { path = \"../app/../text\" }\n"),
+            ("text/nen.toml", "[package]\nname = \"text\"\n")
+        ]);
+
+        let manifest = Manifest::load(&directory.join("app").join(MANIFEST_NAME)).unwrap();
+        let packages = manifest.packages();
+        fs::remove_dir_all(&directory).ok();
+        assert_eq!(packages.unwrap().len(), 2);
+    }
+}
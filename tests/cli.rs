@@ -0,0 +1,52 @@
+use std::{ env, fs, path::PathBuf, process::{ Command, Output } };
+
+// Writes each of `files` to a new directory, returning its path
+fn project(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let directory = env::temp_dir().join(format!("nen-cli-test-{}-{}", std::process::id(), name));
+    for (path, text) in files {
+        let path = directory.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, text).unwrap();
+    }
+    directory
+}
+
+// Runs `nen` with `args` in `directory`
+fn nen(directory: &PathBuf, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_nen"))
+        .args(args)
+        .current_dir(directory)
+        .output()
+        .expect("nen should run")
+}
+
+#[test]
+fn allow_read_without_a_path_allows_everything() {
+    let directory = project("allow_read", &[
+        ("prog.nen", "impure func main() {\n    print(read_file(\"data.txt\"));\n}\n"),
+        ("data.txt", "hello")
+    ]);
+
+    // The file after the flag is the program, rather than the path to allow
+    let output = nen(&directory, &["run", "--allow-read", "prog.nen"]);
+    let denied = nen(&directory, &["run", "--allow-read=other", "prog.nen"]);
+    fs::remove_dir_all(&directory).ok();
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "hello");
+
+    assert!(!denied.status.success());
+    assert!(String::from_utf8_lossy(&denied.stderr).contains("Permission denied to read 'data.txt'"));
+}
+
+#[test]
+fn unknown_flags_are_errors() {
+    let directory = project("unknown_flag", &[]);
+    fs::create_dir_all(&directory).unwrap();
+
+    let output = nen(&directory, &["run", "--bogus", "prog.nen"]);
+    fs::remove_dir_all(&directory).ok();
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Unknown flag '--bogus'."));
+}